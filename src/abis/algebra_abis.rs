@@ -0,0 +1,23 @@
+sol! {
+    /// Algebra pools: QuickSwap V3, Camelot, Thena. `globalState` and `ticks`
+    /// return different tuples across Algebra versions and are decoded by
+    /// hand, only their selectors are declared
+    #[sol(rpc)]
+    interface IAlgebraPool {
+        function token0() external view returns (address);
+        function token1() external view returns (address);
+        function liquidity() external view returns (uint128);
+        function tickSpacing() external view returns (int24);
+        function tickTable(int16 wordPosition) external view returns (uint256);
+        function globalState() external view;
+        function ticks(int24 tick) external view;
+
+        /// Fee of the next swaps, Algebra V1 and Integral
+        event Fee(uint16 fee);
+    }
+
+    /// Algebra 1.9 pools charge a fee per direction
+    interface IAlgebraPoolDirectional {
+        event Fee(uint16 feeZto, uint16 feeOtz);
+    }
+}
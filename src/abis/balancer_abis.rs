@@ -0,0 +1,19 @@
+sol! {
+    /// Balancer V2 Vault, holds the tokens of every pool
+    #[sol(rpc)]
+    interface IBalancerVault {
+        function getPoolTokens(bytes32 poolId) external view returns (address[] memory tokens, uint256[] memory balances, uint256 lastChangeBlock);
+    }
+
+    /// Getters of Balancer V2 weighted and stable pools, each kind has only
+    /// its own of `getNormalizedWeights` and `getAmplificationParameter`
+    #[sol(rpc)]
+    interface IBalancerPool {
+        function getPoolId() external view returns (bytes32);
+        function getVault() external view returns (address);
+        function getSwapFeePercentage() external view returns (uint256);
+        function getNormalizedWeights() external view returns (uint256[] memory);
+        function getAmplificationParameter() external view returns (uint256 value, bool isUpdating, uint256 precision);
+        function getScalingFactors() external view returns (uint256[] memory);
+    }
+}
@@ -0,0 +1,49 @@
+sol! {
+    /// Getters shared by Curve StableSwap pools, older pools take `int128`
+    /// indices for `coins` and `balances` and aren't covered
+    #[sol(rpc)]
+    interface ICurveStableSwap {
+        function coins(uint256 i) external view returns (address);
+        function balances(uint256 i) external view returns (uint256);
+        function A() external view returns (uint256);
+        function A_precise() external view returns (uint256);
+        function fee() external view returns (uint256);
+        function admin_fee() external view returns (uint256);
+        function stored_rates() external view returns (uint256[] memory);
+        function base_pool() external view returns (address);
+        function get_virtual_price() external view returns (uint256);
+        function get_dy(int128 i, int128 j, uint256 dx) external view returns (uint256);
+    }
+
+    #[sol(rpc)]
+    interface IERC20Decimals {
+        function decimals() external view returns (uint8);
+    }
+}
+
+sol! {
+    /// Getters of Curve CryptoSwap pools, `A` is `A * N**N * 10000`
+    #[sol(rpc)]
+    interface ICurveCryptoSwap {
+        function coins(uint256 i) external view returns (address);
+        function balances(uint256 i) external view returns (uint256);
+        function A() external view returns (uint256);
+        function gamma() external view returns (uint256);
+        function D() external view returns (uint256);
+        function mid_fee() external view returns (uint256);
+        function out_fee() external view returns (uint256);
+        function fee_gamma() external view returns (uint256);
+        function future_A_gamma_time() external view returns (uint256);
+        /// Two coin pools, price of coin 1 in coin 0
+        function price_scale() external view returns (uint256);
+        function get_dy(uint256 i, uint256 j, uint256 dx) external view returns (uint256);
+        /// `-ng` pools only, the math contract they solve with
+        function MATH() external view returns (address);
+    }
+
+    /// Three coin pools keep a price per coin after the first
+    #[sol(rpc)]
+    interface ICurveTriCrypto {
+        function price_scale(uint256 k) external view returns (uint256);
+    }
+}
@@ -0,0 +1,12 @@
+sol! {
+    /// Tokenized vault, its shares are an ERC-20 at the vault's address
+    #[sol(rpc)]
+    interface IERC4626 {
+        function asset() external view returns (address);
+        function decimals() external view returns (uint8);
+        function totalAssets() external view returns (uint256);
+        function totalSupply() external view returns (uint256);
+        function previewDeposit(uint256 assets) external view returns (uint256);
+        function previewRedeem(uint256 shares) external view returns (uint256);
+    }
+}
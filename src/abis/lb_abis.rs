@@ -0,0 +1,72 @@
+sol! {
+    /// Trader Joe Liquidity Book pair, v2.1 and v2.2. Amounts in events are
+    /// packed as `bytes32`, X in the low 128 bits and Y in the high
+    #[sol(rpc)]
+    interface ILBPair {
+        function getTokenX() external view returns (address);
+        function getTokenY() external view returns (address);
+        function getBinStep() external view returns (uint16);
+        function getActiveId() external view returns (uint24 activeId);
+        function getBin(uint24 id) external view returns (uint128 binReserveX, uint128 binReserveY);
+        function getNextNonEmptyBin(bool swapForY, uint24 id) external view returns (uint24 nextId);
+        function getStaticFeeParameters() external view returns (
+            uint16 baseFactor,
+            uint16 filterPeriod,
+            uint16 decayPeriod,
+            uint16 reductionFactor,
+            uint24 variableFeeControl,
+            uint16 protocolShare,
+            uint24 maxVolatilityAccumulator
+        );
+        function getVariableFeeParameters() external view returns (
+            uint24 volatilityAccumulator,
+            uint24 volatilityReference,
+            uint24 idReference,
+            uint40 timeOfLastUpdate
+        );
+        function getSwapOut(uint128 amountIn, bool swapForY) external view returns (
+            uint128 amountInLeft,
+            uint128 amountOut,
+            uint128 fee
+        );
+
+        /// One per bin the swap went through
+        event Swap(
+            address indexed sender,
+            address indexed to,
+            uint24 id,
+            bytes32 amountsIn,
+            bytes32 amountsOut,
+            uint24 volatilityAccumulator,
+            bytes32 totalFees,
+            bytes32 protocolFees
+        );
+        event DepositedToBins(
+            address indexed sender,
+            address indexed to,
+            uint256[] ids,
+            bytes32[] amounts
+        );
+        event WithdrawnFromBins(
+            address indexed sender,
+            address indexed to,
+            uint256[] ids,
+            bytes32[] amounts
+        );
+        /// Fees of a deposit that changes the active bin's composition
+        event CompositionFees(
+            address indexed sender,
+            uint24 id,
+            bytes32 totalFees,
+            bytes32 protocolFees
+        );
+        event FlashLoan(
+            address indexed sender,
+            address indexed receiver,
+            uint24 activeId,
+            bytes32 amounts,
+            bytes32 totalFees,
+            bytes32 protocolFees
+        );
+    }
+}
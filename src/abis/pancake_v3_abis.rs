@@ -0,0 +1,30 @@
+sol! {
+    /// PancakeSwap V3 pool where it differs from `UniV3Pool`: a `uint32`
+    /// protocol fee in `slot0`, the farm's LM pool, and swaps logging the
+    /// protocol's cut
+    #[sol(rpc)]
+    contract PancakeV3Pool {
+        function slot0() external view returns (
+            uint160 sqrtPriceX96,
+            int24 tick,
+            uint16 observationIndex,
+            uint16 observationCardinality,
+            uint16 observationCardinalityNext,
+            uint32 feeProtocol,
+            bool unlocked
+        );
+        function lmPool() external view returns (address);
+
+        event Swap(
+            address indexed sender,
+            address indexed recipient,
+            int256 amount0,
+            int256 amount1,
+            uint160 sqrtPriceX96,
+            uint128 liquidity,
+            int24 tick,
+            uint128 protocolFeesToken0,
+            uint128 protocolFeesToken1
+        );
+    }
+}
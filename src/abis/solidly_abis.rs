@@ -0,0 +1,33 @@
+sol! {
+    /// Solidly pair: Velodrome, Aerodrome, Thena. `metadata` gives
+    /// decimals as `10**decimals`
+    #[sol(rpc)]
+    interface ISolidlyPool {
+        function factory() external view returns (address);
+        function stable() external view returns (bool);
+        function metadata() external view returns (
+            uint256 dec0,
+            uint256 dec1,
+            uint256 r0,
+            uint256 r1,
+            bool st,
+            address t0,
+            address t1
+        );
+        function getAmountOut(uint256 amountIn, address tokenIn) external view returns (uint256);
+
+        event Sync(uint256 reserve0, uint256 reserve1);
+    }
+
+    /// Velodrome V2 and Aerodrome factories, fees per pool in basis points
+    #[sol(rpc)]
+    interface ISolidlyFactory {
+        function getFee(address pool, bool stable) external view returns (uint256);
+    }
+
+    /// Factories of the first Solidly forks, one fee per pool kind
+    #[sol(rpc)]
+    interface ISolidlyFactoryV1 {
+        function getFee(bool stable) external view returns (uint256);
+    }
+}
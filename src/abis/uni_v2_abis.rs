@@ -0,0 +1,34 @@
+alloy::sol! {
+    #[sol(rpc)]
+    interface IUniswapV2Pair {
+        function factory() external view returns (address);
+        function token0() external view returns (address);
+        function token1() external view returns (address);
+        function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
+        // forks like BiSwap where every pair sets its own fee, over the factory's denominator
+        function swapFee() external view returns (uint32);
+
+        event Mint(address indexed sender, uint256 amount0, uint256 amount1);
+        event Burn(address indexed sender, uint256 amount0, uint256 amount1, address indexed to);
+        event Swap(
+            address indexed sender,
+            uint256 amount0In,
+            uint256 amount1In,
+            uint256 amount0Out,
+            uint256 amount1Out,
+            address indexed to
+        );
+        event Sync(uint112 reserve0, uint112 reserve1);
+    }
+}
+
+alloy::sol! {
+    #[sol(rpc)]
+    interface IUniswapV2Factory {
+        function getPair(address tokenA, address tokenB) external view returns (address pair);
+        function allPairs(uint256 index) external view returns (address pair);
+        function allPairsLength() external view returns (uint256);
+
+        event PairCreated(address indexed token0, address indexed token1, address pair, uint256 pairCount);
+    }
+}
@@ -23,13 +23,63 @@ sol! {
             bool initialized
         );  
 
+        function factory() external view returns (address);
         function token0() external view returns (address);
         function token1() external view returns (address);
         function fee() external view returns (uint24);
         function tickBitmap(int16 wordPosition) external view returns (uint256);
         function liquidity() external view returns (uint128);
         function tickSpacing() external view returns (int24);
+
+        event Initialize(uint160 sqrtPriceX96, int24 tick);
+        event Swap(
+            address indexed sender,
+            address indexed recipient,
+            int256 amount0,
+            int256 amount1,
+            uint160 sqrtPriceX96,
+            uint128 liquidity,
+            int24 tick
+        );
+        event Mint(
+            address sender,
+            address indexed owner,
+            int24 indexed tickLower,
+            int24 indexed tickUpper,
+            uint128 amount,
+            uint256 amount0,
+            uint256 amount1
+        );
+        event Burn(
+            address indexed owner,
+            int24 indexed tickLower,
+            int24 indexed tickUpper,
+            uint128 amount,
+            uint256 amount0,
+            uint256 amount1
+        );
+        event Collect(
+            address indexed owner,
+            address recipient,
+            int24 indexed tickLower,
+            int24 indexed tickUpper,
+            uint128 amount0,
+            uint128 amount1
+        );
     }
 }
 
+sol! {
+    #[sol(rpc)]
+    contract UniV3Factory {
+        function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool);
 
+        event PoolCreated(
+            address indexed token0,
+            address indexed token1,
+            uint24 indexed fee,
+            int24 tickSpacing,
+            address pool
+        );
+    }
+}
@@ -0,0 +1,59 @@
+sol! {
+    /// Identifies a V4 pool, its `PoolId` is the keccak of the ABI encoded key
+    #[derive(Debug, PartialEq, Eq, Hash)]
+    struct PoolKey {
+        address currency0;
+        address currency1;
+        uint24 fee;
+        int24 tickSpacing;
+        address hooks;
+    }
+
+    #[sol(rpc)]
+    contract StateView {
+        function getSlot0(bytes32 poolId)
+            external
+            view
+            returns (uint160 sqrtPriceX96, int24 tick, uint24 protocolFee, uint24 lpFee);
+        function getLiquidity(bytes32 poolId) external view returns (uint128 liquidity);
+        function getTickBitmap(bytes32 poolId, int16 tick) external view returns (uint256 tickBitmap);
+        function getTickLiquidity(bytes32 poolId, int24 tick)
+            external
+            view
+            returns (uint128 liquidityGross, int128 liquidityNet);
+    }
+
+    /// Events of the singleton PoolManager, every pool's changes come from
+    /// it keyed by `PoolId`
+    #[sol(rpc)]
+    contract PoolManager {
+        event Initialize(
+            bytes32 indexed id,
+            address indexed currency0,
+            address indexed currency1,
+            uint24 fee,
+            int24 tickSpacing,
+            address hooks,
+            uint160 sqrtPriceX96,
+            int24 tick
+        );
+        event ModifyLiquidity(
+            bytes32 indexed id,
+            address indexed sender,
+            int24 tickLower,
+            int24 tickUpper,
+            int256 liquidityDelta,
+            bytes32 salt
+        );
+        event Swap(
+            bytes32 indexed id,
+            address indexed sender,
+            int128 amount0,
+            int128 amount1,
+            uint160 sqrtPriceX96,
+            uint128 liquidity,
+            int24 tick,
+            uint24 fee
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use alloy::primitives::U256;
+use alloy::primitives::aliases::U24;
+
+use crate::err::PoolError;
+use crate::trade::Trade;
+use crate::v3_pool_sim::V3PoolSim;
+
+/// Offline copy of an Algebra pool: V3 swap math with the fee of each
+/// direction.
+///
+/// The fees are the ones the pool last set. It recomputes them from its
+/// volatility oracle at the first swap of a block, so the first quote of a
+/// new block can be a few pips off until the `Fee` log is applied.
+#[derive(Debug, Clone)]
+pub struct AlgebraPoolSim {
+    /// Price, liquidity and ticks, `fee` is set per swap
+    pub pool: V3PoolSim,
+    /// Fee of a swap from token0, in pips
+    pub fee_zto: U24,
+    /// Fee of a swap from token1
+    pub fee_otz: U24,
+}
+
+impl AlgebraPoolSim {
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        self.pool.fee = self.fee(from0);
+        self.pool.trade(amount_in, from0)
+    }
+
+    /// Swap as received by the pool, like `V3PoolSim::swap`
+    pub fn swap(&mut self, amount_in: U256, from0: bool) -> Result<U256, PoolError> {
+        self.pool.fee = self.fee(from0);
+        self.pool.swap(amount_in, from0)
+    }
+
+    pub fn fee(&self, from0: bool) -> U24 {
+        if from0 { self.fee_zto } else { self.fee_otz }
+    }
+
+    /// Fee of both directions, from a `Fee` log
+    pub fn set_fee(&mut self, fee_zto: U24, fee_otz: U24) {
+        self.fee_zto = fee_zto;
+        self.fee_otz = fee_otz;
+    }
+}
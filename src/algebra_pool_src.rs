@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use alloy::eips::BlockId;
+use alloy::network::TransactionBuilder;
+use alloy::primitives::aliases::{I24, U24};
+use alloy::primitives::{Address, I256, U256};
+use alloy::rpc::types::TransactionRequest;
+use alloy::sol_types::SolCall;
+use alloy_provider::Provider;
+
+use crate::IAlgebraPool::{self, IAlgebraPoolInstance};
+use crate::algebra_pool_sim::AlgebraPoolSim;
+use crate::curve_pool_src::optional;
+use crate::err::PoolError;
+use crate::tick_math::Tick;
+use crate::token_tax::TransferTax;
+use crate::v3_pool_sim::V3PoolSim;
+use crate::v3_pool_src::{Rpc, walk_left, walk_right};
+
+type AlgebraContract = IAlgebraPoolInstance<Rpc>;
+
+/// Tick spacing of Algebra V1 pools, which have no getter for it
+const DEFAULT_TICK_SPACING: I24 = I24::from_raw(U24::from_limbs([60]));
+
+/// An Algebra pool: QuickSwap V3, Camelot, Thena. V3 ticks and price, with a
+/// fee the pool moves with volatility instead of a fixed tier.
+///
+/// `globalState` is read by its length: 7 words on V1 and 8 on 1.9, which
+/// splits the fee per direction. Price and tick come first in both. Integral
+/// pools, 6 words, are `Unsupported` as their tick tree is keyed by the
+/// uncompressed `tick >> 8` instead of the V3 bitmap.
+#[derive(Debug)]
+pub struct AlgebraPoolSrc {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub tick_spacing: I24,
+    pub current_tick: I24,
+    pub active_ticks: Vec<Tick>,
+    pub bitmap: HashMap<i16, U256>,
+    pub liquidity: U256,
+    pub x96price: U256,
+    /// Fee of a swap from token0, in pips
+    pub fee_zto: U24,
+    /// Fee of a swap from token1, the same as `fee_zto` but on 1.9 pools
+    pub fee_otz: U24,
+    pub contract: AlgebraContract,
+}
+
+/// Return data split into its words
+fn words(data: &[u8]) -> Vec<U256> {
+    data.chunks_exact(32).map(U256::from_be_slice).collect()
+}
+
+fn signed_word<T: TryFrom<I256>>(word: U256, what: &str) -> Result<T, PoolError> {
+    T::try_from(I256::from_raw(word))
+        .map_err(|_| PoolError::Decode(format!("{what} {word} out of range")))
+}
+
+impl AlgebraPoolSrc {
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(address, provider, BlockId::latest()).await
+    }
+
+    /// Load the pool state as of `block`
+    pub async fn new_at(
+        address: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = IAlgebraPoolInstance::new(address, provider);
+        let token0 = contract.token0().call().block(block).await?;
+        let token1 = contract.token1().call().block(block).await?;
+        let tick_spacing = optional(contract.tickSpacing().call().block(block))
+            .await?
+            .unwrap_or(DEFAULT_TICK_SPACING);
+
+        let mut instance = Self {
+            address,
+            token0,
+            token1,
+            tick_spacing,
+            current_tick: I24::ZERO,
+            active_ticks: Vec::new(),
+            bitmap: HashMap::new(),
+            liquidity: U256::ZERO,
+            x96price: U256::ZERO,
+            fee_zto: U24::ZERO,
+            fee_otz: U24::ZERO,
+            contract,
+        };
+        instance.update_at(block).await?;
+        Ok(instance)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
+    }
+
+    /// Reload price, fee, liquidity and the ticks around the price as of
+    /// `block`
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let state = self
+            .raw_call(IAlgebraPool::globalStateCall {}.abi_encode(), block)
+            .await?;
+        let (fee_zto, fee_otz) = match state.len() {
+            7 => (state[2], state[2]),
+            8 => (state[2], state[3]),
+            6 => {
+                return Err(PoolError::Unsupported {
+                    pool: self.address,
+                    reason: "Algebra Integral tick tree".into(),
+                });
+            }
+            n => {
+                return Err(PoolError::Unsupported {
+                    pool: self.address,
+                    reason: format!("globalState of {n} words"),
+                });
+            }
+        };
+        self.x96price = state[0];
+        self.current_tick = signed_word::<i32>(state[1], "tick")?
+            .try_into()
+            .map_err(|_| PoolError::Decode(format!("tick {} out of range", state[1])))?;
+        self.fee_zto = U24::saturating_from(fee_zto);
+        self.fee_otz = U24::saturating_from(fee_otz);
+        self.liquidity = U256::from(self.contract.liquidity().call().block(block).await?);
+
+        self.bitmap.clear();
+        self.active_ticks = self.load_ticks(5, block).await?;
+        Ok(())
+    }
+
+    /// `range` initialized ticks on each side of the price with their
+    /// liquidity deltas
+    async fn load_ticks(&mut self, range: usize, block: BlockId) -> Result<Vec<Tick>, PoolError> {
+        let contract = &self.contract;
+        let read_word = async |word: i16| -> Result<U256, PoolError> {
+            Ok(contract.tickTable(word).call().block(block).await?)
+        };
+        let (start, spacing) = (self.current_tick, self.tick_spacing);
+        let mut ticks = walk_left(&mut self.bitmap, start, spacing, range, &read_word).await?;
+        ticks.reverse();
+        ticks.extend(walk_right(&mut self.bitmap, start, spacing, range, &read_word).await?);
+
+        let mut loaded = Vec::with_capacity(ticks.len());
+        for tick in ticks {
+            // `liquidityTotal` then `liquidityDelta` in every version
+            let info = self
+                .raw_call(IAlgebraPool::ticksCall { tick }.abi_encode(), block)
+                .await?;
+            let delta = info.get(1).ok_or_else(|| {
+                PoolError::Decode(format!("ticks({tick}) returned {} words", info.len()))
+            })?;
+            loaded.push(Tick {
+                tick,
+                liquidity_net: Some(signed_word(*delta, "liquidityDelta")?),
+            });
+        }
+        Ok(loaded)
+    }
+
+    async fn raw_call(&self, input: Vec<u8>, block: BlockId) -> Result<Vec<U256>, PoolError> {
+        let tx = TransactionRequest::default()
+            .with_to(self.address)
+            .with_input(input);
+        let output = self.contract.provider().call(tx).block(block).await?;
+        Ok(words(&output))
+    }
+
+    pub fn into_sim(&self) -> AlgebraPoolSim {
+        AlgebraPoolSim {
+            pool: V3PoolSim {
+                address: self.address,
+                token0: self.token0,
+                token1: self.token1,
+                fee: self.fee_zto,
+                current_tick: self.current_tick,
+                active_ticks: self.active_ticks.clone(),
+                tick_spacing: self.tick_spacing,
+                liquidity: self.liquidity,
+                x96price: self.x96price,
+                tax0: TransferTax::None,
+                tax1: TransferTax::None,
+            },
+            fee_zto: self.fee_zto,
+            fee_otz: self.fee_otz,
+        }
+    }
+}
@@ -0,0 +1,466 @@
+//! Balancer V2 math: `FixedPoint`, `LogExpMath`, `WeightedMath` and
+//! `StableMath`, ported with the contracts' rounding so quotes match the
+//! Vault's `queryBatchSwap`.
+
+use alloy::primitives::{I256, U256, uint};
+
+use crate::curve_pool_sim::{add, div, mul, sub};
+use crate::err::MathError;
+
+pub const ONE: U256 = uint!(1000000000000000000_U256);
+/// Relative error `powDown` / `powUp` allow for `LogExpMath.pow`, 1e-14
+const MAX_POW_RELATIVE_ERROR: U256 = uint!(10000_U256);
+/// Swaps can't move more than 30% of a weighted pool's balance
+const MAX_IN_RATIO: U256 = uint!(300000000000000000_U256);
+const MAX_OUT_RATIO: U256 = uint!(300000000000000000_U256);
+/// `getAmplificationParameter` is scaled by this
+pub const AMP_PRECISION: u64 = 1_000;
+
+pub fn mul_down(a: U256, b: U256) -> Result<U256, MathError> {
+    Ok(mul(a, b, "mulDown")? / ONE)
+}
+
+pub fn mul_up(a: U256, b: U256) -> Result<U256, MathError> {
+    let product = mul(a, b, "mulUp")?;
+    if product.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    Ok((product - U256::ONE) / ONE + U256::ONE)
+}
+
+pub fn div_down(a: U256, b: U256) -> Result<U256, MathError> {
+    div(mul(a, ONE, "divDown")?, b, "divDown")
+}
+
+pub fn div_up(a: U256, b: U256) -> Result<U256, MathError> {
+    if b.is_zero() {
+        return Err(MathError::overflow("divUp"));
+    }
+    if a.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    Ok((mul(a, ONE, "divUp")? - U256::ONE) / b + U256::ONE)
+}
+
+pub fn complement(x: U256) -> U256 {
+    ONE.saturating_sub(x)
+}
+
+/// `Math.divUp` on plain integers
+fn int_div_up(a: U256, b: U256, op: &'static str) -> Result<U256, MathError> {
+    if b.is_zero() {
+        return Err(MathError::overflow(op));
+    }
+    if a.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    Ok((a - U256::ONE) / b + U256::ONE)
+}
+
+/// `x^y` rounded down, exact for the integer exponents weights often give
+pub fn pow_down(x: U256, y: U256) -> Result<U256, MathError> {
+    if y == ONE {
+        return Ok(x);
+    }
+    if y == ONE * uint!(2_U256) {
+        return mul_down(x, x);
+    }
+    if y == ONE * uint!(4_U256) {
+        let square = mul_down(x, x)?;
+        return mul_down(square, square);
+    }
+    let raw = log_exp::pow(x, y)?;
+    let max_error = add(mul_up(raw, MAX_POW_RELATIVE_ERROR)?, U256::ONE, "powDown")?;
+    Ok(raw.saturating_sub(max_error))
+}
+
+/// `x^y` rounded up
+pub fn pow_up(x: U256, y: U256) -> Result<U256, MathError> {
+    if y == ONE {
+        return Ok(x);
+    }
+    if y == ONE * uint!(2_U256) {
+        return mul_up(x, x);
+    }
+    if y == ONE * uint!(4_U256) {
+        let square = mul_up(x, x)?;
+        return mul_up(square, square);
+    }
+    let raw = log_exp::pow(x, y)?;
+    let max_error = add(mul_up(raw, MAX_POW_RELATIVE_ERROR)?, U256::ONE, "powUp")?;
+    add(raw, max_error, "powUp")
+}
+
+/// `LogExpMath`: `exp` and `ln` by decomposition into precomputed powers of
+/// e and a Taylor series, 18 decimal fixed point
+pub mod log_exp {
+    use super::*;
+
+    const fn int(v: U256) -> I256 {
+        I256::from_raw(v)
+    }
+
+    const ONE_18: I256 = int(uint!(1000000000000000000_U256));
+    const ONE_20: I256 = int(uint!(100000000000000000000_U256));
+    const ONE_36: I256 = int(uint!(1000000000000000000000000000000000000_U256));
+    const TWO: I256 = int(uint!(2_U256));
+    const HUNDRED: I256 = int(uint!(100_U256));
+
+    const MAX_NATURAL_EXPONENT: I256 = int(uint!(130000000000000000000_U256));
+    /// Negated, `MIN_NATURAL_EXPONENT` is -41e18
+    const MIN_NATURAL_EXPONENT_NEG: I256 = int(uint!(41000000000000000000_U256));
+    const LN_36_LOWER_BOUND: I256 = int(uint!(900000000000000000_U256));
+    const LN_36_UPPER_BOUND: I256 = int(uint!(1100000000000000000_U256));
+    /// `2**254 / ONE_20`
+    const MILD_EXPONENT_BOUND: U256 =
+        uint!(289480223093290488558927462521719769633174961664101410098_U256);
+
+    // 18 decimals
+    const X0: I256 = int(uint!(128000000000000000000_U256));
+    const A0: I256 = int(uint!(
+        38877084059945950922200000000000000000000000000000000000_U256
+    ));
+    const X1: I256 = int(uint!(64000000000000000000_U256));
+    const A1: I256 = int(uint!(6235149080811616882910000000_U256));
+
+    // 20 decimals
+    const X2: I256 = int(uint!(3200000000000000000000_U256));
+    const A2: I256 = int(uint!(7896296018268069516100000000000000_U256));
+    const X3: I256 = int(uint!(1600000000000000000000_U256));
+    const A3: I256 = int(uint!(888611052050787263676000000_U256));
+    const X4: I256 = int(uint!(800000000000000000000_U256));
+    const A4: I256 = int(uint!(298095798704172827474000_U256));
+    const X5: I256 = int(uint!(400000000000000000000_U256));
+    const A5: I256 = int(uint!(5459815003314423907810_U256));
+    const X6: I256 = int(uint!(200000000000000000000_U256));
+    const A6: I256 = int(uint!(738905609893065022723_U256));
+    const X7: I256 = int(uint!(100000000000000000000_U256));
+    const A7: I256 = int(uint!(271828182845904523536_U256));
+    const X8: I256 = int(uint!(50000000000000000000_U256));
+    const A8: I256 = int(uint!(164872127070012814685_U256));
+    const X9: I256 = int(uint!(25000000000000000000_U256));
+    const A9: I256 = int(uint!(128402541668774148407_U256));
+    const X10: I256 = int(uint!(12500000000000000000_U256));
+    const A10: I256 = int(uint!(113314845306682631683_U256));
+    const X11: I256 = int(uint!(6250000000000000000_U256));
+    const A11: I256 = int(uint!(106449445891785942956_U256));
+
+    /// `x^y`, both 18 decimal fixed point
+    pub fn pow(x: U256, y: U256) -> Result<U256, MathError> {
+        if y.is_zero() {
+            return Ok(ONE);
+        }
+        if x.is_zero() {
+            return Ok(U256::ZERO);
+        }
+        if x.bit(255) || y >= MILD_EXPONENT_BOUND {
+            return Err(MathError::Unsafe { op: "pow" });
+        }
+        let (x, y) = (int(x), int(y));
+
+        let mut logx_times_y = if LN_36_LOWER_BOUND < x && x < LN_36_UPPER_BOUND {
+            let ln_36_x = ln_36(x);
+            (ln_36_x / ONE_18) * y + ((ln_36_x % ONE_18) * y) / ONE_18
+        } else {
+            ln(x) * y
+        };
+        logx_times_y /= ONE_18;
+
+        if logx_times_y < -MIN_NATURAL_EXPONENT_NEG || logx_times_y > MAX_NATURAL_EXPONENT {
+            return Err(MathError::Unsafe { op: "pow" });
+        }
+        Ok(exp(logx_times_y)?.into_raw())
+    }
+
+    /// `e^x`, 18 decimal fixed point
+    pub fn exp(mut x: I256) -> Result<I256, MathError> {
+        if x < -MIN_NATURAL_EXPONENT_NEG || x > MAX_NATURAL_EXPONENT {
+            return Err(MathError::Unsafe { op: "exp" });
+        }
+        if x.is_negative() {
+            return Ok((ONE_18 * ONE_18) / exp(-x)?);
+        }
+
+        let first_an = if x >= X0 {
+            x -= X0;
+            A0
+        } else if x >= X1 {
+            x -= X1;
+            A1
+        } else {
+            I256::ONE
+        };
+
+        x *= HUNDRED;
+        let mut product = ONE_20;
+        for (x_n, a_n) in [
+            (X2, A2),
+            (X3, A3),
+            (X4, A4),
+            (X5, A5),
+            (X6, A6),
+            (X7, A7),
+            (X8, A8),
+            (X9, A9),
+        ] {
+            if x >= x_n {
+                x -= x_n;
+                product = (product * a_n) / ONE_20;
+            }
+        }
+
+        let mut series_sum = ONE_20;
+        let mut term = x;
+        series_sum += term;
+        for n in 2..=12u64 {
+            term = ((term * x) / ONE_20) / int(U256::from(n));
+            series_sum += term;
+        }
+
+        Ok((((product * series_sum) / ONE_20) * first_an) / HUNDRED)
+    }
+
+    /// `ln(a)`, 18 decimal fixed point
+    fn ln(mut a: I256) -> I256 {
+        if a < ONE_18 {
+            return -ln((ONE_18 * ONE_18) / a);
+        }
+
+        let mut sum = I256::ZERO;
+        if a >= A0 * ONE_18 {
+            a /= A0;
+            sum += X0;
+        }
+        if a >= A1 * ONE_18 {
+            a /= A1;
+            sum += X1;
+        }
+
+        sum *= HUNDRED;
+        a *= HUNDRED;
+        for (x_n, a_n) in [
+            (X2, A2),
+            (X3, A3),
+            (X4, A4),
+            (X5, A5),
+            (X6, A6),
+            (X7, A7),
+            (X8, A8),
+            (X9, A9),
+            (X10, A10),
+            (X11, A11),
+        ] {
+            if a >= a_n {
+                a = (a * ONE_20) / a_n;
+                sum += x_n;
+            }
+        }
+
+        let z = ((a - ONE_20) * ONE_20) / (a + ONE_20);
+        let z_squared = (z * z) / ONE_20;
+        let mut num = z;
+        let mut series_sum = num;
+        for n in [3u64, 5, 7, 9, 11] {
+            num = (num * z_squared) / ONE_20;
+            series_sum += num / int(U256::from(n));
+        }
+        series_sum *= TWO;
+
+        (sum + series_sum) / HUNDRED
+    }
+
+    /// `ln(x)` with 36 decimals for `x` close to one, 18 decimal input
+    fn ln_36(mut x: I256) -> I256 {
+        x *= ONE_18;
+        let z = ((x - ONE_36) * ONE_36) / (x + ONE_36);
+        let z_squared = (z * z) / ONE_36;
+        let mut num = z;
+        let mut series_sum = num;
+        for n in [3u64, 5, 7, 9, 11, 13, 15] {
+            num = (num * z_squared) / ONE_36;
+            series_sum += num / int(U256::from(n));
+        }
+        series_sum * TWO
+    }
+}
+
+/// `WeightedMath._calcOutGivenIn`, all amounts upscaled to 18 decimals
+pub fn weighted_out_given_in(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_in: U256,
+) -> Result<U256, MathError> {
+    if amount_in > mul_down(balance_in, MAX_IN_RATIO)? {
+        return Err(MathError::Unsafe { op: "max in ratio" });
+    }
+    let denominator = add(balance_in, amount_in, "calcOutGivenIn")?;
+    let base = div_up(balance_in, denominator)?;
+    let exponent = div_down(weight_in, weight_out)?;
+    let power = pow_up(base, exponent)?;
+    mul_down(balance_out, complement(power))
+}
+
+/// `WeightedMath._calcInGivenOut`
+pub fn weighted_in_given_out(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_out: U256,
+) -> Result<U256, MathError> {
+    if amount_out > mul_down(balance_out, MAX_OUT_RATIO)? {
+        return Err(MathError::Unsafe {
+            op: "max out ratio",
+        });
+    }
+    let base = div_up(balance_out, sub(balance_out, amount_out, "calcInGivenOut")?)?;
+    let exponent = div_up(weight_out, weight_in)?;
+    let power = pow_up(base, exponent)?;
+    let ratio = sub(power, ONE, "calcInGivenOut")?;
+    mul_up(balance_in, ratio)
+}
+
+/// `StableMath._calculateInvariant`, `amp` times `AMP_PRECISION`
+pub fn stable_invariant(amp: U256, balances: &[U256]) -> Result<U256, MathError> {
+    const OP: &str = "calculateInvariant";
+    let n = U256::from(balances.len());
+    let amp_precision = U256::from(AMP_PRECISION);
+
+    let mut sum = U256::ZERO;
+    for balance in balances {
+        sum = add(sum, *balance, OP)?;
+    }
+    if sum.is_zero() {
+        return Ok(U256::ZERO);
+    }
+
+    let mut invariant = sum;
+    let amp_times_total = mul(amp, n, OP)?;
+    for _ in 0..255 {
+        let mut d_p = invariant;
+        for balance in balances {
+            d_p = div(mul(d_p, invariant, OP)?, mul(*balance, n, OP)?, OP)?;
+        }
+        let prev_invariant = invariant;
+        invariant = div(
+            mul(
+                add(
+                    div(mul(amp_times_total, sum, OP)?, amp_precision, OP)?,
+                    mul(d_p, n, OP)?,
+                    OP,
+                )?,
+                invariant,
+                OP,
+            )?,
+            add(
+                div(
+                    mul(sub(amp_times_total, amp_precision, OP)?, invariant, OP)?,
+                    amp_precision,
+                    OP,
+                )?,
+                mul(n + U256::ONE, d_p, OP)?,
+                OP,
+            )?,
+            OP,
+        )?;
+        if invariant.abs_diff(prev_invariant) <= U256::ONE {
+            return Ok(invariant);
+        }
+    }
+    Err(MathError::NoConvergence { op: OP })
+}
+
+/// `StableMath._getTokenBalanceGivenInvariantAndAllOtherBalances`
+fn stable_balance(
+    amp: U256,
+    balances: &[U256],
+    invariant: U256,
+    token_index: usize,
+) -> Result<U256, MathError> {
+    const OP: &str = "getTokenBalance";
+    let n = U256::from(balances.len());
+    let amp_precision = U256::from(AMP_PRECISION);
+    let amp_times_total = mul(amp, n, OP)?;
+
+    let mut sum = balances[0];
+    let mut p_d = mul(balances[0], n, OP)?;
+    for balance in &balances[1..] {
+        p_d = div(mul(mul(p_d, *balance, OP)?, n, OP)?, invariant, OP)?;
+        sum = add(sum, *balance, OP)?;
+    }
+    sum -= balances[token_index];
+
+    let inv2 = mul(invariant, invariant, OP)?;
+    let c = mul(
+        mul(
+            int_div_up(inv2, mul(amp_times_total, p_d, OP)?, OP)?,
+            amp_precision,
+            OP,
+        )?,
+        balances[token_index],
+        OP,
+    )?;
+    let b = add(
+        sum,
+        mul(div(invariant, amp_times_total, OP)?, amp_precision, OP)?,
+        OP,
+    )?;
+
+    let mut token_balance = int_div_up(add(inv2, c, OP)?, add(invariant, b, OP)?, OP)?;
+    for _ in 0..255 {
+        let prev_token_balance = token_balance;
+        token_balance = int_div_up(
+            add(mul(token_balance, token_balance, OP)?, c, OP)?,
+            sub(
+                add(mul(token_balance, U256::from(2), OP)?, b, OP)?,
+                invariant,
+                OP,
+            )?,
+            OP,
+        )?;
+        if token_balance.abs_diff(prev_token_balance) <= U256::ONE {
+            return Ok(token_balance);
+        }
+    }
+    Err(MathError::NoConvergence { op: OP })
+}
+
+/// `StableMath._calcOutGivenIn`
+pub fn stable_out_given_in(
+    amp: U256,
+    balances: &[U256],
+    index_in: usize,
+    index_out: usize,
+    amount_in: U256,
+    invariant: U256,
+) -> Result<U256, MathError> {
+    let mut balances = balances.to_vec();
+    balances[index_in] = add(balances[index_in], amount_in, "calcOutGivenIn")?;
+    let final_balance_out = stable_balance(amp, &balances, invariant, index_out)?;
+    sub(
+        sub(balances[index_out], final_balance_out, "calcOutGivenIn")?,
+        U256::ONE,
+        "calcOutGivenIn",
+    )
+}
+
+/// `StableMath._calcInGivenOut`
+pub fn stable_in_given_out(
+    amp: U256,
+    balances: &[U256],
+    index_in: usize,
+    index_out: usize,
+    amount_out: U256,
+    invariant: U256,
+) -> Result<U256, MathError> {
+    let mut balances = balances.to_vec();
+    balances[index_out] = sub(balances[index_out], amount_out, "calcInGivenOut")?;
+    let final_balance_in = stable_balance(amp, &balances, invariant, index_in)?;
+    add(
+        sub(final_balance_in, balances[index_in], "calcInGivenOut")?,
+        U256::ONE,
+        "calcInGivenOut",
+    )
+}
@@ -0,0 +1,148 @@
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, B256, U256};
+
+use crate::balancer_math::{
+    ONE, complement, div_down, div_up, mul_down, mul_up, stable_in_given_out, stable_invariant,
+    stable_out_given_in, weighted_in_given_out, weighted_out_given_in,
+};
+use crate::err::{MathError, PoolError};
+use crate::trade::Trade;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalancerKind {
+    /// Normalized weights summing to 1e18
+    Weighted { weights: Vec<U256> },
+    /// Amplification times `AMP_PRECISION`
+    Stable { amp: U256 },
+}
+
+/// Offline copy of a Balancer V2 weighted or stable pool.
+#[derive(Debug, Clone)]
+pub struct BalancerPoolSim {
+    pub address: Address,
+    pub pool_id: B256,
+    pub tokens: Vec<Address>,
+    pub balances: Vec<U256>,
+    /// `10**(18 - decimals) * 1e18` per token, times its rate in pools with
+    /// rate providers
+    pub scaling_factors: Vec<U256>,
+    /// 1e18 scaled
+    pub swap_fee: U256,
+    pub kind: BalancerKind,
+}
+
+impl BalancerPoolSim {
+    pub fn index_of(&self, token: &Address) -> Option<usize> {
+        self.tokens.iter().position(|t| t == token)
+    }
+
+    fn check_tokens(&self, i: usize, j: usize) -> Result<(), PoolError> {
+        if i == j || i >= self.tokens.len() || j >= self.tokens.len() {
+            return Err(PoolError::Unsupported {
+                pool: self.address,
+                reason: format!("no swap from token {i} to token {j}"),
+            });
+        }
+        Ok(())
+    }
+
+    fn upscaled_balances(&self) -> Result<Vec<U256>, MathError> {
+        self.balances
+            .iter()
+            .zip(&self.scaling_factors)
+            .map(|(balance, factor)| mul_down(*balance, *factor))
+            .collect()
+    }
+
+    /// Output of selling `amount_in` of token `i` for token `j`, as the pool's
+    /// `onSwap` gives it to `queryBatchSwap`
+    pub fn out_given_in(&self, i: usize, j: usize, amount_in: U256) -> Result<U256, PoolError> {
+        self.check_tokens(i, j)?;
+        // the fee comes off before scaling, like `_subtractSwapFeeAmount`
+        let fee = mul_up(amount_in, self.swap_fee)?;
+        let amount_in = mul_down(amount_in - fee, self.scaling_factors[i])?;
+        let balances = self.upscaled_balances()?;
+
+        let amount_out = match &self.kind {
+            BalancerKind::Weighted { weights } => {
+                weighted_out_given_in(balances[i], weights[i], balances[j], weights[j], amount_in)?
+            }
+            BalancerKind::Stable { amp } => {
+                let invariant = stable_invariant(*amp, &balances)?;
+                stable_out_given_in(*amp, &balances, i, j, amount_in, invariant)?
+            }
+        };
+        Ok(div_down(amount_out, self.scaling_factors[j])?)
+    }
+
+    /// Input of token `i`, fee included, that buys `amount_out` of token `j`
+    pub fn in_given_out(&self, i: usize, j: usize, amount_out: U256) -> Result<U256, PoolError> {
+        self.check_tokens(i, j)?;
+        let amount_out = mul_down(amount_out, self.scaling_factors[j])?;
+        let balances = self.upscaled_balances()?;
+
+        let amount_in = match &self.kind {
+            BalancerKind::Weighted { weights } => {
+                weighted_in_given_out(balances[i], weights[i], balances[j], weights[j], amount_out)?
+            }
+            BalancerKind::Stable { amp } => {
+                let invariant = stable_invariant(*amp, &balances)?;
+                stable_in_given_out(*amp, &balances, i, j, amount_out, invariant)?
+            }
+        };
+        let amount_in = div_up(amount_in, self.scaling_factors[i])?;
+        Ok(div_up(amount_in, complement(self.swap_fee))?)
+    }
+
+    /// Sell `amount_in` of token `i` for token `j`, the whole input including
+    /// the fee stays in the pool
+    pub fn swap(&mut self, i: usize, j: usize, amount_in: U256) -> Result<U256, PoolError> {
+        let amount_out = self.out_given_in(i, j, amount_in)?;
+        self.balances[i] += amount_in;
+        self.balances[j] -= amount_out;
+        Ok(amount_out)
+    }
+
+    /// Swap between any two tokens, reported with token `i` as token0
+    pub fn trade_tokens(
+        &mut self,
+        i: usize,
+        j: usize,
+        amount_in: U256,
+    ) -> Result<Trade, PoolError> {
+        let amount_out = self.swap(i, j, amount_in)?;
+        Ok(Trade {
+            fee: self.fee_pips(),
+            token0: self.tokens[i],
+            token1: self.tokens[j],
+            pool: self.address,
+            from0: true,
+            amount_in,
+            amount_out,
+            amount_in_net: amount_in,
+            amount_out_net: amount_out,
+        })
+    }
+
+    /// Swap between tokens 0 and 1, `trade_tokens` reaches the others
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let (i, j) = if from0 { (0, 1) } else { (1, 0) };
+        let amount_out = self.swap(i, j, amount_in)?;
+        Ok(Trade {
+            fee: self.fee_pips(),
+            token0: self.tokens[0],
+            token1: self.tokens[1],
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out,
+            amount_in_net: amount_in,
+            amount_out_net: amount_out,
+        })
+    }
+
+    /// Fee in millionths, the unit `Trade` reports fees in
+    pub fn fee_pips(&self) -> U24 {
+        U24::from(self.swap_fee / (ONE / U256::from(1_000_000)))
+    }
+}
@@ -0,0 +1,179 @@
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, B256, U256};
+
+use crate::IBalancerPool::IBalancerPoolInstance;
+use crate::balancer_math::ONE;
+use crate::balancer_pool_sim::{BalancerKind, BalancerPoolSim};
+use crate::curve_pool_src::optional;
+use crate::err::PoolError;
+use crate::v3_pool_src::Rpc;
+use crate::{IBalancerVault, IERC20Decimals};
+
+type BalancerContract = IBalancerPoolInstance<Rpc>;
+
+/// A Balancer V2 weighted or stable pool. Tokens and balances live in the
+/// Vault, weights or amplification and the fee in the pool.
+///
+/// Composable stable pools list their own BPT among the tokens, it is left
+/// out so only swaps between the underlying tokens are quoted.
+#[derive(Debug)]
+pub struct BalancerPoolSrc {
+    pub address: Address,
+    pub pool_id: B256,
+    pub vault: Address,
+    pub tokens: Vec<Address>,
+    pub balances: Vec<U256>,
+    pub scaling_factors: Vec<U256>,
+    /// Scaling of every token the Vault lists, from their decimals, on pools
+    /// without `getScalingFactors`. Decimals don't change, they're read once
+    pub decimal_scaling: Option<Vec<U256>>,
+    pub swap_fee: U256,
+    pub kind: BalancerKind,
+    pub contract: BalancerContract,
+}
+
+impl BalancerPoolSrc {
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(address, provider, BlockId::latest()).await
+    }
+
+    /// Load the pool as of `block`, weighted if it has weights, stable if it
+    /// has an amplification
+    pub async fn new_at(
+        address: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = IBalancerPoolInstance::new(address, provider.clone());
+        let pool_id = contract.getPoolId().call().block(block).await?;
+        let vault = contract.getVault().call().block(block).await?;
+        let pool_tokens = IBalancerVault::new(vault, provider.clone())
+            .getPoolTokens(pool_id)
+            .call()
+            .block(block)
+            .await?;
+
+        let kind = match optional(contract.getNormalizedWeights().call().block(block)).await? {
+            Some(weights) => BalancerKind::Weighted { weights },
+            None => match optional(contract.getAmplificationParameter().call().block(block)).await?
+            {
+                Some(amp) => BalancerKind::Stable { amp: amp.value },
+                None => {
+                    return Err(PoolError::Unsupported {
+                        pool: address,
+                        reason: "neither a weighted nor a stable pool".into(),
+                    });
+                }
+            },
+        };
+        let swap_fee = contract.getSwapFeePercentage().call().block(block).await?;
+
+        let (scaling_factors, decimal_scaling) =
+            match optional(contract.getScalingFactors().call().block(block)).await? {
+                Some(factors) => (factors, None),
+                None => {
+                    let mut factors = Vec::with_capacity(pool_tokens.tokens.len());
+                    for token in &pool_tokens.tokens {
+                        let decimals = IERC20Decimals::new(*token, provider.clone())
+                            .decimals()
+                            .call()
+                            .block(block)
+                            .await?;
+                        let scale = U256::from(10).pow(U256::from(18 - decimals.min(18) as u64));
+                        factors.push(scale * ONE);
+                    }
+                    (factors.clone(), Some(factors))
+                }
+            };
+
+        let mut instance = Self {
+            address,
+            pool_id,
+            vault,
+            tokens: Vec::new(),
+            balances: Vec::new(),
+            scaling_factors: Vec::new(),
+            decimal_scaling,
+            swap_fee,
+            kind,
+            contract,
+        };
+        instance.set_tokens(pool_tokens.tokens, pool_tokens.balances, scaling_factors)?;
+        Ok(instance)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
+    }
+
+    /// Reload balances, fee, rates and weights or amplification as of
+    /// `block`, the tokens and the kind of pool stay
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let contract = &self.contract;
+        let vault = IBalancerVault::new(self.vault, contract.provider().clone());
+        let pool_tokens = vault
+            .getPoolTokens(self.pool_id)
+            .call()
+            .block(block)
+            .await?;
+
+        let kind = match self.kind {
+            BalancerKind::Weighted { .. } => BalancerKind::Weighted {
+                weights: contract.getNormalizedWeights().call().block(block).await?,
+            },
+            BalancerKind::Stable { .. } => BalancerKind::Stable {
+                amp: contract
+                    .getAmplificationParameter()
+                    .call()
+                    .block(block)
+                    .await?
+                    .value,
+            },
+        };
+        let swap_fee = contract.getSwapFeePercentage().call().block(block).await?;
+        let scaling_factors = match &self.decimal_scaling {
+            Some(factors) => factors.clone(),
+            None => contract.getScalingFactors().call().block(block).await?,
+        };
+
+        self.set_tokens(pool_tokens.tokens, pool_tokens.balances, scaling_factors)?;
+        self.kind = kind;
+        self.swap_fee = swap_fee;
+        Ok(())
+    }
+
+    /// Take the Vault's tokens and balances without the pool's own BPT
+    fn set_tokens(
+        &mut self,
+        tokens: Vec<Address>,
+        balances: Vec<U256>,
+        scaling_factors: Vec<U256>,
+    ) -> Result<(), PoolError> {
+        if scaling_factors.len() != tokens.len() {
+            return Err(PoolError::Decode(format!(
+                "{} scaling factors for {} tokens",
+                scaling_factors.len(),
+                tokens.len()
+            )));
+        }
+        let keep: Vec<usize> = (0..tokens.len())
+            .filter(|&i| tokens[i] != self.address)
+            .collect();
+        self.tokens = keep.iter().map(|&i| tokens[i]).collect();
+        self.balances = keep.iter().map(|&i| balances[i]).collect();
+        self.scaling_factors = keep.iter().map(|&i| scaling_factors[i]).collect();
+        Ok(())
+    }
+
+    pub fn into_sim(&self) -> BalancerPoolSim {
+        BalancerPoolSim {
+            address: self.address,
+            pool_id: self.pool_id,
+            tokens: self.tokens.clone(),
+            balances: self.balances.clone(),
+            scaling_factors: self.scaling_factors.clone(),
+            swap_fee: self.swap_fee,
+            kind: self.kind.clone(),
+        }
+    }
+}
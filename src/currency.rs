@@ -0,0 +1,99 @@
+//! Native currency next to ERC-20 tokens. V4 pools can hold ETH / BNB as
+//! `address(0)` while V2 and V3 pools only hold the wrapped token, so routes
+//! between them go through a `WrapSim`.
+
+use std::fmt;
+
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, U256, address};
+
+use crate::trade::Trade;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Currency {
+    /// The chain's own coin, `address(0)` in V4 pool keys and trades
+    Native,
+    Token(Address),
+}
+
+impl Currency {
+    pub fn is_native(&self) -> bool {
+        matches!(self, Self::Native)
+    }
+
+    /// Address pools and `Trade` use for it, zero for native
+    pub fn address(&self) -> Address {
+        match self {
+            Self::Native => Address::ZERO,
+            Self::Token(token) => *token,
+        }
+    }
+}
+
+/// Stands in for the chain's native coin where `address(0)` isn't used,
+/// Curve pools list it in `coins`
+pub const NATIVE_COIN: Address = address!("0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE");
+
+/// No token lives at `address(0)` or `NATIVE_COIN`, so both always mean native
+impl From<Address> for Currency {
+    fn from(address: Address) -> Self {
+        if address.is_zero() || address == NATIVE_COIN {
+            Self::Native
+        } else {
+            Self::Token(address)
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Native => write!(f, "native"),
+            Self::Token(token) => write!(f, "{token}"),
+        }
+    }
+}
+
+/// Canonical wrapped native token of a chain
+pub fn wrapped_native(chain_id: u64) -> Option<Address> {
+    match chain_id {
+        1 => Some(address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")),
+        56 => Some(address!("0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c")),
+        10 | 8453 => Some(address!("0x4200000000000000000000000000000000000006")),
+        42161 => Some(address!("0x82aF49447D8a07e3bd95BD0d56f35241523fBab1")),
+        137 => Some(address!("0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270")),
+        _ => None,
+    }
+}
+
+/// Wrapping and unwrapping as a pool between native (token0) and its
+/// wrapped token (token1), 1:1 and free apart from gas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrapSim {
+    pub wrapped: Address,
+}
+
+impl WrapSim {
+    pub fn new(wrapped: Address) -> Self {
+        Self { wrapped }
+    }
+
+    pub fn for_chain(chain_id: u64) -> Option<Self> {
+        wrapped_native(chain_id).map(Self::new)
+    }
+
+    /// `from0` wraps, otherwise unwraps
+    pub fn trade(&self, amount_in: U256, from0: bool) -> Trade {
+        Trade {
+            fee: U24::ZERO,
+            token0: Address::ZERO,
+            token1: self.wrapped,
+            pool: self.wrapped,
+            from0,
+            amount_in,
+            amount_out: amount_in,
+            amount_in_net: amount_in,
+            amount_out_net: amount_in,
+        }
+    }
+}
@@ -0,0 +1,712 @@
+//! Curve CryptoSwap math for volatile pairs (twocrypto, tricrypto), ported
+//! from the Newton solvers of the Vyper pools.
+//!
+//! The `-ng` pools solve for `y` in closed form and only fall back to
+//! Newton, `get_y` ports that solver and `CurveCryptoPoolSim::ng` picks it.
+
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, I256, U256};
+
+use crate::curve_pool_sim::{FEE_DENOMINATOR, add, div, mul, sub};
+use crate::err::{MathError, PoolError};
+use crate::trade::Trade;
+
+/// `A` is stored as `A * N**N * A_MULTIPLIER`
+pub const A_MULTIPLIER: u64 = 10_000;
+
+const E18: u128 = 1_000_000_000_000_000_000;
+const MAX_ROUNDS: usize = 255;
+
+fn e18() -> U256 {
+    U256::from(E18)
+}
+
+/// `10**18 * x[0] * ... * x[n-1] / (sum / n)**n`, or the fee weight derived
+/// from it with `fee_gamma`
+pub fn reduction_coefficient(x: &[U256], fee_gamma: U256) -> Result<U256, MathError> {
+    const OP: &str = "reduction_coefficient";
+    let n = U256::from(x.len());
+    let mut s = U256::ZERO;
+    for x in x {
+        s = add(s, *x, OP)?;
+    }
+    let mut k = e18();
+    for x in x {
+        k = div(mul(mul(k, n, OP)?, *x, OP)?, s, OP)?;
+    }
+    if !fee_gamma.is_zero() {
+        k = div(
+            mul(fee_gamma, e18(), OP)?,
+            sub(add(fee_gamma, e18(), OP)?, k, OP)?,
+            OP,
+        )?;
+    }
+    Ok(k)
+}
+
+/// Geometric mean of `x` sorted high to low
+fn geometric_mean(x: &[U256]) -> Result<U256, MathError> {
+    const OP: &str = "geometric_mean";
+    let n = U256::from(x.len());
+    let mut d = x[0];
+    for _ in 0..MAX_ROUNDS {
+        let d_prev = d;
+        let mut tmp = e18();
+        for x in x {
+            tmp = div(mul(tmp, *x, OP)?, d, OP)?;
+        }
+        d = div(
+            mul(d, add(mul(n - U256::ONE, e18(), OP)?, tmp, OP)?, OP)?,
+            mul(n, e18(), OP)?,
+            OP,
+        )?;
+        let diff = d.abs_diff(d_prev);
+        if diff <= U256::ONE || mul(diff, e18(), OP)? < d {
+            return Ok(d);
+        }
+    }
+    Err(MathError::NoConvergence { op: OP })
+}
+
+fn sorted_desc(x: &[U256]) -> Vec<U256> {
+    let mut x = x.to_vec();
+    x.sort_unstable_by(|a, b| b.cmp(a));
+    x
+}
+
+/// `|a - b| + 1`, the `_g1k0` of the solvers
+fn g1k0(gamma: U256, k0: U256) -> U256 {
+    (gamma + e18()).abs_diff(k0) + U256::ONE
+}
+
+/// `D / (A * N**N) * _g1k0**2 / gamma**2`, scaled by 1e18
+fn mul1(ann: U256, gamma: U256, d: U256, g1k0: U256, op: &'static str) -> Result<U256, MathError> {
+    let v = div(mul(e18(), d, op)?, gamma, op)?;
+    let v = div(mul(v, g1k0, op)?, gamma, op)?;
+    let v = mul(mul(v, g1k0, op)?, U256::from(A_MULTIPLIER), op)?;
+    div(v, ann, op)
+}
+
+/// The invariant `D` of balances `x` in coin 0's units, 1e18 scaled
+pub fn newton_d(ann: U256, gamma: U256, x: &[U256]) -> Result<U256, MathError> {
+    const OP: &str = "newton_D";
+    let n = U256::from(x.len());
+    let x = sorted_desc(x);
+    if x[0] < U256::from(1_000_000_000u64) {
+        return Err(MathError::Unsafe { op: OP });
+    }
+    for x_i in &x[1..] {
+        if div(mul(*x_i, e18(), OP)?, x[0], OP)? < U256::from(100_000_000_000u64) {
+            return Err(MathError::Unsafe { op: OP });
+        }
+    }
+
+    let mut d = mul(n, geometric_mean(&x)?, OP)?;
+    let mut s = U256::ZERO;
+    for x in &x {
+        s = add(s, *x, OP)?;
+    }
+
+    for _ in 0..MAX_ROUNDS {
+        let d_prev = d;
+        let mut k0 = e18();
+        for x in &x {
+            k0 = div(mul(mul(k0, *x, OP)?, n, OP)?, d, OP)?;
+        }
+        let g1k0 = g1k0(gamma, k0);
+        let mul1 = mul1(ann, gamma, d, g1k0, OP)?;
+        // 2 * N * K0 / _g1k0
+        let mul2 = div(mul(mul(U256::from(2) * e18(), n, OP)?, k0, OP)?, g1k0, OP)?;
+
+        let neg_fprime = sub(
+            add(
+                add(s, div(mul(s, mul2, OP)?, e18(), OP)?, OP)?,
+                div(mul(mul1, n, OP)?, k0, OP)?,
+                OP,
+            )?,
+            div(mul(mul2, d, OP)?, e18(), OP)?,
+            OP,
+        )?;
+
+        // D -= f / fprime
+        let d_plus = div(mul(d, add(neg_fprime, s, OP)?, OP)?, neg_fprime, OP)?;
+        let mut d_minus = div(mul(d, d, OP)?, neg_fprime, OP)?;
+        let step = div(mul(d, div(mul1, neg_fprime, OP)?, OP)?, e18(), OP)?;
+        if e18() > k0 {
+            d_minus = add(d_minus, div(mul(step, e18() - k0, OP)?, k0, OP)?, OP)?;
+        } else {
+            d_minus = sub(d_minus, div(mul(step, k0 - e18(), OP)?, k0, OP)?, OP)?;
+        }
+        d = if d_plus > d_minus {
+            d_plus - d_minus
+        } else {
+            (d_minus - d_plus) / U256::from(2)
+        };
+
+        if mul(d.abs_diff(d_prev), U256::from(100_000_000_000_000u64), OP)?
+            < d.max(U256::from(10_000_000_000_000_000u64))
+        {
+            for x in &x {
+                let frac = div(mul(*x, e18(), OP)?, d, OP)?;
+                if frac < U256::from(10_000_000_000_000_000u64) || frac > U256::from(E18 * 100) {
+                    return Err(MathError::Unsafe { op: OP });
+                }
+            }
+            return Ok(d);
+        }
+    }
+    Err(MathError::NoConvergence { op: OP })
+}
+
+/// Balance of coin `i` in coin 0's units that keeps `d` given the others
+pub fn newton_y(ann: U256, gamma: U256, x: &[U256], d: U256, i: usize) -> Result<U256, MathError> {
+    const OP: &str = "newton_y";
+    let coins = x.len();
+    let n = U256::from(coins);
+    let frac_ok =
+        |frac: U256| frac >= U256::from(10_000_000_000_000_000u64) && frac <= U256::from(E18 * 100);
+    for (k, x) in x.iter().enumerate() {
+        if k != i && !frac_ok(div(mul(*x, e18(), OP)?, d, OP)?) {
+            return Err(MathError::Unsafe { op: OP });
+        }
+    }
+
+    let mut y = d / n;
+    let mut k0_i = e18();
+    let mut s_i = U256::ZERO;
+
+    let mut x_sorted = x.to_vec();
+    x_sorted[i] = U256::ZERO;
+    let x_sorted = sorted_desc(&x_sorted);
+
+    let convergence_limit = (x_sorted[0] / U256::from(100_000_000_000_000u64))
+        .max(d / U256::from(100_000_000_000_000u64))
+        .max(U256::from(100));
+    for j in 2..=coins {
+        let x = x_sorted[coins - j];
+        y = div(mul(y, d, OP)?, mul(x, n, OP)?, OP)?;
+        s_i = add(s_i, x, OP)?;
+    }
+    for x in &x_sorted[..coins - 1] {
+        k0_i = div(mul(mul(k0_i, *x, OP)?, n, OP)?, d, OP)?;
+    }
+
+    for _ in 0..MAX_ROUNDS {
+        let y_prev = y;
+
+        let k0 = div(mul(mul(k0_i, y, OP)?, n, OP)?, d, OP)?;
+        let s = add(s_i, y, OP)?;
+        let g1k0 = g1k0(gamma, k0);
+        let mul1 = mul1(ann, gamma, d, g1k0, OP)?;
+        // 2 * K0 / _g1k0
+        let mul2 = add(
+            e18(),
+            div(mul(U256::from(2) * e18(), k0, OP)?, g1k0, OP)?,
+            OP,
+        )?;
+
+        let yfprime = add(add(mul(e18(), y, OP)?, mul(s, mul2, OP)?, OP)?, mul1, OP)?;
+        let dyfprime = mul(d, mul2, OP)?;
+        if yfprime < dyfprime {
+            y = y_prev / U256::from(2);
+            continue;
+        }
+        let yfprime = yfprime - dyfprime;
+        let fprime = div(yfprime, y, OP)?;
+
+        // y -= f / f_prime
+        let mut y_minus = div(mul1, fprime, OP)?;
+        let y_plus = add(
+            div(add(yfprime, mul(e18(), d, OP)?, OP)?, fprime, OP)?,
+            div(mul(y_minus, e18(), OP)?, k0, OP)?,
+            OP,
+        )?;
+        y_minus = add(y_minus, div(mul(e18(), s, OP)?, fprime, OP)?, OP)?;
+        y = if y_plus < y_minus {
+            y_prev / U256::from(2)
+        } else {
+            y_plus - y_minus
+        };
+
+        if y.abs_diff(y_prev) < convergence_limit.max(y / U256::from(100_000_000_000_000u64)) {
+            if !frac_ok(div(mul(y, e18(), OP)?, d, OP)?) {
+                return Err(MathError::Unsafe { op: OP });
+            }
+            return Ok(y);
+        }
+    }
+    Err(MathError::NoConvergence { op: OP })
+}
+
+/// Cube root of `x` as 1e18 scaled values, the `_cbrt` of the `-ng` math:
+/// an initial guess from `log2(x)` and seven Newton rounds
+pub fn cbrt(x: U256) -> U256 {
+    if x.is_zero() {
+        return U256::ZERO;
+    }
+    let e = |exp: u64| U256::from(10).pow(U256::from(exp));
+    let limit = U256::MAX / e(36);
+    let xx = if x >= limit * e(18) {
+        x
+    } else if x >= limit {
+        x * e(18)
+    } else {
+        x * e(36)
+    };
+
+    let log2x = xx.bit_len() - 1;
+    let remainder = (log2x % 3) as u32;
+    let mut a = (U256::ONE << (log2x / 3)) * U256::from(1260u64.pow(remainder))
+        / U256::from(1000u64.pow(remainder));
+    for _ in 0..7 {
+        a = (U256::from(2) * a + xx.checked_div(a * a).unwrap_or_default()) / U256::from(3);
+    }
+
+    if x >= limit * e(18) {
+        a * e(12)
+    } else if x >= limit {
+        a * e(6)
+    } else {
+        a
+    }
+}
+
+/// Signed 256 bit math for the cubic, `MathError::overflow` where the Vyper
+/// pools would revert
+struct Signed(&'static str);
+
+impl Signed {
+    fn int(&self, x: U256) -> Result<I256, MathError> {
+        I256::try_from(x).map_err(|_| MathError::overflow(self.0))
+    }
+
+    fn add(&self, a: I256, b: I256) -> Result<I256, MathError> {
+        a.checked_add(b).ok_or(MathError::overflow(self.0))
+    }
+
+    fn sub(&self, a: I256, b: I256) -> Result<I256, MathError> {
+        a.checked_sub(b).ok_or(MathError::overflow(self.0))
+    }
+
+    fn mul(&self, a: I256, b: I256) -> Result<I256, MathError> {
+        a.checked_mul(b).ok_or(MathError::overflow(self.0))
+    }
+
+    /// Rounds toward zero like Vyper's `/`
+    fn div(&self, a: I256, b: I256) -> Result<I256, MathError> {
+        a.checked_div(b).ok_or(MathError::overflow(self.0))
+    }
+
+    /// `sign(x) * cbrt(|x|)`
+    fn cbrt(&self, x: I256) -> Result<I256, MathError> {
+        let root = self.int(cbrt(x.unsigned_abs()))?;
+        Ok(if x.is_negative() { -root } else { root })
+    }
+}
+
+fn pow10(exp: u64) -> I256 {
+    I256::from_raw(U256::from(10).pow(U256::from(exp)))
+}
+
+/// Constant of the math, every `u64` fits without a sign check
+fn i256(x: u64) -> I256 {
+    I256::from_raw(U256::from(x))
+}
+
+/// The `-ng` pools' `get_y`, solving the invariant for `K0` as a cubic in
+/// closed form and falling back to `newton_y` when its discriminant isn't
+/// positive
+pub fn get_y(ann: U256, gamma: U256, x: &[U256], d: U256, i: usize) -> Result<U256, MathError> {
+    if d < U256::from(E18 / 10) || d > U256::from(E18) * U256::from(E18 / 1000) {
+        return Err(MathError::Unsafe { op: "get_y" });
+    }
+    match x.len() {
+        2 => get_y_twocrypto(ann, gamma, x, d, i),
+        _ => get_y_tricrypto(ann, gamma, x, d, i),
+    }
+}
+
+/// twocrypto-ng's `get_y`, `lim_mul` narrowing the safe range for large gammas
+fn get_y_twocrypto(
+    ann: U256,
+    gamma: U256,
+    x: &[U256],
+    d: U256,
+    i: usize,
+) -> Result<U256, MathError> {
+    const OP: &str = "get_y";
+    let s = Signed(OP);
+    let max_gamma_small = U256::from(20_000_000_000_000_000u64);
+    let mut lim_mul = U256::from(E18 * 100);
+    if gamma > max_gamma_small {
+        lim_mul = div(mul(lim_mul, max_gamma_small, OP)?, gamma, OP)?;
+    }
+    let lim = s.int(lim_mul)?;
+
+    let ann_ = s.int(ann)?;
+    let gamma_ = s.int(gamma)?;
+    let d_ = s.int(d)?;
+    let x_j = s.int(x[1 - i])?;
+    let gamma2 = s.mul(gamma_, gamma_)?;
+
+    let k0_i = s.div(s.mul(pow10(18) * i256(2), x_j)?, d_)?;
+    if k0_i < s.div(pow10(36), lim)? || k0_i > lim {
+        return Err(MathError::Unsafe { op: OP });
+    }
+
+    let ann_gamma2 = s.mul(ann_, gamma2)?;
+    let ann_gamma2_4 = s.div(s.mul(i256(4), ann_gamma2)?, i256(400_000_000))?;
+    let mut a = pow10(32);
+    let mut b = s.sub(
+        s.sub(
+            s.div(s.div(s.mul(d_, ann_gamma2)?, i256(400_000_000))?, x_j)?,
+            pow10(32) * i256(3),
+        )?,
+        s.mul(s.mul(i256(2), gamma_)?, pow10(14))?,
+    )?;
+    let mut c = s.sub(
+        s.add(
+            s.add(
+                s.add(
+                    pow10(32) * i256(3),
+                    s.mul(s.mul(i256(4), gamma_)?, pow10(14))?,
+                )?,
+                gamma2 / pow10(4),
+            )?,
+            s.div(s.mul(ann_gamma2_4, x_j)?, d_)?,
+        )?,
+        ann_gamma2_4,
+    )?;
+    let one_gamma = s.add(pow10(18), gamma_)?;
+    let mut d0 = -(s.mul(one_gamma, one_gamma)? / pow10(4));
+
+    // 3*a*c/b - b and 9*a*b*c - 2*b**3 - 27*a**2*d over b**2
+    let deltas = |a: I256, b: I256, c: I256, d0: I256| -> Result<(I256, I256), MathError> {
+        let delta0 = s.sub(s.div(s.mul(s.mul(i256(3), a)?, c)?, b)?, b)?;
+        let delta1 = s.sub(
+            s.add(s.mul(i256(3), delta0)?, b)?,
+            s.div(s.mul(s.div(s.mul(i256(27), s.mul(a, a)?)?, b)?, d0)?, b)?,
+        )?;
+        Ok((delta0, delta1))
+    };
+    let (delta0, delta1) = deltas(a, b, c, d0)?;
+
+    let threshold = delta0.abs().min(delta1.abs()).min(a);
+    let divider = [48, 46, 44, 42, 40, 38, 36, 34, 32, 30, 28, 26, 24, 20]
+        .into_iter()
+        .find(|exp| threshold > pow10(*exp))
+        .map_or(I256::ONE, |exp| pow10(exp - 18));
+    a /= divider;
+    b /= divider;
+    c /= divider;
+    d0 /= divider;
+
+    let (delta0, delta1) = deltas(a, b, c, d0)?;
+    let sqrt_arg = s.add(
+        s.mul(delta1, delta1)?,
+        s.mul(s.div(s.mul(i256(4), s.mul(delta0, delta0)?)?, b)?, delta0)?,
+    )?;
+    if sqrt_arg <= I256::ZERO {
+        return newton_y(ann, gamma, x, d, i);
+    }
+    let sqrt_val = s.int(sqrt_arg.unsigned_abs().root(2))?;
+
+    let b_cbrt = s.cbrt(b)?;
+    let second_cbrt = if delta1 > I256::ZERO {
+        s.cbrt(s.add(delta1, sqrt_val)? / i256(2))?
+    } else {
+        -s.cbrt(s.sub(sqrt_val, delta1)? / i256(2))?
+    };
+
+    let c1 = s.div(
+        s.mul(s.mul(b_cbrt, b_cbrt)? / pow10(18), second_cbrt)?,
+        pow10(18),
+    )?;
+    let root = s.div(
+        s.sub(
+            s.sub(s.mul(pow10(18), c1)?, s.mul(pow10(18), b)?)?,
+            s.mul(s.div(s.mul(pow10(18), b)?, c1)?, delta0)?,
+        )?,
+        s.mul(i256(3), a)?,
+    )?;
+    let y = s.div(
+        s.mul(s.div(s.mul(d_, d_)?, x_j)?, root)? / i256(4),
+        pow10(18),
+    )?;
+    if y.is_negative() {
+        return Err(MathError::Unsafe { op: OP });
+    }
+    let y = y.into_raw();
+
+    let frac = div(mul(y, e18(), OP)?, d, OP)?;
+    if frac < U256::from(E18 * E18 / 2) / lim_mul || frac > lim_mul / U256::from(2) {
+        return Err(MathError::Unsafe { op: OP });
+    }
+    Ok(y)
+}
+
+/// tricrypto-ng's `get_y`
+fn get_y_tricrypto(
+    ann: U256,
+    gamma: U256,
+    x: &[U256],
+    d: U256,
+    i: usize,
+) -> Result<U256, MathError> {
+    const OP: &str = "get_y";
+    let s = Signed(OP);
+    let frac_ok = |frac: U256| frac >= U256::from(E18 / 100) && frac <= U256::from(E18 * 100);
+    for (k, x) in x.iter().enumerate() {
+        if k != i && !frac_ok(div(mul(*x, e18(), OP)?, d, OP)?) {
+            return Err(MathError::Unsafe { op: OP });
+        }
+    }
+    let (j, k) = match i {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+
+    let ann_ = s.int(ann)?;
+    let gamma_ = s.int(gamma)?;
+    let d_ = s.int(d)?;
+    let x_j = s.int(x[j])?;
+    let x_k = s.int(x[k])?;
+    let gamma2 = s.mul(gamma_, gamma_)?;
+    let a_multiplier = i256(A_MULTIPLIER);
+
+    let mut a = pow10(36) / i256(27);
+    let mut b = s.sub(
+        pow10(36) / i256(9) + s.mul(pow10(18) * i256(2), gamma_)? / i256(27),
+        s.div(
+            s.div(
+                s.div(
+                    s.mul(s.mul(s.div(s.mul(d_, d_)?, x_j)?, gamma2)?, ann_)?,
+                    i256(27 * 27),
+                )?,
+                a_multiplier,
+            )?,
+            x_k,
+        )?,
+    )?;
+    let mut c = s.add(
+        pow10(36) / i256(9) + s.mul(gamma_, s.add(gamma_, pow10(18) * i256(4))?)? / i256(27),
+        s.div(
+            s.div(
+                s.mul(
+                    s.div(s.mul(gamma2, s.sub(s.add(x_j, x_k)?, d_)?)?, d_)?,
+                    ann_,
+                )?,
+                i256(27),
+            )?,
+            a_multiplier,
+        )?,
+    )?;
+    let one_gamma = s.add(pow10(18), gamma_)?;
+    let mut d0 = s.mul(one_gamma, one_gamma)? / i256(27);
+
+    let delta = s.sub(s.div(s.mul(i256(3) * a, c)?, b)?, b)?.abs();
+    let divider = [48, 44, 40, 36, 32, 28, 24, 20]
+        .into_iter()
+        .find(|exp| delta > pow10(*exp))
+        .map_or(I256::ONE, |exp| pow10(exp - 18));
+
+    if a.abs() > b.abs() {
+        let prec = s.div(a, b)?.abs();
+        a = s.mul(a, prec)? / divider;
+        b = s.mul(b, prec)? / divider;
+        c = s.mul(c, prec)? / divider;
+        d0 = s.mul(d0, prec)? / divider;
+    } else {
+        let prec = s.div(b, a)?.abs();
+        a = s.div(a, prec)? / divider;
+        b = s.div(b, prec)? / divider;
+        c = s.div(c, prec)? / divider;
+        d0 = s.div(d0, prec)? / divider;
+    }
+
+    let delta0 = s.sub(s.div(s.mul(i256(3) * a, c)?, b)?, b)?;
+    let delta1 = s.sub(
+        s.sub(s.div(s.mul(i256(9) * a, c)?, b)?, s.mul(i256(2), b)?)?,
+        s.div(s.mul(s.div(s.mul(i256(27), s.mul(a, a)?)?, b)?, d0)?, b)?,
+    )?;
+    let sqrt_arg = s.add(
+        s.mul(delta1, delta1)?,
+        s.mul(s.div(s.mul(i256(4), s.mul(delta0, delta0)?)?, b)?, delta0)?,
+    )?;
+    if sqrt_arg <= I256::ZERO {
+        return newton_y(ann, gamma, x, d, i);
+    }
+    let sqrt_val = s.int(sqrt_arg.unsigned_abs().root(2))?;
+
+    let b_cbrt = s.cbrt(b)?;
+    let second_cbrt = if delta1 > I256::ZERO {
+        s.cbrt(s.add(delta1, sqrt_val)? / i256(2))?
+    } else {
+        -s.cbrt(s.sub(sqrt_val, delta1)? / i256(2))?
+    };
+
+    let c1 = s.div(
+        s.mul(s.mul(b_cbrt, b_cbrt)? / pow10(18), second_cbrt)?,
+        pow10(18),
+    )?;
+    let root_k0 = s.sub(s.add(b, s.div(s.mul(b, delta0)?, c1)?)?, c1)? / i256(3);
+    let root = s.div(
+        s.mul(
+            s.div(s.mul(s.div(s.mul(d_, d_)? / i256(27), x_k)?, d_)?, x_j)?,
+            root_k0,
+        )?,
+        a,
+    )?;
+    if root.is_negative() {
+        return Err(MathError::Unsafe { op: OP });
+    }
+    let y = root.into_raw();
+    if !frac_ok(div(mul(y, e18(), OP)?, d, OP)?) {
+        return Err(MathError::Unsafe { op: OP });
+    }
+    Ok(y)
+}
+
+/// Offline copy of a Curve CryptoSwap pool with two or three coins.
+#[derive(Debug, Clone)]
+pub struct CurveCryptoPoolSim {
+    pub address: Address,
+    pub coins: Vec<Address>,
+    pub balances: Vec<U256>,
+    /// `10**(18 - decimals)` per coin
+    pub precisions: Vec<U256>,
+    /// Price of each coin after the first in coin 0, 1e18 scaled
+    pub price_scale: Vec<U256>,
+    /// `A * N**N * A_MULTIPLIER`
+    pub ann: U256,
+    pub gamma: U256,
+    pub d: U256,
+    /// Fee when balanced and fully imbalanced, over `FEE_DENOMINATOR`
+    pub mid_fee: U256,
+    pub out_fee: U256,
+    pub fee_gamma: U256,
+    /// `-ng` pool, `y` comes from `get_y` instead of `newton_y`
+    pub ng: bool,
+}
+
+impl CurveCryptoPoolSim {
+    pub fn index_of(&self, coin: &Address) -> Option<usize> {
+        self.coins.iter().position(|c| c == coin)
+    }
+
+    /// `balances` in coin 0's units, 1e18 scaled
+    pub fn xp(&self, balances: &[U256]) -> Result<Vec<U256>, MathError> {
+        let mut xp = Vec::with_capacity(balances.len());
+        for (k, balance) in balances.iter().enumerate() {
+            let x = mul(*balance, self.precisions[k], "xp")?;
+            xp.push(match k {
+                0 => x,
+                k => div(mul(x, self.price_scale[k - 1], "xp")?, e18(), "xp")?,
+            });
+        }
+        Ok(xp)
+    }
+
+    /// Fee over `FEE_DENOMINATOR` at balances `xp`, from `mid_fee` when
+    /// balanced towards `out_fee` as they drift apart
+    pub fn fee(&self, xp: &[U256]) -> Result<U256, MathError> {
+        const OP: &str = "fee";
+        let f = reduction_coefficient(xp, self.fee_gamma)?;
+        div(
+            add(
+                mul(self.mid_fee, f, OP)?,
+                mul(self.out_fee, sub(e18(), f, OP)?, OP)?,
+                OP,
+            )?,
+            e18(),
+            OP,
+        )
+    }
+
+    /// Output, fee and balances in coin 0's units after swapping `dx` of
+    /// coin `i` for coin `j`
+    fn quote(&self, i: usize, j: usize, dx: U256) -> Result<(U256, U256, Vec<U256>), PoolError> {
+        const OP: &str = "get_dy";
+        if i == j || i >= self.coins.len() || j >= self.coins.len() {
+            return Err(PoolError::Unsupported {
+                pool: self.address,
+                reason: format!("no swap from coin {i} to coin {j}"),
+            });
+        }
+        let mut balances = self.balances.clone();
+        balances[i] = add(balances[i], dx, OP)?;
+        let mut xp = self.xp(&balances)?;
+
+        let y = match self.ng {
+            true => get_y(self.ann, self.gamma, &xp, self.d, j)?,
+            false => newton_y(self.ann, self.gamma, &xp, self.d, j)?,
+        };
+        let mut dy = sub(xp[j], y, OP)?
+            .checked_sub(U256::ONE)
+            .ok_or(PoolError::NoLiquidity { pool: self.address })?;
+        xp[j] = y;
+        if j > 0 {
+            dy = div(mul(dy, e18(), OP)?, self.price_scale[j - 1], OP)?;
+        }
+        dy = div(dy, self.precisions[j], OP)?;
+
+        let fee = self.fee(&xp)?;
+        dy -= div(mul(fee, dy, OP)?, U256::from(FEE_DENOMINATOR), OP)?;
+        Ok((dy, fee, xp))
+    }
+
+    /// The pool's `get_dy(i, j, dx)`
+    pub fn get_dy(&self, i: usize, j: usize, dx: U256) -> Result<U256, PoolError> {
+        Ok(self.quote(i, j, dx)?.0)
+    }
+
+    /// The pool's `exchange(i, j, dx, 0)`. `D` is recomputed for the new
+    /// balances, `price_scale` stays until the pool is reloaded since the
+    /// repeg depends on the oracle and time
+    pub fn exchange(&mut self, i: usize, j: usize, dx: U256) -> Result<(U256, U256), PoolError> {
+        let (dy, fee, _) = self.quote(i, j, dx)?;
+        self.balances[i] += dx;
+        self.balances[j] -= dy;
+        self.d = newton_d(self.ann, self.gamma, &self.xp(&self.balances)?)?;
+        Ok((dy, fee))
+    }
+
+    /// Swap between any two coins, reported with coin `i` as token0
+    pub fn trade_coins(&mut self, i: usize, j: usize, dx: U256) -> Result<Trade, PoolError> {
+        let (dy, fee) = self.exchange(i, j, dx)?;
+        Ok(Trade {
+            fee: fee_pips(fee),
+            token0: self.coins[i],
+            token1: self.coins[j],
+            pool: self.address,
+            from0: true,
+            amount_in: dx,
+            amount_out: dy,
+            amount_in_net: dx,
+            amount_out_net: dy,
+        })
+    }
+
+    /// Swap between coins 0 and 1, `trade_coins` reaches the others
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let (i, j) = if from0 { (0, 1) } else { (1, 0) };
+        let (dy, fee) = self.exchange(i, j, amount_in)?;
+        Ok(Trade {
+            fee: fee_pips(fee),
+            token0: self.coins[0],
+            token1: self.coins[1],
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out: dy,
+            amount_in_net: amount_in,
+            amount_out_net: dy,
+        })
+    }
+}
+
+/// Fee over `FEE_DENOMINATOR` in millionths, the unit `Trade` reports
+fn fee_pips(fee: U256) -> U24 {
+    U24::from(fee / U256::from(FEE_DENOMINATOR / 1_000_000))
+}
@@ -0,0 +1,161 @@
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, U256};
+
+use crate::ICurveCryptoSwap::ICurveCryptoSwapInstance;
+use crate::curve_crypto_pool_sim::{CurveCryptoPoolSim, newton_d};
+use crate::curve_pool_src::{NATIVE_COIN, optional};
+use crate::err::PoolError;
+use crate::v3_pool_src::Rpc;
+use crate::{ICurveTriCrypto, IERC20Decimals};
+
+type CryptoContract = ICurveCryptoSwapInstance<Rpc>;
+
+/// A Curve CryptoSwap pool, twocrypto or tricrypto, v1 or `-ng`.
+///
+/// Balances are kept in coin 0's units through `price_scale`, which the pool
+/// repegs towards its price oracle as it trades.
+#[derive(Debug)]
+pub struct CurveCryptoPoolSrc {
+    pub address: Address,
+    pub coins: Vec<Address>,
+    /// `10**(18 - decimals)` per coin
+    pub precisions: Vec<U256>,
+    pub balances: Vec<U256>,
+    pub price_scale: Vec<U256>,
+    /// `A * N**N * A_MULTIPLIER`
+    pub ann: U256,
+    pub gamma: U256,
+    pub d: U256,
+    pub mid_fee: U256,
+    pub out_fee: U256,
+    pub fee_gamma: U256,
+    /// Solves with the `-ng` math, told apart by the `MATH` getter only
+    /// those pools have
+    pub ng: bool,
+    pub contract: CryptoContract,
+}
+
+impl CurveCryptoPoolSrc {
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(address, provider, BlockId::latest()).await
+    }
+
+    /// Load coins, decimals and pool state as of `block`
+    pub async fn new_at(
+        address: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = ICurveCryptoSwapInstance::new(address, provider.clone());
+
+        let mut coins = Vec::new();
+        while coins.len() < 3 {
+            let i = U256::from(coins.len());
+            match optional(contract.coins(i).call().block(block)).await? {
+                Some(coin) => coins.push(coin),
+                None => break,
+            }
+        }
+        if coins.len() < 2 {
+            return Err(PoolError::Unsupported {
+                pool: address,
+                reason: "not a CryptoSwap pool".into(),
+            });
+        }
+
+        let mut precisions = Vec::with_capacity(coins.len());
+        for coin in &coins {
+            let decimals = match *coin {
+                NATIVE_COIN => 18,
+                coin => {
+                    IERC20Decimals::new(coin, provider.clone())
+                        .decimals()
+                        .call()
+                        .block(block)
+                        .await?
+                }
+            };
+            precisions.push(U256::from(10).pow(U256::from(18 - decimals.min(18) as u64)));
+        }
+
+        let ng = optional(contract.MATH().call().block(block))
+            .await?
+            .is_some();
+
+        let mut instance = Self {
+            address,
+            balances: vec![U256::ZERO; coins.len()],
+            price_scale: vec![U256::ZERO; coins.len() - 1],
+            coins,
+            precisions,
+            ann: U256::ZERO,
+            gamma: U256::ZERO,
+            d: U256::ZERO,
+            mid_fee: U256::ZERO,
+            out_fee: U256::ZERO,
+            fee_gamma: U256::ZERO,
+            ng,
+            contract,
+        };
+        instance.update_at(block).await?;
+        Ok(instance)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
+    }
+
+    /// Reload balances, prices, `A`, `gamma`, `D` and fees as of `block`
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let contract = &self.contract;
+        for (i, balance) in self.balances.iter_mut().enumerate() {
+            *balance = contract.balances(U256::from(i)).call().block(block).await?;
+        }
+        if self.coins.len() == 2 {
+            self.price_scale[0] = contract.price_scale().call().block(block).await?;
+        } else {
+            let tricrypto = ICurveTriCrypto::new(self.address, contract.provider().clone());
+            for (k, price) in self.price_scale.iter_mut().enumerate() {
+                *price = tricrypto
+                    .price_scale(U256::from(k))
+                    .call()
+                    .block(block)
+                    .await?;
+            }
+        }
+
+        self.ann = contract.A().call().block(block).await?;
+        self.gamma = contract.gamma().call().block(block).await?;
+        self.d = contract.D().call().block(block).await?;
+        self.mid_fee = contract.mid_fee().call().block(block).await?;
+        self.out_fee = contract.out_fee().call().block(block).await?;
+        self.fee_gamma = contract.fee_gamma().call().block(block).await?;
+
+        // once `A` and `gamma` have ramped the stored `D` can be stale, the
+        // pool solves for it again whenever `future_A_gamma_time` is set,
+        // which stays 1 after a ramp ends
+        let ramp = contract.future_A_gamma_time().call().block(block).await?;
+        if !ramp.is_zero() {
+            let sim = self.into_sim();
+            self.d = newton_d(self.ann, self.gamma, &sim.xp(&self.balances)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_sim(&self) -> CurveCryptoPoolSim {
+        CurveCryptoPoolSim {
+            address: self.address,
+            coins: self.coins.clone(),
+            balances: self.balances.clone(),
+            precisions: self.precisions.clone(),
+            price_scale: self.price_scale.clone(),
+            ann: self.ann,
+            gamma: self.gamma,
+            d: self.d,
+            mid_fee: self.mid_fee,
+            out_fee: self.out_fee,
+            fee_gamma: self.fee_gamma,
+            ng: self.ng,
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Curve StableSwap math, ported line by line from the Vyper pools so that
+//! quotes match `get_dy` to the wei, rounding and all.
+
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, U256};
+
+use crate::err::{MathError, PoolError};
+use crate::trade::Trade;
+
+/// `A` is stored times this in pools with `A_precise`
+pub const A_PRECISION: u64 = 100;
+/// Scale of `fee` and `admin_fee`
+pub const FEE_DENOMINATOR: u64 = 10_000_000_000;
+pub const PRECISION: u128 = 1_000_000_000_000_000_000;
+
+/// Rounds of Newton iteration before the contract gives up
+const MAX_ROUNDS: usize = 255;
+
+pub(crate) fn mul(a: U256, b: U256, op: &'static str) -> Result<U256, MathError> {
+    a.checked_mul(b).ok_or_else(|| MathError::overflow(op))
+}
+
+pub(crate) fn div(a: U256, b: U256, op: &'static str) -> Result<U256, MathError> {
+    a.checked_div(b).ok_or_else(|| MathError::overflow(op))
+}
+
+pub(crate) fn add(a: U256, b: U256, op: &'static str) -> Result<U256, MathError> {
+    a.checked_add(b).ok_or_else(|| MathError::overflow(op))
+}
+
+pub(crate) fn sub(a: U256, b: U256, op: &'static str) -> Result<U256, MathError> {
+    a.checked_sub(b).ok_or_else(|| MathError::overflow(op))
+}
+
+/// The invariant `D` of balances `xp` scaled to 18 decimals, `amp` is
+/// `A * A_PRECISION`
+pub fn get_d(xp: &[U256], amp: U256) -> Result<U256, MathError> {
+    const OP: &str = "get_D";
+    let n = U256::from(xp.len());
+    let a_precision = U256::from(A_PRECISION);
+
+    let mut s = U256::ZERO;
+    for x in xp {
+        s = add(s, *x, OP)?;
+    }
+    if s.is_zero() {
+        return Ok(U256::ZERO);
+    }
+
+    let mut d = s;
+    let ann = mul(amp, n, OP)?;
+    for _ in 0..MAX_ROUNDS {
+        let mut d_p = d;
+        for x in xp {
+            d_p = div(mul(d_p, d, OP)?, mul(*x, n, OP)?, OP)?;
+        }
+        let d_prev = d;
+        let numerator = mul(
+            add(
+                div(mul(ann, s, OP)?, a_precision, OP)?,
+                mul(d_p, n, OP)?,
+                OP,
+            )?,
+            d,
+            OP,
+        )?;
+        let denominator = add(
+            div(mul(sub(ann, a_precision, OP)?, d, OP)?, a_precision, OP)?,
+            mul(n + U256::ONE, d_p, OP)?,
+            OP,
+        )?;
+        d = div(numerator, denominator, OP)?;
+        if d.abs_diff(d_prev) <= U256::ONE {
+            return Ok(d);
+        }
+    }
+    Err(MathError::NoConvergence { op: OP })
+}
+
+/// Balance of coin `j` that keeps `D` when coin `i` is at `x`
+pub fn get_y(i: usize, j: usize, x: U256, xp: &[U256], amp: U256) -> Result<U256, MathError> {
+    const OP: &str = "get_y";
+    let n = U256::from(xp.len());
+    let a_precision = U256::from(A_PRECISION);
+
+    let d = get_d(xp, amp)?;
+    let ann = mul(amp, n, OP)?;
+    let mut c = d;
+    let mut s = U256::ZERO;
+    for (k, balance) in xp.iter().enumerate() {
+        let x = if k == i {
+            x
+        } else if k != j {
+            *balance
+        } else {
+            continue;
+        };
+        s = add(s, x, OP)?;
+        c = div(mul(c, d, OP)?, mul(x, n, OP)?, OP)?;
+    }
+    c = div(mul(mul(c, d, OP)?, a_precision, OP)?, mul(ann, n, OP)?, OP)?;
+    let b = add(s, div(mul(d, a_precision, OP)?, ann, OP)?, OP)?;
+
+    let mut y = d;
+    for _ in 0..MAX_ROUNDS {
+        let y_prev = y;
+        let numerator = add(mul(y, y, OP)?, c, OP)?;
+        let denominator = sub(add(mul(U256::from(2), y, OP)?, b, OP)?, d, OP)?;
+        y = div(numerator, denominator, OP)?;
+        if y.abs_diff(y_prev) <= U256::ONE {
+            return Ok(y);
+        }
+    }
+    Err(MathError::NoConvergence { op: OP })
+}
+
+/// Offline copy of a Curve StableSwap pool, plain or meta.
+#[derive(Debug, Clone)]
+pub struct CurvePoolSim {
+    pub address: Address,
+    pub coins: Vec<Address>,
+    pub balances: Vec<U256>,
+    /// Per coin multiplier to 18 decimals times 1e18, `10**(36 - decimals)`,
+    /// for the LP coin of a meta pool the base pool's virtual price
+    pub rates: Vec<U256>,
+    /// `A * A_PRECISION`
+    pub amp: U256,
+    /// Over `FEE_DENOMINATOR`
+    pub fee: U256,
+    /// Share of the fee that leaves the balances, over `FEE_DENOMINATOR`
+    pub admin_fee: U256,
+}
+
+impl CurvePoolSim {
+    pub fn index_of(&self, coin: &Address) -> Option<usize> {
+        self.coins.iter().position(|c| c == coin)
+    }
+
+    /// Balances scaled to 18 decimals
+    pub fn xp(&self) -> Result<Vec<U256>, MathError> {
+        self.rates
+            .iter()
+            .zip(&self.balances)
+            .map(|(rate, balance)| div(mul(*rate, *balance, "xp")?, U256::from(PRECISION), "xp"))
+            .collect()
+    }
+
+    fn check_coins(&self, i: usize, j: usize) -> Result<(), PoolError> {
+        if i == j || i >= self.coins.len() || j >= self.coins.len() {
+            return Err(PoolError::Unsupported {
+                pool: self.address,
+                reason: format!("no swap from coin {i} to coin {j}"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Output and admin fee of swapping `dx` of coin `i` for coin `j`, both
+    /// in coin `j`'s decimals
+    fn quote(&self, i: usize, j: usize, dx: U256) -> Result<(U256, U256), PoolError> {
+        const OP: &str = "get_dy";
+        self.check_coins(i, j)?;
+        let precision = U256::from(PRECISION);
+        let fee_denominator = U256::from(FEE_DENOMINATOR);
+
+        let xp = self.xp()?;
+        let x = add(xp[i], div(mul(dx, self.rates[i], OP)?, precision, OP)?, OP)?;
+        let y = get_y(i, j, x, &xp, self.amp)?;
+        let dy = sub(xp[j], y, OP)?
+            .checked_sub(U256::ONE)
+            .ok_or(PoolError::NoLiquidity { pool: self.address })?;
+        let dy_fee = div(mul(dy, self.fee, OP)?, fee_denominator, OP)?;
+        let out = div(mul(dy - dy_fee, precision, OP)?, self.rates[j], OP)?;
+        let admin_fee = div(mul(dy_fee, self.admin_fee, OP)?, fee_denominator, OP)?;
+        let admin_fee = div(mul(admin_fee, precision, OP)?, self.rates[j], OP)?;
+        Ok((out, admin_fee))
+    }
+
+    /// The pool's `get_dy(i, j, dx)`
+    pub fn get_dy(&self, i: usize, j: usize, dx: U256) -> Result<U256, PoolError> {
+        Ok(self.quote(i, j, dx)?.0)
+    }
+
+    /// The pool's `exchange(i, j, dx, 0)`, the admin fee leaves the balances
+    pub fn exchange(&mut self, i: usize, j: usize, dx: U256) -> Result<U256, PoolError> {
+        let (dy, admin_fee) = self.quote(i, j, dx)?;
+        self.balances[i] = add(self.balances[i], dx, "exchange")?;
+        self.balances[j] = sub(
+            self.balances[j],
+            add(dy, admin_fee, "exchange")?,
+            "exchange",
+        )?;
+        Ok(dy)
+    }
+
+    /// Swap between any two coins, reported with coin `i` as token0
+    pub fn trade_coins(&mut self, i: usize, j: usize, dx: U256) -> Result<Trade, PoolError> {
+        let dy = self.exchange(i, j, dx)?;
+        Ok(Trade {
+            fee: self.fee_pips(),
+            token0: self.coins[i],
+            token1: self.coins[j],
+            pool: self.address,
+            from0: true,
+            amount_in: dx,
+            amount_out: dy,
+            amount_in_net: dx,
+            amount_out_net: dy,
+        })
+    }
+
+    /// Swap between coins 0 and 1, `trade_coins` reaches the others
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let (i, j) = if from0 { (0, 1) } else { (1, 0) };
+        let dy = self.exchange(i, j, amount_in)?;
+        Ok(Trade {
+            fee: self.fee_pips(),
+            token0: self.coins[0],
+            token1: self.coins[1],
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out: dy,
+            amount_in_net: amount_in,
+            amount_out_net: dy,
+        })
+    }
+
+    /// Fee in millionths, the unit `Trade` reports fees in
+    pub fn fee_pips(&self) -> U24 {
+        U24::from(self.fee / U256::from(FEE_DENOMINATOR / 1_000_000))
+    }
+}
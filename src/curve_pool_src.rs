@@ -0,0 +1,161 @@
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, U256};
+
+use crate::ICurveStableSwap::ICurveStableSwapInstance;
+use crate::IERC20Decimals;
+use crate::curve_pool_sim::{A_PRECISION, CurvePoolSim};
+use crate::err::PoolError;
+use crate::v3_pool_src::Rpc;
+
+type CurveContract = ICurveStableSwapInstance<Rpc>;
+
+pub use crate::currency::NATIVE_COIN;
+
+/// No StableSwap pool holds more coins
+const MAX_COINS: usize = 8;
+
+/// `None` when the call reverts or returns nothing, as getters the pool
+/// doesn't have do
+pub(crate) async fn optional<T>(
+    call: impl IntoFuture<Output = Result<T, alloy::contract::Error>>,
+) -> Result<Option<T>, PoolError> {
+    match call.await {
+        Ok(value) => Ok(Some(value)),
+        Err(alloy::contract::Error::TransportError(e)) if e.is_error_resp() => Ok(None),
+        Err(alloy::contract::Error::ZeroData(..)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A Curve StableSwap pool, plain or meta, with `uint256` coin indices.
+///
+/// A meta pool pairs coins with the LP token of a base pool, the LP token is
+/// its last coin and is priced at the base pool's virtual price.
+#[derive(Debug)]
+pub struct CurvePoolSrc {
+    pub address: Address,
+    pub coins: Vec<Address>,
+    /// `10**(36 - decimals)` per coin, the rates of pools without `stored_rates`
+    pub precisions: Vec<U256>,
+    pub base_pool: Option<Address>,
+    pub balances: Vec<U256>,
+    pub rates: Vec<U256>,
+    /// `A * A_PRECISION`
+    pub amp: U256,
+    pub fee: U256,
+    pub admin_fee: U256,
+    pub contract: CurveContract,
+}
+
+impl CurvePoolSrc {
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(address, provider, BlockId::latest()).await
+    }
+
+    /// Load coins, decimals and pool state as of `block`
+    pub async fn new_at(
+        address: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = ICurveStableSwapInstance::new(address, provider.clone());
+
+        let mut coins = Vec::new();
+        while coins.len() < MAX_COINS {
+            let i = U256::from(coins.len());
+            match optional(contract.coins(i).call().block(block)).await? {
+                Some(coin) => coins.push(coin),
+                None => break,
+            }
+        }
+        if coins.len() < 2 {
+            return Err(PoolError::Unsupported {
+                pool: address,
+                reason: "not a StableSwap pool with uint256 coin indices".into(),
+            });
+        }
+
+        let mut precisions = Vec::with_capacity(coins.len());
+        for coin in &coins {
+            let decimals = match *coin {
+                NATIVE_COIN => 18,
+                coin => {
+                    IERC20Decimals::new(coin, provider.clone())
+                        .decimals()
+                        .call()
+                        .block(block)
+                        .await?
+                }
+            };
+            precisions.push(U256::from(10).pow(U256::from(36 - decimals.min(36) as u64)));
+        }
+        let base_pool = optional(contract.base_pool().call().block(block))
+            .await?
+            .filter(|pool| !pool.is_zero());
+
+        let mut instance = Self {
+            address,
+            rates: precisions.clone(),
+            balances: vec![U256::ZERO; coins.len()],
+            coins,
+            precisions,
+            base_pool,
+            amp: U256::ZERO,
+            fee: U256::ZERO,
+            admin_fee: U256::ZERO,
+            contract,
+        };
+        instance.update_at(block).await?;
+        Ok(instance)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
+    }
+
+    /// Reload balances, `A`, fees and rates as of `block`
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let contract = &self.contract;
+        for (i, balance) in self.balances.iter_mut().enumerate() {
+            *balance = contract.balances(U256::from(i)).call().block(block).await?;
+        }
+
+        self.amp = match optional(contract.A_precise().call().block(block)).await? {
+            Some(amp) => amp,
+            None => contract.A().call().block(block).await? * U256::from(A_PRECISION),
+        };
+        self.fee = contract.fee().call().block(block).await?;
+        self.admin_fee = optional(contract.admin_fee().call().block(block))
+            .await?
+            .unwrap_or_default();
+
+        self.rates = match optional(contract.stored_rates().call().block(block)).await? {
+            Some(rates) if rates.len() == self.coins.len() => rates,
+            _ => {
+                let mut rates = self.precisions.clone();
+                if let Some(base_pool) = self.base_pool {
+                    let base =
+                        ICurveStableSwapInstance::new(base_pool, contract.provider().clone());
+                    let virtual_price = base.get_virtual_price().call().block(block).await?;
+                    // the LP token has 18 decimals, its rate is the price alone
+                    let last = rates.len() - 1;
+                    rates[last] = virtual_price;
+                }
+                rates
+            }
+        };
+        Ok(())
+    }
+
+    pub fn into_sim(&self) -> CurvePoolSim {
+        CurvePoolSim {
+            address: self.address,
+            coins: self.coins.clone(),
+            balances: self.balances.clone(),
+            rates: self.rates.clone(),
+            amp: self.amp,
+            fee: self.fee,
+            admin_fee: self.admin_fee,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! ERC-4626 vaults as pools between the asset (token0) and the vault's
+//! shares (token1), depositing one way and redeeming the other.
+//!
+//! Quotes follow `previewDeposit` and `previewRedeem` from `totalAssets`
+//! and `totalSupply`, both rounding down in the vault's favour.
+
+use alloy::primitives::aliases::U24;
+use alloy::primitives::ruint::UintTryFrom;
+use alloy::primitives::{Address, U256, U512};
+
+use crate::curve_pool_sim::{add, sub};
+use crate::err::{MathError, PoolError};
+use crate::trade::Trade;
+
+/// How a vault converts between assets and shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultMath {
+    /// OpenZeppelin's, `10**decimalsOffset` virtual shares and one virtual
+    /// asset keep an empty vault priced
+    Virtual { shares: U256 },
+    /// Solmate's, one share per asset while the vault is empty
+    Plain,
+}
+
+/// `x * y / denominator` rounded down, without overflow in between
+fn mul_div(x: U256, y: U256, denominator: U256, op: &'static str) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::overflow(op));
+    }
+    let result = U512::from(x) * U512::from(y) / U512::from(denominator);
+    U256::uint_try_from(result).map_err(|_| MathError::overflow(op))
+}
+
+impl VaultMath {
+    /// `_convertToShares` rounding down
+    pub fn to_shares(
+        &self,
+        assets: U256,
+        total_assets: U256,
+        total_supply: U256,
+    ) -> Result<U256, MathError> {
+        let op = "vault shares";
+        match self {
+            Self::Virtual { shares } => mul_div(
+                assets,
+                add(total_supply, *shares, op)?,
+                add(total_assets, U256::ONE, op)?,
+                op,
+            ),
+            Self::Plain if total_supply.is_zero() => Ok(assets),
+            Self::Plain => mul_div(assets, total_supply, total_assets, op),
+        }
+    }
+
+    /// `_convertToAssets` rounding down
+    pub fn to_assets(
+        &self,
+        shares: U256,
+        total_assets: U256,
+        total_supply: U256,
+    ) -> Result<U256, MathError> {
+        let op = "vault assets";
+        match self {
+            Self::Virtual {
+                shares: virtual_shares,
+            } => mul_div(
+                shares,
+                add(total_assets, U256::ONE, op)?,
+                add(total_supply, *virtual_shares, op)?,
+                op,
+            ),
+            Self::Plain if total_supply.is_zero() => Ok(shares),
+            Self::Plain => mul_div(shares, total_assets, total_supply, op),
+        }
+    }
+}
+
+/// Offline copy of an ERC-4626 vault, keyed by the vault's address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Erc4626Sim {
+    pub address: Address,
+    pub asset: Address,
+    pub total_assets: U256,
+    pub total_supply: U256,
+    pub math: VaultMath,
+}
+
+impl Erc4626Sim {
+    /// Shares minted for `assets`, `previewDeposit`
+    pub fn preview_deposit(&self, assets: U256) -> Result<U256, MathError> {
+        self.math
+            .to_shares(assets, self.total_assets, self.total_supply)
+    }
+
+    /// Assets paid out for `shares`, `previewRedeem`
+    pub fn preview_redeem(&self, shares: U256) -> Result<U256, MathError> {
+        self.math
+            .to_assets(shares, self.total_assets, self.total_supply)
+    }
+
+    /// Deposit assets when `from0`, otherwise redeem shares
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let op = "vault totals";
+        let amount_out = if from0 {
+            let shares = self.preview_deposit(amount_in)?;
+            self.total_assets = add(self.total_assets, amount_in, op)?;
+            self.total_supply = add(self.total_supply, shares, op)?;
+            shares
+        } else {
+            if amount_in > self.total_supply {
+                return Err(PoolError::NoLiquidity { pool: self.address });
+            }
+            let assets = self.preview_redeem(amount_in)?;
+            self.total_assets = sub(self.total_assets, assets, op)?;
+            self.total_supply -= amount_in;
+            assets
+        };
+        Ok(Trade {
+            fee: U24::ZERO,
+            token0: self.asset,
+            token1: self.address,
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out,
+            amount_in_net: amount_in,
+            amount_out_net: amount_out,
+        })
+    }
+}
@@ -0,0 +1,111 @@
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, U256};
+
+use crate::IERC20Decimals;
+use crate::IERC4626::IERC4626Instance;
+use crate::erc4626_sim::{Erc4626Sim, VaultMath};
+use crate::err::PoolError;
+use crate::v3_pool_src::Rpc;
+
+type VaultContract = IERC4626Instance<Rpc>;
+
+/// An ERC-4626 vault with the totals its previews are computed from.
+///
+/// Which conversion the vault runs is told by checking both against its own
+/// `previewDeposit` and `previewRedeem`, vaults charging fees or pricing
+/// shares some other way match neither and aren't supported.
+#[derive(Debug)]
+pub struct Erc4626Src {
+    pub address: Address,
+    pub asset: Address,
+    pub total_assets: U256,
+    pub total_supply: U256,
+    pub math: VaultMath,
+    pub contract: VaultContract,
+}
+
+impl Erc4626Src {
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(address, provider, BlockId::latest()).await
+    }
+
+    /// Load the vault and tell its conversion as of `block`
+    pub async fn new_at(
+        address: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = IERC4626Instance::new(address, provider.clone());
+        let asset = contract.asset().call().block(block).await?;
+
+        let mut instance = Self {
+            address,
+            asset,
+            total_assets: U256::ZERO,
+            total_supply: U256::ZERO,
+            math: VaultMath::Plain,
+            contract,
+        };
+        instance.update_at(block).await?;
+        instance.math = instance.detect_math(provider, block).await?;
+        Ok(instance)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
+    }
+
+    /// Reload the totals as of `block`
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        self.total_assets = self.contract.totalAssets().call().block(block).await?;
+        self.total_supply = self.contract.totalSupply().call().block(block).await?;
+        Ok(())
+    }
+
+    /// OpenZeppelin's conversion with the offset between the share and
+    /// asset decimals, else Solmate's, whichever reproduces both previews.
+    /// The probes are a whole unit or the totals, at those the two differ
+    /// once shares are worth more than an asset
+    async fn detect_math(&self, provider: Rpc, block: BlockId) -> Result<VaultMath, PoolError> {
+        let contract = &self.contract;
+        let share_decimals = contract.decimals().call().block(block).await?;
+        let asset_decimals = IERC20Decimals::new(self.asset, provider)
+            .decimals()
+            .call()
+            .block(block)
+            .await?;
+        let unit = |decimals: u8| U256::from(10).pow(U256::from(decimals));
+        let assets = self.total_assets.max(unit(asset_decimals));
+        let shares = self.total_supply.max(unit(share_decimals));
+        let deposited = contract.previewDeposit(assets).call().block(block).await?;
+        let redeemed = contract.previewRedeem(shares).call().block(block).await?;
+
+        let mut candidates = vec![VaultMath::Plain];
+        if let Some(offset) = share_decimals.checked_sub(asset_decimals) {
+            let shares = unit(offset);
+            candidates.insert(0, VaultMath::Virtual { shares });
+        }
+        let (total_assets, total_supply) = (self.total_assets, self.total_supply);
+        for math in candidates {
+            if math.to_shares(assets, total_assets, total_supply).ok() == Some(deposited)
+                && math.to_assets(shares, total_assets, total_supply).ok() == Some(redeemed)
+            {
+                return Ok(math);
+            }
+        }
+        Err(PoolError::Unsupported {
+            pool: self.address,
+            reason: "previews don't follow totalAssets and totalSupply".to_string(),
+        })
+    }
+
+    pub fn into_sim(&self) -> Erc4626Sim {
+        Erc4626Sim {
+            address: self.address,
+            asset: self.asset,
+            total_assets: self.total_assets,
+            total_supply: self.total_supply,
+            math: self.math,
+        }
+    }
+}
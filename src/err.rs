@@ -1,5 +1,119 @@
-// 1. Declare a unified error type:
-#[derive(Debug,)]
-pub enum PoolUpdateError {
-    Error,    
+//! Errors of pool sources and simulations, matchable on why a pool failed
+//! to load or a swap failed.
+
+use std::fmt;
+
+use alloy::primitives::aliases::I24;
+use alloy::primitives::{Address, U256};
+use alloy::transports::TransportError;
+
+/// Failure of the fixed point math in `tick_math` and the simulations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathError {
+    /// Checked arithmetic in `op` overflowed, underflowed or divided by zero
+    Overflow { op: &'static str },
+    /// Tick outside `±887272`
+    TickOutOfRange(I24),
+    /// Sqrt price outside `[MIN_SQRT_RATIO, MAX_SQRT_RATIO)`
+    PriceOutOfBounds { price: U256, min: U256, max: U256 },
+    /// Newton iteration in `op` didn't settle within its rounds, the
+    /// contract would revert too
+    NoConvergence { op: &'static str },
+    /// Input or result outside the range `op` asserts on
+    Unsafe { op: &'static str },
+}
+
+impl MathError {
+    pub fn overflow(op: &'static str) -> Self {
+        Self::Overflow { op }
+    }
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow { op } => write!(f, "arithmetic overflow in {op}"),
+            Self::TickOutOfRange(tick) => write!(f, "tick {tick} outside ±887272"),
+            Self::PriceOutOfBounds { price, min, max } => {
+                write!(f, "sqrt price {price} outside [{min}, {max})")
+            }
+            Self::NoConvergence { op } => write!(f, "{op} did not converge"),
+            Self::Unsafe { op } => write!(f, "unsafe value in {op}"),
+        }
+    }
+}
+
+impl std::error::Error for MathError {}
+
+#[derive(Debug)]
+pub enum PoolError {
+    /// Transport failure or JSON-RPC error response
+    Rpc(TransportError),
+    /// Return data or a log that doesn't decode as expected
+    Decode(String),
+    /// A tick the swap needs was loaded without its liquidity
+    MissingTick { pool: Address, tick: I24 },
+    /// The swap ran past the last loaded tick
+    OutOfTicks { pool: Address, from0: bool },
+    /// Nothing to swap against, or less than the output asked for
+    NoLiquidity { pool: Address },
+    Math(MathError),
+    /// The address isn't a pool this source understands
+    Unsupported { pool: Address, reason: String },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rpc(e) => write!(f, "rpc error: {e}"),
+            Self::Decode(e) => write!(f, "decode error: {e}"),
+            Self::MissingTick { pool, tick } => {
+                write!(f, "tick {tick} of {pool} has no liquidity loaded")
+            }
+            Self::OutOfTicks { pool, from0 } => {
+                let side = if *from0 { "above" } else { "below" };
+                write!(f, "swap ran out of loaded ticks {side} the price of {pool}")
+            }
+            Self::NoLiquidity { pool } => write!(f, "{pool} has no liquidity"),
+            Self::Math(e) => write!(f, "{e}"),
+            Self::Unsupported { pool, reason } => write!(f, "unsupported pool {pool}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Rpc(e) => Some(e),
+            Self::Math(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TransportError> for PoolError {
+    fn from(e: TransportError) -> Self {
+        Self::Rpc(e)
+    }
+}
+
+impl From<MathError> for PoolError {
+    fn from(e: MathError) -> Self {
+        Self::Math(e)
+    }
+}
+
+impl From<alloy::sol_types::Error> for PoolError {
+    fn from(e: alloy::sol_types::Error) -> Self {
+        Self::Decode(e.to_string())
+    }
+}
+
+impl From<alloy::contract::Error> for PoolError {
+    fn from(e: alloy::contract::Error) -> Self {
+        match e {
+            alloy::contract::Error::TransportError(e) => Self::Rpc(e),
+            e => Self::Decode(e.to_string()),
+        }
+    }
 }
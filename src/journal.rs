@@ -0,0 +1,132 @@
+//! Per-block journal of pool states so reorged blocks can be unwound.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use alloy::primitives::{Address, B256};
+
+use crate::v_pool_sim::AnyPoolSim;
+
+/// How many blocks back a reorg is still expected on a chain
+pub fn reorg_depth(chain_id: u64) -> u64 {
+    match chain_id {
+        // two epochs, anything deeper is finalized
+        1 => 64,
+        // one full validator turn before fast finality kicks in
+        56 => 21,
+        137 => 128,
+        // sequencer reorgs are rare and shallow
+        10 | 8453 | 42161 => 16,
+        _ => 64,
+    }
+}
+
+/// Pool states as they were before a block touched them
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub number: u64,
+    pub hash: B256,
+    pub prior: HashMap<Address, AnyPoolSim>,
+    /// Pools refetched from chain at this block, their prior state is meaningless
+    pub refetched: HashSet<Address>,
+}
+
+impl JournalEntry {
+    fn new(number: u64, hash: B256) -> Self {
+        Self {
+            number,
+            hash,
+            prior: HashMap::new(),
+            refetched: HashSet::new(),
+        }
+    }
+}
+
+/// Ring of the last `depth` blocks, oldest first
+#[derive(Debug, Clone)]
+pub struct StateJournal {
+    depth: u64,
+    entries: VecDeque<JournalEntry>,
+}
+
+impl StateJournal {
+    pub fn new(depth: u64) -> Self {
+        Self {
+            depth,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn for_chain(chain_id: u64) -> Self {
+        Self::new(reorg_depth(chain_id))
+    }
+
+    pub fn depth(&self) -> u64 {
+        self.depth
+    }
+
+    fn entry(&mut self, number: u64, hash: B256) -> &mut JournalEntry {
+        let newest = self.entries.back().map(|e| e.number);
+        if newest != Some(number) {
+            self.entries.push_back(JournalEntry::new(number, hash));
+        }
+        self.entries.back_mut().unwrap()
+    }
+
+    /// Remember the hash of a block even if nothing changed in it
+    pub fn mark(&mut self, number: u64, hash: B256) {
+        self.entry(number, hash);
+    }
+
+    /// Record `pool` as it was before its first change in block `number`
+    pub fn record(&mut self, number: u64, hash: B256, address: Address, pool: &AnyPoolSim) {
+        self.entry(number, hash)
+            .prior
+            .entry(address)
+            .or_insert_with(|| pool.clone());
+    }
+
+    pub fn record_refetch(&mut self, number: u64, hash: B256, address: Address) {
+        self.entry(number, hash).refetched.insert(address);
+    }
+
+    /// Drop entries that fell out of the reorg window, the newest one always stays
+    pub fn prune(&mut self, head: u64) {
+        while self.entries.len() > 1
+            && self
+                .entries
+                .front()
+                .is_some_and(|e| e.number + self.depth <= head)
+        {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn hash_at(&self, number: u64) -> Option<B256> {
+        self.entries
+            .iter()
+            .find(|e| e.number == number)
+            .map(|e| e.hash)
+    }
+
+    /// Journaled blocks, newest first
+    pub fn blocks(&self) -> Vec<(u64, B256)> {
+        self.entries
+            .iter()
+            .rev()
+            .map(|e| (e.number, e.hash))
+            .collect()
+    }
+
+    /// Remove every entry after `ancestor`, newest first, for the caller to undo
+    pub fn rollback(&mut self, ancestor: u64) -> Vec<JournalEntry> {
+        let mut undone = Vec::new();
+        while self.entries.back().is_some_and(|e| e.number > ancestor) {
+            undone.extend(self.entries.pop_back());
+        }
+        undone
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,410 @@
+//! Liquidity Book math, ported from the v2.1 `LBPair`, `BinHelper`,
+//! `FeeHelper` and `PairParameterHelper` so quotes match `getSwapOut` to
+//! the wei.
+//!
+//! Prices are 128.128 fixed point, `(1 + binStep / 10_000)^(id - 2^23)`.
+//! Bins below the active one hold only Y, bins above only X.
+
+use std::collections::BTreeMap;
+
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, B256, U256, U512};
+
+use crate::err::{MathError, PoolError};
+use crate::trade::Trade;
+
+pub const SCALE_OFFSET: usize = 128;
+pub const BASIS_POINT_MAX: u64 = 10_000;
+/// Id of the bin priced at exactly 1
+pub const REAL_ID_SHIFT: i64 = 1 << 23;
+/// Scale of fees
+pub const PRECISION: u64 = 1_000_000_000_000_000_000;
+
+fn scale() -> U256 {
+    U256::ONE << SCALE_OFFSET
+}
+
+/// `x^y` of a 128.128 number, `Uint128x128Math.pow` with its inversions so
+/// the rounding is the same
+pub fn pow(x: U256, y: i64) -> Result<U256, MathError> {
+    if y == 0 {
+        return Ok(scale());
+    }
+    let mut invert = y < 0;
+    let abs_y = y.unsigned_abs();
+    let mut result = U256::ZERO;
+    if abs_y < 0x100000 {
+        result = scale();
+        let mut squared = x;
+        if x > U256::from(u128::MAX) {
+            squared = U256::MAX / squared;
+            invert = !invert;
+        }
+        for bit in 0..20 {
+            if abs_y & (1 << bit) != 0 {
+                result = (result * squared) >> 128;
+            }
+            squared = (squared * squared) >> 128;
+        }
+    }
+    if result.is_zero() {
+        return Err(MathError::Unsafe { op: "lb pow" });
+    }
+    Ok(if invert { U256::MAX / result } else { result })
+}
+
+/// Price of bin `id` in Y per X, 128.128
+pub fn price_from_id(id: u32, bin_step: u16) -> Result<U256, MathError> {
+    let base = scale() + (U256::from(bin_step) << SCALE_OFFSET) / U256::from(BASIS_POINT_MAX);
+    pow(base, id as i64 - REAL_ID_SHIFT)
+}
+
+/// `x * y >> 128`, rounded up when `round_up`
+fn mul_shift(x: U256, y: U256, round_up: bool) -> U256 {
+    let product = U512::from(x) * U512::from(y);
+    let mut result = product >> SCALE_OFFSET;
+    if round_up && product != result << SCALE_OFFSET {
+        result += U512::ONE;
+    }
+    U256::from(result)
+}
+
+/// `(x << 128) / denominator`, rounded up when `round_up`
+fn shift_div(x: U256, denominator: U256, round_up: bool) -> U256 {
+    let numerator = U512::from(x) << SCALE_OFFSET;
+    let denominator = U512::from(denominator);
+    let mut result = numerator / denominator;
+    if round_up && numerator % denominator != U512::ZERO {
+        result += U512::ONE;
+    }
+    U256::from(result)
+}
+
+/// `[x, y]` of amounts packed in a `bytes32`, X in the low 128 bits
+pub fn decode_amounts(amounts: B256) -> [U256; 2] {
+    let packed = U256::from_be_bytes(amounts.0);
+    [packed & U256::from(u128::MAX), packed >> 128]
+}
+
+fn safe128(x: U256, op: &'static str) -> Result<U256, MathError> {
+    if x > U256::from(u128::MAX) {
+        return Err(MathError::overflow(op));
+    }
+    Ok(x)
+}
+
+/// Fee and volatility state of a pair, the static and variable parameters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LbFeeParameters {
+    pub base_factor: u16,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub variable_fee_control: u32,
+    /// Basis points of the fees going to the protocol
+    pub protocol_share: u16,
+    pub max_volatility_accumulator: u32,
+    pub volatility_accumulator: u32,
+    pub volatility_reference: u32,
+    pub id_reference: u32,
+    pub time_of_last_update: u64,
+}
+
+impl LbFeeParameters {
+    /// Decay the volatility since the last update, at the start of a swap
+    pub fn update_references(&mut self, active_id: u32, timestamp: u64) {
+        let dt = timestamp.saturating_sub(self.time_of_last_update);
+        if dt >= self.filter_period as u64 {
+            self.id_reference = active_id;
+            self.volatility_reference = if dt < self.decay_period as u64 {
+                (self.volatility_accumulator as u64 * self.reduction_factor as u64
+                    / BASIS_POINT_MAX) as u32
+            } else {
+                0
+            };
+        }
+        self.time_of_last_update = timestamp;
+    }
+
+    /// Volatility of crossing into bin `id`
+    pub fn update_volatility_accumulator(&mut self, id: u32) {
+        let delta_id = id.abs_diff(self.id_reference) as u64;
+        let accumulator = self.volatility_reference as u64 + delta_id * BASIS_POINT_MAX;
+        self.volatility_accumulator =
+            accumulator.min(self.max_volatility_accumulator as u64) as u32;
+    }
+
+    /// Base plus variable fee, scaled by `PRECISION`
+    pub fn total_fee(&self, bin_step: u16) -> U256 {
+        let base_fee =
+            U256::from(self.base_factor) * U256::from(bin_step) * U256::from(10u64.pow(10));
+        let mut variable_fee = U256::ZERO;
+        if self.variable_fee_control != 0 {
+            let prod = U256::from(self.volatility_accumulator) * U256::from(bin_step);
+            variable_fee = (prod * prod * U256::from(self.variable_fee_control) + U256::from(99))
+                / U256::from(100);
+        }
+        base_fee + variable_fee
+    }
+}
+
+/// Reserves of one bin
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bin {
+    pub reserve_x: U256,
+    pub reserve_y: U256,
+}
+
+impl Bin {
+    fn reserve(&self, x: bool) -> U256 {
+        if x { self.reserve_x } else { self.reserve_y }
+    }
+
+    fn reserve_mut(&mut self, x: bool) -> &mut U256 {
+        if x {
+            &mut self.reserve_x
+        } else {
+            &mut self.reserve_y
+        }
+    }
+}
+
+/// What a swap takes from one bin, `BinHelper.getAmounts`
+struct BinAmounts {
+    in_with_fees: U256,
+    out: U256,
+    fee: U256,
+}
+
+fn bin_amounts(
+    bin: &Bin,
+    price: U256,
+    total_fee: U256,
+    swap_for_y: bool,
+    amount_in_left: U256,
+) -> Result<BinAmounts, MathError> {
+    let op = "lb bin amounts";
+    let reserve_out = bin.reserve(!swap_for_y);
+    let mut max_in = match swap_for_y {
+        true => safe128(shift_div(reserve_out, price, true), op)?,
+        false => safe128(mul_shift(reserve_out, price, true), op)?,
+    };
+    // `getFeeAmount`, the fee on top of an amount, rounded up
+    let precision = U256::from(PRECISION);
+    let denominator = precision - total_fee;
+    let max_fee = (max_in * total_fee + denominator - U256::ONE) / denominator;
+    max_in += max_fee;
+
+    if amount_in_left >= max_in {
+        return Ok(BinAmounts {
+            in_with_fees: max_in,
+            out: reserve_out,
+            fee: max_fee,
+        });
+    }
+    // `getFeeAmountFrom`, the fee inside an amount, rounded up
+    let fee = (amount_in_left * total_fee + precision - U256::ONE) / precision;
+    let amount_in = amount_in_left - fee;
+    let out = match swap_for_y {
+        true => safe128(mul_shift(amount_in, price, false), op)?,
+        false => safe128(shift_div(amount_in, price, false), op)?,
+    };
+    Ok(BinAmounts {
+        in_with_fees: amount_in_left,
+        out: out.min(reserve_out),
+        fee,
+    })
+}
+
+/// Offline copy of a Liquidity Book pair with the bins around its price.
+#[derive(Debug, Clone)]
+pub struct LbPoolSim {
+    pub address: Address,
+    pub token_x: Address,
+    pub token_y: Address,
+    pub bin_step: u16,
+    pub active_id: u32,
+    /// Non-empty bins of the loaded window by id
+    pub bins: BTreeMap<u32, Bin>,
+    pub params: LbFeeParameters,
+    /// Block time swaps are quoted at, the volatility decays with it
+    pub timestamp: u64,
+}
+
+/// Outcome of a swap through the bins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LbSwapOut {
+    /// Input the bins couldn't take, nonzero when the window ran out
+    pub amount_in_left: U256,
+    pub amount_out: U256,
+    pub fee: U256,
+}
+
+/// Next non-empty bin a swap moves to, `None` past the loaded window
+fn next_bin(bins: &BTreeMap<u32, Bin>, id: u32, swap_for_y: bool) -> Option<u32> {
+    match swap_for_y {
+        true => bins.range(..id).next_back().map(|(id, _)| *id),
+        false => bins.range(id + 1..).next().map(|(id, _)| *id),
+    }
+}
+
+impl LbPoolSim {
+    /// Walk the bins like `swap`, on `bins` and `params`, returning what
+    /// went in and out and where the price ended
+    fn walk(
+        &self,
+        bins: &mut BTreeMap<u32, Bin>,
+        params: &mut LbFeeParameters,
+        amount_in: U256,
+        swap_for_y: bool,
+    ) -> Result<(LbSwapOut, u32), PoolError> {
+        let amount_in = safe128(amount_in, "lb amount in")?;
+        let mut left = amount_in;
+        let mut amount_out = U256::ZERO;
+        let mut fee = U256::ZERO;
+        let mut id = self.active_id;
+        params.update_references(self.active_id, self.timestamp);
+
+        loop {
+            if let Some(bin) = bins.get_mut(&id)
+                && !bin.reserve(!swap_for_y).is_zero()
+            {
+                params.update_volatility_accumulator(id);
+                let price = price_from_id(id, self.bin_step)?;
+                let total_fee = params.total_fee(self.bin_step);
+                let amounts = bin_amounts(bin, price, total_fee, swap_for_y, left)?;
+                if !amounts.in_with_fees.is_zero() {
+                    left -= amounts.in_with_fees;
+                    amount_out += amounts.out;
+                    fee += amounts.fee;
+                    let protocol_fee = amounts.fee * U256::from(params.protocol_share)
+                        / U256::from(BASIS_POINT_MAX);
+                    *bin.reserve_mut(swap_for_y) += amounts.in_with_fees - protocol_fee;
+                    *bin.reserve_mut(!swap_for_y) -= amounts.out;
+                }
+            }
+            if left.is_zero() {
+                break;
+            }
+            match next_bin(bins, id, swap_for_y) {
+                Some(next) => id = next,
+                None => break,
+            }
+        }
+        let out = LbSwapOut {
+            amount_in_left: left,
+            amount_out,
+            fee,
+        };
+        Ok((out, id))
+    }
+
+    /// `getSwapOut`, selling X for Y when `swap_for_y`
+    pub fn get_swap_out(&self, amount_in: U256, swap_for_y: bool) -> Result<LbSwapOut, PoolError> {
+        let mut bins = self.bins.clone();
+        let mut params = self.params.clone();
+        Ok(self.walk(&mut bins, &mut params, amount_in, swap_for_y)?.0)
+    }
+
+    /// Swap `amount_in` through the bins, the protocol's part of the fee
+    /// leaves them
+    pub fn swap(&mut self, amount_in: U256, swap_for_y: bool) -> Result<U256, PoolError> {
+        let mut bins = self.bins.clone();
+        let mut params = self.params.clone();
+        let (out, id) = self.walk(&mut bins, &mut params, amount_in, swap_for_y)?;
+        if !out.amount_in_left.is_zero() {
+            return Err(PoolError::OutOfTicks {
+                pool: self.address,
+                from0: swap_for_y,
+            });
+        }
+        self.bins = bins;
+        self.params = params;
+        self.active_id = id;
+        Ok(out.amount_out)
+    }
+
+    /// Swap with X as token0
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let fee = self.fee_pips();
+        let amount_out = self.swap(amount_in, from0)?;
+        Ok(Trade {
+            fee,
+            token0: self.token_x,
+            token1: self.token_y,
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out,
+            amount_in_net: amount_in,
+            amount_out_net: amount_out,
+        })
+    }
+
+    /// Apply one bin of a swap logged at `timestamp`, amounts as `[x, y]`
+    /// with the protocol's fee already taken out
+    pub fn apply_swap(
+        &mut self,
+        id: u32,
+        amounts_in: [U256; 2],
+        amounts_out: [U256; 2],
+        volatility_accumulator: u32,
+        timestamp: u64,
+    ) {
+        // a swap through several bins logs each, the references move once
+        // at its start
+        if timestamp != self.params.time_of_last_update {
+            self.params.update_references(self.active_id, timestamp);
+        }
+        self.timestamp = self.timestamp.max(timestamp);
+        if let Some(bin) = self.bin_mut(id) {
+            bin.reserve_x = (bin.reserve_x + amounts_in[0]).saturating_sub(amounts_out[0]);
+            bin.reserve_y = (bin.reserve_y + amounts_in[1]).saturating_sub(amounts_out[1]);
+        }
+        self.active_id = id;
+        self.params.volatility_accumulator = volatility_accumulator;
+    }
+
+    /// Add `amounts` to bin `id`
+    pub fn deposit(&mut self, id: u32, amounts: [U256; 2]) {
+        if let Some(bin) = self.bin_mut(id) {
+            bin.reserve_x += amounts[0];
+            bin.reserve_y += amounts[1];
+        }
+    }
+
+    /// Take `amounts` out of bin `id`
+    pub fn withdraw(&mut self, id: u32, amounts: [U256; 2]) {
+        if let Some(bin) = self.bin_mut(id) {
+            bin.reserve_x = bin.reserve_x.saturating_sub(amounts[0]);
+            bin.reserve_y = bin.reserve_y.saturating_sub(amounts[1]);
+        }
+    }
+
+    /// Bin `id` if it lies inside the loaded window, bins outside it aren't
+    /// tracked
+    fn bin_mut(&mut self, id: u32) -> Option<&mut Bin> {
+        if !self.in_window(id) {
+            return None;
+        }
+        Some(self.bins.entry(id).or_default())
+    }
+
+    /// Whether bin `id` lies between the lowest and highest loaded bins
+    pub fn in_window(&self, id: u32) -> bool {
+        match (self.bins.first_key_value(), self.bins.last_key_value()) {
+            (Some((first, _)), Some((last, _))) => (*first..=*last).contains(&id),
+            _ => false,
+        }
+    }
+
+    /// Fee of a swap starting now, in millionths, the unit `Trade` reports
+    /// fees in
+    pub fn fee_pips(&self) -> U24 {
+        let mut params = self.params.clone();
+        params.update_references(self.active_id, self.timestamp);
+        params.update_volatility_accumulator(self.active_id);
+        let fee = params.total_fee(self.bin_step) / U256::from(PRECISION / 1_000_000);
+        U24::saturating_from(fee)
+    }
+}
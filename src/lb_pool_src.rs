@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use alloy::eips::BlockId;
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, U256};
+use alloy_provider::Provider;
+
+use crate::ILBPair::ILBPairInstance;
+use crate::err::PoolError;
+use crate::lb_pool_sim::{Bin, LbFeeParameters, LbPoolSim};
+use crate::v3_pool_src::Rpc;
+
+type LbContract = ILBPairInstance<Rpc>;
+
+/// Non-empty bins loaded on each side of the active one
+const BINS_EACH_SIDE: usize = 10;
+/// `getNextNonEmptyBin` returns one of these when no bin is left
+const NO_BIN: [u32; 2] = [0, (1 << 24) - 1];
+
+/// A Trader Joe Liquidity Book pair with the non-empty bins around its
+/// active one.
+///
+/// Like `V3PoolSrc::update_ticks` only a window is loaded, `BINS_EACH_SIDE`
+/// non-empty bins below and above the active bin, a swap running past it
+/// fails with `OutOfTicks` instead of being priced on missing liquidity.
+#[derive(Debug)]
+pub struct LbPoolSrc {
+    pub address: Address,
+    pub token_x: Address,
+    pub token_y: Address,
+    pub bin_step: u16,
+    pub active_id: u32,
+    pub bins: BTreeMap<u32, Bin>,
+    pub params: LbFeeParameters,
+    /// Time of the block the pair was read at
+    pub timestamp: u64,
+    pub contract: LbContract,
+}
+
+impl LbPoolSrc {
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(address, provider, BlockId::latest()).await
+    }
+
+    /// Load the pair and the bins around its active one as of `block`
+    pub async fn new_at(
+        address: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = ILBPairInstance::new(address, provider);
+        let token_x = contract.getTokenX().call().block(block).await?;
+        let token_y = contract.getTokenY().call().block(block).await?;
+        let bin_step = contract.getBinStep().call().block(block).await?;
+
+        let mut instance = Self {
+            address,
+            token_x,
+            token_y,
+            bin_step,
+            active_id: 0,
+            bins: BTreeMap::new(),
+            params: LbFeeParameters::default(),
+            timestamp: 0,
+            contract,
+        };
+        instance.update_at(block).await?;
+        Ok(instance)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
+    }
+
+    /// Reload the active bin, fee parameters and the bin window as of `block`
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let contract = &self.contract;
+        self.active_id = contract.getActiveId().call().block(block).await?.to();
+
+        let fees = contract
+            .getStaticFeeParameters()
+            .call()
+            .block(block)
+            .await?;
+        let volatility = contract
+            .getVariableFeeParameters()
+            .call()
+            .block(block)
+            .await?;
+        self.params = LbFeeParameters {
+            base_factor: fees.baseFactor,
+            filter_period: fees.filterPeriod,
+            decay_period: fees.decayPeriod,
+            reduction_factor: fees.reductionFactor,
+            variable_fee_control: fees.variableFeeControl.to(),
+            protocol_share: fees.protocolShare,
+            max_volatility_accumulator: fees.maxVolatilityAccumulator.to(),
+            volatility_accumulator: volatility.volatilityAccumulator.to(),
+            volatility_reference: volatility.volatilityReference.to(),
+            id_reference: volatility.idReference.to(),
+            time_of_last_update: volatility.timeOfLastUpdate.to(),
+        };
+        self.timestamp = contract
+            .provider()
+            .get_block(block)
+            .await?
+            .map(|block| block.header.timestamp)
+            .unwrap_or_default();
+
+        self.bins = self.load_bins(block).await?;
+        Ok(())
+    }
+
+    /// The active bin and up to `BINS_EACH_SIDE` non-empty bins on each
+    /// side, found through the pair's bin tree
+    async fn load_bins(&self, block: BlockId) -> Result<BTreeMap<u32, Bin>, PoolError> {
+        let contract = &self.contract;
+        let mut ids = vec![self.active_id];
+        // lower ids hold Y, the side a swap for Y walks down
+        for swap_for_y in [true, false] {
+            let mut id = self.active_id;
+            for _ in 0..BINS_EACH_SIDE {
+                id = contract
+                    .getNextNonEmptyBin(swap_for_y, U24::from(id))
+                    .call()
+                    .block(block)
+                    .await?
+                    .to();
+                if NO_BIN.contains(&id) {
+                    break;
+                }
+                ids.push(id);
+            }
+        }
+
+        let mut bins = BTreeMap::new();
+        for id in ids {
+            let bin = contract.getBin(U24::from(id)).call().block(block).await?;
+            bins.insert(
+                id,
+                Bin {
+                    reserve_x: U256::from(bin.binReserveX),
+                    reserve_y: U256::from(bin.binReserveY),
+                },
+            );
+        }
+        Ok(bins)
+    }
+
+    pub fn into_sim(&self) -> LbPoolSim {
+        LbPoolSim {
+            address: self.address,
+            token_x: self.token_x,
+            token_y: self.token_y,
+            bin_step: self.bin_step,
+            active_id: self.active_id,
+            bins: self.bins.clone(),
+            params: self.params.clone(),
+            timestamp: self.timestamp,
+        }
+    }
+}
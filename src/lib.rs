@@ -1,10 +1,53 @@
-mod v3_pool_src;
+pub mod v3_pool_src;
+pub mod v3_pool_sim;
+pub mod v3_forks;
+pub mod v2_pool_sim;
+pub mod v2_factory;
+pub mod v2_fees;
+pub mod v2_pool_src;
+pub mod v_pool_sim;
+pub mod journal;
+pub mod pool_storage;
+pub mod pool_sync;
+pub mod rpc;
+pub mod v3_lens;
+pub mod v3_rebuild;
+pub mod v4_hooks;
+pub mod v4_pool_sim;
+pub mod v4_pool_src;
+pub mod v_pool_src;
+pub mod curve_pool_sim;
+pub mod curve_pool_src;
+pub mod curve_crypto_pool_sim;
+pub mod curve_crypto_pool_src;
+pub mod balancer_math;
+pub mod balancer_pool_sim;
+pub mod balancer_pool_src;
+pub mod algebra_pool_sim;
+pub mod algebra_pool_src;
+pub mod solidly_pool_sim;
+pub mod solidly_pool_src;
+pub mod lb_pool_sim;
+pub mod lb_pool_src;
+pub mod erc4626_sim;
+pub mod erc4626_src;
 
 include!("abis/uni_v3_abis.rs");
+include!("abis/pancake_v3_abis.rs");
+include!("abis/uni_v2_abis.rs");
+include!("abis/uni_v4_abis.rs");
+include!("abis/curve_abis.rs");
+include!("abis/balancer_abis.rs");
+include!("abis/algebra_abis.rs");
+include!("abis/solidly_abis.rs");
+include!("abis/lb_abis.rs");
+include!("abis/erc4626_abis.rs");
 
-mod err;
-mod tick_math;
-mod trade;
+pub mod currency;
+pub mod err;
+pub mod tick_math;
+pub mod token_tax;
+pub mod trade;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
@@ -18,11 +61,12 @@ mod tests {
         primitives::{Address, U256},
         transports::http::reqwest::Url,
     };
-    use alloy_provider::ProviderBuilder;
+    use alloy_provider::{Provider, ProviderBuilder};
 
     use super::*;
 
     #[tokio::test]
+    #[ignore = "needs the public BSC endpoint"]
     async fn v3_src() {
         let base = ProviderBuilder::new()
             .connect_http(Url::from_str("https://binance.llamarpc.com").unwrap());
@@ -37,14 +81,14 @@ mod tests {
         )
         .await;
 
-        let mut new_v3 = v3.unwrap();
-        
-        let mut current_tick = new_v3.current_tick;
-        let mut current_price = new_v3.x96price;
+        let new_v3 = v3.unwrap();
+
+        let current_tick = new_v3.current_tick;
+        let current_price = new_v3.x96price;
 
         println!("slot0 tick {}",current_tick);        
         
-        let mut my_price = tick_math::price_from_tick(current_tick).unwrap();
+        let my_price = tick_math::price_from_tick(current_tick).unwrap();
         println!("calc price {}", my_price);
         println!("slot0 price {}", current_price);
 
@@ -67,4 +111,3139 @@ mod tests {
             println!("simulation failed");
         }*/
     }
+    #[test]
+    fn pool_sync_applies_logs_in_chain_order() {
+        use alloy::primitives::{B256, LogData, U256};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::SolEvent;
+
+        let provider =
+            ProviderBuilder::new().connect_http(Url::from_str("http://127.0.0.1:8545").unwrap());
+        let pair = Address::repeat_byte(0x11);
+        let sync_log = |block: u64, index: u64, reserve: u64| {
+            let event = IUniswapV2Pair::Sync {
+                reserve0: U256::from(reserve).to(),
+                reserve1: U256::from(reserve).to(),
+            };
+            Log {
+                inner: alloy::primitives::Log {
+                    address: pair,
+                    data: LogData::new_unchecked(
+                        vec![IUniswapV2Pair::Sync::SIGNATURE_HASH],
+                        event.encode_data().into(),
+                    ),
+                },
+                block_number: Some(block),
+                block_hash: Some(B256::with_last_byte(block as u8)),
+                log_index: Some(index),
+                ..Default::default()
+            }
+        };
+
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track_synced(v_pool_sim::AnyPoolSim::V2(v2_pool_sim::V2PoolSim::new(
+            "uniswap".to_string(),
+            "v2".to_string(),
+            3000,
+            pair,
+            Address::ZERO,
+            Address::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+        )));
+        sync.apply_logs(vec![sync_log(2, 0, 20), sync_log(1, 5, 10)]);
+
+        let Some(v_pool_sim::AnyPoolSim::V2(v2)) = sync.pool(&pair) else {
+            panic!("pool not tracked");
+        };
+        assert_eq!(v2.reserves0, U256::from(20));
+
+        // unwinding block 2 restores the state it was applied on
+        let undone = sync.journal().clone().rollback(1);
+        let Some(v_pool_sim::AnyPoolSim::V2(prior)) = undone[0].prior.get(&pair) else {
+            panic!("block 2 not journaled");
+        };
+        assert_eq!(prior.reserves0, U256::from(10));
+    }
+
+    #[test]
+    fn pool_sync_counts_v2_swaps_once() {
+        use alloy::primitives::{B256, LogData};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::SolEvent;
+
+        let provider =
+            ProviderBuilder::new().connect_http(Url::from_str("http://127.0.0.1:8545").unwrap());
+        let pair = Address::repeat_byte(0x12);
+        let log = |index: u64, data: LogData| Log {
+            inner: alloy::primitives::Log {
+                address: pair,
+                data,
+            },
+            block_number: Some(1),
+            block_hash: Some(B256::with_last_byte(1)),
+            log_index: Some(index),
+            ..Default::default()
+        };
+
+        // what a pair logs for a swap of 100 token0 and a mint, `Sync`
+        // first with the reserves after
+        let sync = |reserve0: u64, reserve1: u64| IUniswapV2Pair::Sync {
+            reserve0: U256::from(reserve0).to(),
+            reserve1: U256::from(reserve1).to(),
+        };
+        let swap = IUniswapV2Pair::Swap {
+            sender: Address::ZERO,
+            amount0In: U256::from(100),
+            amount1In: U256::ZERO,
+            amount0Out: U256::ZERO,
+            amount1Out: U256::from(90),
+            to: Address::ZERO,
+        };
+        let mint = IUniswapV2Pair::Mint {
+            sender: Address::ZERO,
+            amount0: U256::from(11),
+            amount1: U256::from(9),
+        };
+        let logs = vec![
+            log(0, sync(1_100, 910).encode_log_data()),
+            log(1, swap.encode_log_data()),
+            log(2, sync(1_111, 919).encode_log_data()),
+            log(3, mint.encode_log_data()),
+        ];
+
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track_synced(v_pool_sim::AnyPoolSim::V2(v2_pool_sim::V2PoolSim::new(
+            "uniswap".to_string(),
+            "v2".to_string(),
+            3000,
+            pair,
+            Address::with_last_byte(1),
+            Address::with_last_byte(2),
+            U256::from(1_000),
+            U256::from(1_000),
+        )));
+        sync.apply_logs(logs);
+
+        let Some(v_pool_sim::AnyPoolSim::V2(v2)) = sync.pool(&pair) else {
+            panic!("pool not tracked");
+        };
+        assert_eq!(
+            (v2.reserves0, v2.reserves1),
+            (U256::from(1_111), U256::from(919))
+        );
+    }
+
+    #[test]
+    fn pool_sync_routes_pool_manager_logs() {
+        use alloy::primitives::aliases::{I24, U24};
+        use alloy::primitives::{B256, LogData};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::SolEvent;
+
+        let provider =
+            ProviderBuilder::new().connect_http(Url::from_str("http://127.0.0.1:8545").unwrap());
+        let manager = Address::repeat_byte(0x44);
+        let tick = |t: i32| I24::try_from(t).unwrap();
+        let key = |fee: u32| PoolKey {
+            currency0: Address::ZERO,
+            currency1: Address::with_last_byte(2),
+            fee: U24::from(fee),
+            tickSpacing: tick(60),
+            hooks: Address::ZERO,
+        };
+        let log = |index: u64, data: LogData| Log {
+            inner: alloy::primitives::Log {
+                address: manager,
+                data,
+            },
+            block_number: Some(1),
+            block_hash: Some(B256::with_last_byte(1)),
+            log_index: Some(index),
+            ..Default::default()
+        };
+        let encode = |topics: Vec<B256>, data: Vec<u8>| LogData::new_unchecked(topics, data.into());
+
+        let tracked = key(v4_hooks::DYNAMIC_FEE_FLAG);
+        let id = v4_pool_src::pool_id(&tracked);
+        let mut sim = v4_pool_sim::V4PoolSim::new(&tracked);
+        sim.pool.x96price = U256::ONE << 96;
+        sim.pool.active_ticks = vec![
+            tick_math::Tick {
+                tick: tick(-600),
+                liquidity_net: Some(0),
+            },
+            tick_math::Tick {
+                tick: tick(600),
+                liquidity_net: Some(0),
+            },
+        ];
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64).with_v4(manager, manager);
+        sync.track_synced(v_pool_sim::AnyPoolSim::V4(sim));
+
+        let modify = |lower: i32, upper: i32, delta: i64| {
+            let e = PoolManager::ModifyLiquidity {
+                id,
+                sender: Address::ZERO,
+                tickLower: tick(lower),
+                tickUpper: tick(upper),
+                liquidityDelta: alloy::primitives::I256::try_from(delta).unwrap(),
+                salt: B256::ZERO,
+            };
+            encode(
+                vec![PoolManager::ModifyLiquidity::SIGNATURE_HASH, id, B256::ZERO],
+                e.encode_data(),
+            )
+        };
+        let swap = |id: B256| {
+            let e = PoolManager::Swap {
+                id,
+                sender: Address::ZERO,
+                amount0: 10,
+                amount1: -9,
+                sqrtPriceX96: alloy::primitives::aliases::U160::from(1u128 << 95),
+                liquidity: 700,
+                tick: tick(-13863),
+                fee: U24::from(4000),
+            };
+            encode(
+                vec![PoolManager::Swap::SIGNATURE_HASH, id, B256::ZERO],
+                e.encode_data(),
+            )
+        };
+        let new_key = key(500);
+        let initialize = {
+            let e = PoolManager::Initialize {
+                id: v4_pool_src::pool_id(&new_key),
+                currency0: new_key.currency0,
+                currency1: new_key.currency1,
+                fee: new_key.fee,
+                tickSpacing: new_key.tickSpacing,
+                hooks: new_key.hooks,
+                sqrtPriceX96: alloy::primitives::aliases::U160::from(1u128 << 96),
+                tick: tick(0),
+            };
+            encode(
+                vec![
+                    PoolManager::Initialize::SIGNATURE_HASH,
+                    e.id,
+                    new_key.currency0.into_word(),
+                    new_key.currency1.into_word(),
+                ],
+                e.encode_data(),
+            )
+        };
+
+        sync.apply_logs(vec![
+            log(3, swap(id)),
+            log(0, modify(-60, 60, 1_000)),
+            log(1, modify(-60, 60, -300)),
+            log(2, initialize),
+            // a pool nobody tracks
+            log(4, swap(B256::repeat_byte(9))),
+            // the swap left the dynamic fee pool stale, this isn't applied
+            log(5, modify(-60, 60, 100)),
+        ]);
+
+        let Some(v_pool_sim::AnyPoolSim::V4(v4)) = sync.pool(&Address::from_word(id)) else {
+            panic!("V4 pool not tracked");
+        };
+        let ticks: Vec<(i32, Option<i128>)> = v4
+            .pool
+            .active_ticks
+            .iter()
+            .map(|t| (t.tick.as_i32(), t.liquidity_net))
+            .collect();
+        assert_eq!(
+            ticks,
+            vec![
+                (-600, Some(0)),
+                (-60, Some(700)),
+                (60, Some(-700)),
+                (600, Some(0))
+            ]
+        );
+        // applied in log order, the swap last
+        assert_eq!(v4.pool.liquidity, U256::from(700));
+        assert_eq!(v4.pool.current_tick, tick(-13863));
+        // its logged fee isn't taken as the LP fee, the pool is refetched
+        assert_eq!(v4.lp_fee, U24::ZERO);
+        assert_eq!(sync.take_discovered(), vec![new_key]);
+        assert!(sync.take_discovered().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pool_sync_replays_nothing_twice_after_a_failed_sync() {
+        use alloy::sol_types::{SolCall, SolValue};
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let pool = Address::repeat_byte(0x31);
+        let pair = Address::repeat_byte(0x32);
+        // block 11 can't be looked up the first time, the pair's reserves
+        // can't be read the first time
+        let block_failed = AtomicBool::new(false);
+        let reserves_failed = AtomicBool::new(false);
+        let node = fake_node(move |_, request| {
+            let number = |param: &serde_json::Value| {
+                u64::from_str_radix(param.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+            };
+            match request["method"].as_str().unwrap() {
+                "eth_getBlockByNumber" => {
+                    if request["params"][0] == "0xb" && !block_failed.swap(true, Ordering::SeqCst) {
+                        return FakeReply::Error(-32000, "header not found");
+                    }
+                    fake_block(request)
+                }
+                "eth_getLogs" => {
+                    let to = number(&request["params"][0]["toBlock"]);
+                    // a mint in every block
+                    FakeReply::Result(serde_json::to_value(vec![fake_v3_mint(pool, to)]).unwrap())
+                }
+                _ => match eth_call(request) {
+                    (to, IUniswapV2Pair::getReservesCall::SELECTOR, _) if to == pair => {
+                        if !reserves_failed.swap(true, Ordering::SeqCst) {
+                            return FakeReply::Error(-32000, "missing trie node");
+                        }
+                        FakeReply::returns((5u128, 6u128, 0u32).abi_encode())
+                    }
+                    (_, selector, _) => fake_v3_pool(selector),
+                },
+            }
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node])
+            .max_retries(0)
+            .connect()
+            .unwrap();
+        let liquidity = |sync: &pool_sync::PoolSync| match sync.pool(&pool) {
+            Some(v_pool_sim::AnyPoolSim::V3(v3)) => v3.liquidity,
+            _ => panic!("pool not tracked"),
+        };
+
+        let v3 = v3_pool_src::V3PoolSrc::new(pool, provider.clone())
+            .await
+            .unwrap();
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track(v_pool_sim::AnyPoolSim::V3(v3.into_sim()));
+        sync.sync_to(10).await.unwrap();
+        assert_eq!(liquidity(&sync), U256::from(1000));
+
+        // the head's hash is looked up before block 11's mint is applied
+        assert!(sync.sync_to(11).await.is_err());
+        assert_eq!(sync.last_block(), Some(10));
+        assert_eq!(liquidity(&sync), U256::from(1000));
+        sync.sync_to(11).await.unwrap();
+        assert_eq!(liquidity(&sync), U256::from(1100));
+
+        // a refetch failing after block 12's mint is applied undoes it
+        sync.track(v_pool_sim::AnyPoolSim::V2(v2_pool_sim::V2PoolSim::new(
+            "uniswap".to_string(),
+            "v2".to_string(),
+            3000,
+            pair,
+            Address::with_last_byte(1),
+            Address::with_last_byte(2),
+            U256::ZERO,
+            U256::ZERO,
+        )));
+        assert!(sync.sync_to(12).await.is_err());
+        assert_eq!(sync.last_block(), Some(11));
+        assert_eq!(liquidity(&sync), U256::from(1100));
+        sync.sync_to(12).await.unwrap();
+        assert_eq!(liquidity(&sync), U256::from(1200));
+        let Some(v_pool_sim::AnyPoolSim::V2(v2)) = sync.pool(&pair) else {
+            panic!("pair not tracked");
+        };
+        assert_eq!(v2.reserves0, U256::from(5));
+    }
+
+    #[tokio::test]
+    async fn pool_sync_unwinds_a_reorged_block() {
+        use alloy::sol_types::{SolCall, SolValue};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        let pool = Address::repeat_byte(0x36);
+        let pair = Address::repeat_byte(0x37);
+        // block 12 is replaced by one without the mint, and the pair's
+        // reserves differ on the new chain
+        let reorged = Arc::new(AtomicBool::new(false));
+        let refetches = Arc::new(AtomicUsize::new(0));
+        let (node_reorged, node_refetches) = (reorged.clone(), refetches.clone());
+        let node = fake_node(move |_, request| {
+            let reorged = node_reorged.load(Ordering::SeqCst);
+            let number = |param: &serde_json::Value| {
+                u64::from_str_radix(param.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+            };
+            match request["method"].as_str().unwrap() {
+                "eth_getBlockByNumber" if reorged && request["params"][0] == "0xc" => {
+                    let mut block =
+                        alloy::rpc::types::Block::<alloy::rpc::types::Transaction>::default();
+                    block.header.hash = alloy::primitives::B256::repeat_byte(0xcc);
+                    block.header.inner.number = 12;
+                    FakeReply::Result(serde_json::to_value(block).unwrap())
+                }
+                "eth_getBlockByNumber" => fake_block(request),
+                "eth_getLogs" => {
+                    let from = number(&request["params"][0]["fromBlock"]);
+                    let to = number(&request["params"][0]["toBlock"]);
+                    let logs: Vec<_> = (from..=to)
+                        .filter(|&n| !(reorged && n == 12))
+                        .map(|n| fake_v3_mint(pool, n))
+                        .collect();
+                    FakeReply::Result(serde_json::to_value(logs).unwrap())
+                }
+                _ => match eth_call(request) {
+                    (to, IUniswapV2Pair::getReservesCall::SELECTOR, _) if to == pair => {
+                        node_refetches.fetch_add(1, Ordering::SeqCst);
+                        let reserve = if reorged { 7u128 } else { 5 };
+                        FakeReply::returns((reserve, reserve, 0u32).abi_encode())
+                    }
+                    (_, selector, _) => fake_v3_pool(selector),
+                },
+            }
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node]).connect().unwrap();
+        let liquidity = |sync: &pool_sync::PoolSync| match sync.pool(&pool) {
+            Some(v_pool_sim::AnyPoolSim::V3(v3)) => v3.liquidity,
+            _ => panic!("pool not tracked"),
+        };
+
+        let v3 = v3_pool_src::V3PoolSrc::new(pool, provider.clone())
+            .await
+            .unwrap();
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track(v_pool_sim::AnyPoolSim::V3(v3.into_sim()));
+        sync.sync_to(11).await.unwrap();
+        assert_eq!(liquidity(&sync), U256::from(1000));
+
+        // the pair is first loaded in block 12, with block 12's mint
+        sync.track(v_pool_sim::AnyPoolSim::V2(v2_pool_sim::V2PoolSim::new(
+            "uniswap".to_string(),
+            "v2".to_string(),
+            3000,
+            pair,
+            Address::with_last_byte(1),
+            Address::with_last_byte(2),
+            U256::ZERO,
+            U256::ZERO,
+        )));
+        sync.sync_to(12).await.unwrap();
+        assert_eq!(liquidity(&sync), U256::from(1100));
+        assert_eq!(refetches.load(Ordering::SeqCst), 1);
+
+        // block 12 is rolled back to block 11's state and only block 13's
+        // mint applies, the pair loaded in block 12 is loaded again
+        reorged.store(true, Ordering::SeqCst);
+        sync.sync_to(13).await.unwrap();
+        assert_eq!(sync.last_block(), Some(13));
+        assert_eq!(liquidity(&sync), U256::from(1100));
+        // and the orphaned block left the journal
+        assert_eq!(sync.journal().hash_at(12), None);
+        assert_eq!(refetches.load(Ordering::SeqCst), 2);
+        let Some(v_pool_sim::AnyPoolSim::V2(v2)) = sync.pool(&pair) else {
+            panic!("pair not tracked");
+        };
+        assert_eq!(v2.reserves0, U256::from(7));
+    }
+
+    #[tokio::test]
+    async fn pool_sync_keeps_taxes_across_refetches() {
+        use token_tax::{TokenTaxes, TransferTax};
+
+        let pool = Address::repeat_byte(0x35);
+        let node = fake_node(|_, request| match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => fake_block(request),
+            "eth_getLogs" => FakeReply::Result(serde_json::json!([])),
+            _ => fake_v3_pool(eth_call(request).1),
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node]).connect().unwrap();
+        let mut v3 = v3_pool_src::V3PoolSrc::new(pool, provider.clone())
+            .await
+            .unwrap()
+            .into_sim();
+        let mut taxes = TokenTaxes::new();
+        taxes.insert(Address::with_last_byte(2), TransferTax::flat(500));
+        v3.apply_taxes(&taxes);
+
+        // tracked stale, the first sync reloads the pool from chain
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track(v_pool_sim::AnyPoolSim::V3(v3));
+        sync.sync_to(10).await.unwrap();
+        let Some(v_pool_sim::AnyPoolSim::V3(refetched)) = sync.pool(&pool) else {
+            panic!("pool not tracked");
+        };
+        assert!(refetched.tax0.is_none());
+        assert!(matches!(
+            refetched.tax1,
+            TransferTax::Percent { buy_bps: 500, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn pool_sync_rereads_only_what_changes() {
+        use alloy::sol_types::{SolCall, SolValue};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        // a plain pool and a twocrypto pool of two 18 decimal coins, their
+        // first balance moves
+        let pool = Address::repeat_byte(0xc0);
+        let crypto = Address::repeat_byte(0xc2);
+        let balance = Arc::new(AtomicU64::new(1_000));
+        let node_balance = balance.clone();
+        let node = fake_node(
+            move |_, request| match request["method"].as_str().unwrap() {
+                "eth_getBlockByNumber" => fake_block(request),
+                "eth_getLogs" => FakeReply::Result(serde_json::json!([])),
+                _ => {
+                    let (to, selector, args) = eth_call(request);
+                    let arg = || U256::from_be_slice(&args[..32]);
+                    let result = match selector {
+                        ICurveStableSwap::coinsCall::SELECTOR if arg() < U256::from(2) => {
+                            arg() + U256::ONE
+                        }
+                        ICurveStableSwap::balancesCall::SELECTOR if arg().is_zero() => {
+                            U256::from(node_balance.load(Ordering::SeqCst))
+                        }
+                        ICurveStableSwap::balancesCall::SELECTOR => U256::from(1_000),
+                        ICurveStableSwap::ACall::SELECTOR => U256::from(200),
+                        ICurveStableSwap::feeCall::SELECTOR => U256::from(4_000_000),
+                        IERC20Decimals::decimalsCall::SELECTOR => U256::from(18),
+                        _ if to != crypto => return FakeReply::revert(),
+                        ICurveCryptoSwap::price_scaleCall::SELECTOR
+                        | ICurveCryptoSwap::gammaCall::SELECTOR
+                        | ICurveCryptoSwap::DCall::SELECTOR
+                        | ICurveCryptoSwap::mid_feeCall::SELECTOR
+                        | ICurveCryptoSwap::out_feeCall::SELECTOR
+                        | ICurveCryptoSwap::fee_gammaCall::SELECTOR => U256::ONE,
+                        ICurveCryptoSwap::future_A_gamma_timeCall::SELECTOR => U256::ZERO,
+                        _ => return FakeReply::revert(),
+                    };
+                    FakeReply::returns(result.abi_encode())
+                }
+            },
+        )
+        .await;
+        let transport = rpc::RpcConfig::new(vec![node]).transport().unwrap();
+        let mut sync = pool_sync::PoolSync::new(transport.provider(), 1_000, 64);
+        sync.track(v_pool_sim::AnyPoolSim::Curve(
+            curve_pool_sim::CurvePoolSim {
+                address: pool,
+                coins: Vec::new(),
+                balances: Vec::new(),
+                rates: Vec::new(),
+                amp: U256::ZERO,
+                fee: U256::ZERO,
+                admin_fee: U256::ZERO,
+            },
+        ));
+        sync.track(v_pool_sim::AnyPoolSim::CurveCrypto(
+            curve_crypto_pool_sim::CurveCryptoPoolSim {
+                address: crypto,
+                coins: Vec::new(),
+                balances: Vec::new(),
+                precisions: Vec::new(),
+                price_scale: Vec::new(),
+                ann: U256::ZERO,
+                gamma: U256::ZERO,
+                d: U256::ZERO,
+                mid_fee: U256::ZERO,
+                out_fee: U256::ZERO,
+                fee_gamma: U256::ZERO,
+                ng: false,
+            },
+        ));
+        let balances = |sync: &pool_sync::PoolSync| match (sync.pool(&pool), sync.pool(&crypto)) {
+            (
+                Some(v_pool_sim::AnyPoolSim::Curve(curve)),
+                Some(v_pool_sim::AnyPoolSim::CurveCrypto(crypto)),
+            ) => [curve.balances.clone(), crypto.balances.clone()],
+            _ => panic!("pools not tracked"),
+        };
+
+        // three coins() probes and two decimals() on the first load, then
+        // base_pool() or MATH()
+        sync.sync_to(10).await.unwrap();
+        assert_eq!(transport.count().by_method["eth_call"], (6 + 7) + (6 + 10));
+        assert_eq!(
+            balances(&sync),
+            [vec![U256::from(1_000); 2], vec![U256::from(1_000); 2]]
+        );
+
+        // then balances, A_precise(), A(), fee(), admin_fee() and stored_rates(),
+        // or balances, price_scale(), A(), gamma(), D(), the three fee getters
+        // and future_A_gamma_time()
+        balance.store(1_500, Ordering::SeqCst);
+        let before = transport.count();
+        sync.sync_to(11).await.unwrap();
+        assert_eq!(
+            transport.count().since(&before).by_method["eth_call"],
+            7 + 10
+        );
+        let moved = vec![U256::from(1_500), U256::from(1_000)];
+        assert_eq!(balances(&sync), [moved.clone(), moved]);
+    }
+
+    #[test]
+    fn v3_rebuild_replays_and_checkpoints() {
+        use alloy::primitives::{
+            U256,
+            aliases::{I24, U24},
+        };
+        use pool_sync::PoolEvent;
+
+        let tick = |t: i32| I24::try_from(t).unwrap();
+        let mut rebuild = v3_rebuild::V3Rebuild {
+            pool: Address::repeat_byte(0x33),
+            token0: Address::repeat_byte(0x01),
+            token1: Address::repeat_byte(0x02),
+            fee: U24::from(500),
+            tick_spacing: tick(10),
+            next_block: 100,
+            x96price: U256::ZERO,
+            liquidity: U256::ZERO,
+            current_tick: I24::ZERO,
+            ticks: Default::default(),
+        };
+
+        rebuild.apply(&PoolEvent::V3Initialize {
+            sqrt_price_x96: U256::ONE << 96,
+            tick: tick(0),
+        });
+        rebuild.apply(&PoolEvent::V3Mint {
+            tick_lower: tick(-20),
+            tick_upper: tick(20),
+            amount: 1_000,
+        });
+        rebuild.apply(&PoolEvent::V3Mint {
+            tick_lower: tick(20),
+            tick_upper: tick(40),
+            amount: 1_000,
+        });
+        rebuild.apply(&PoolEvent::V3Burn {
+            tick_lower: tick(-20),
+            tick_upper: tick(20),
+            amount: 400,
+        });
+
+        assert_eq!(rebuild.liquidity, U256::from(600));
+        // the shared tick nets out but stays initialized
+        assert_eq!(rebuild.ticks[&tick(20)].net, 400);
+        assert_eq!(rebuild.ticks[&tick(20)].gross, 1_600);
+        assert_eq!(rebuild.into_sim().active_ticks.len(), 3);
+
+        let restored =
+            v3_rebuild::V3Rebuild::from_checkpoint(&rebuild.to_checkpoint()).unwrap();
+        assert_eq!(restored, rebuild);
+    }
+
+    #[tokio::test]
+    async fn v3_rebuild_replays_pancake_swaps() {
+        use alloy::primitives::aliases::{I24, U24};
+        use alloy::primitives::{B256, LogData, U160};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::SolEvent;
+
+        let tick = |t: i32| I24::try_from(t).unwrap();
+        let pool = Address::repeat_byte(0x34);
+        let log = |block: u64, index: u64, data: LogData| Log {
+            inner: alloy::primitives::Log {
+                address: pool,
+                data,
+            },
+            block_number: Some(block),
+            block_hash: Some(B256::with_last_byte(block as u8)),
+            log_index: Some(index),
+            ..Default::default()
+        };
+        let initialize = UniV3Pool::Initialize {
+            sqrtPriceX96: U160::ONE << 96,
+            tick: tick(0),
+        };
+        let mint = UniV3Pool::Mint {
+            sender: Address::ZERO,
+            owner: Address::ZERO,
+            tickLower: tick(-20),
+            tickUpper: tick(20),
+            amount: 1_000,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        };
+        let swap = PancakeV3Pool::Swap {
+            sender: Address::ZERO,
+            recipient: Address::ZERO,
+            amount0: Default::default(),
+            amount1: Default::default(),
+            sqrtPriceX96: U160::ONE << 95,
+            liquidity: 0,
+            tick: tick(-13_863),
+            protocolFeesToken0: 1,
+            protocolFeesToken1: 0,
+        };
+        let logs = [
+            log(100, 0, initialize.encode_log_data()),
+            log(100, 1, mint.encode_log_data()),
+            log(101, 0, swap.encode_log_data()),
+        ];
+
+        // a node only returns the logs whose topic the filter asks for
+        let node = fake_node(move |_, request| {
+            let filter = &request["params"][0];
+            let from = filter["fromBlock"].as_str().unwrap();
+            let from = u64::from_str_radix(from.trim_start_matches("0x"), 16).unwrap();
+            let to = filter["toBlock"].as_str().unwrap();
+            let to = u64::from_str_radix(to.trim_start_matches("0x"), 16).unwrap();
+            let topics: Vec<B256> = match &filter["topics"][0] {
+                serde_json::Value::Array(topics) => topics
+                    .iter()
+                    .map(|t| serde_json::from_value(t.clone()).unwrap())
+                    .collect(),
+                topic => vec![serde_json::from_value(topic.clone()).unwrap()],
+            };
+            let matching: Vec<&Log> = logs
+                .iter()
+                .filter(|l| (from..=to).contains(&l.block_number.unwrap()))
+                .filter(|l| topics.contains(&l.topics()[0]))
+                .collect();
+            FakeReply::Result(serde_json::to_value(matching).unwrap())
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node]).connect().unwrap();
+
+        let mut rebuild = v3_rebuild::V3Rebuild {
+            pool,
+            token0: Address::repeat_byte(0x01),
+            token1: Address::repeat_byte(0x02),
+            fee: U24::from(500),
+            tick_spacing: tick(10),
+            next_block: 100,
+            x96price: U256::ZERO,
+            liquidity: U256::ZERO,
+            current_tick: I24::ZERO,
+            ticks: Default::default(),
+        };
+        rebuild.run(&provider, 101, 10).await.unwrap();
+        assert_eq!(rebuild.next_block, 102);
+        assert_eq!(rebuild.ticks.len(), 2);
+        // the swap left the mint's range
+        assert_eq!(rebuild.x96price, U256::ONE << 95);
+        assert_eq!(rebuild.current_tick, tick(-13_863));
+        assert_eq!(rebuild.liquidity, U256::ZERO);
+
+        // a range of 0 blocks still moves on, a block at a time
+        rebuild.run(&provider, 103, 0).await.unwrap();
+        assert_eq!(rebuild.next_block, 104);
+    }
+
+    #[test]
+    fn pool_storage_decodes_packed_slots() {
+        use alloy::primitives::{U256, aliases::I24};
+
+        // slot0 with tick -5 and observation fields set above it
+        let price = U256::from(79228162514264337593543950336u128);
+        let tick_bits = U256::from(0xFFFFFBu32) << 160;
+        let word = price | tick_bits | (U256::from(7) << 184);
+        let (p, t) = pool_storage::decode_slot0(word);
+        assert_eq!(p, price);
+        assert_eq!(t, I24::try_from(-5).unwrap());
+
+        let net = -42i128;
+        let word = (U256::from(net as u128) << 128) | U256::from(100u128);
+        assert_eq!(pool_storage::decode_tick(word), (100, -42));
+
+        let word = U256::from(3u8) | (U256::from(4u8) << 112) | (U256::from(5u8) << 224);
+        assert_eq!(
+            pool_storage::decode_reserves(word),
+            (U256::from(3), U256::from(4), 5)
+        );
+    }
+
+    #[test]
+    fn v3_lens_output_decodes() {
+        use alloy::primitives::{I256, U256, aliases::I24};
+
+        let w = |v: i64| I256::try_from(v).unwrap().into_raw();
+        let pool = Address::repeat_byte(0x22);
+        let missing = Address::repeat_byte(0x33);
+        // one pool at tick -100 with spacing 60 and one word on each side,
+        // then a pool whose slot0 call failed
+        let words = [
+            U256::from(123456789),
+            w(-100),
+            U256::from(5555),
+            U256::from(60),
+            U256::ONE,
+            w(10),
+            (U256::ONE << 254) | (U256::ONE << 255),
+            w(7),
+            w(-8),
+            U256::from(2),
+            w(9),
+            U256::ZERO,
+        ];
+
+        let loaded = v3_lens::decode(&[pool, missing], 1, &words).unwrap();
+        let state = loaded[0].as_ref().unwrap();
+        assert_eq!(state.current_tick, I24::try_from(-100).unwrap());
+        assert_eq!(state.bitmap.len(), 3);
+        let ticks: Vec<(i32, i128)> = state
+            .active_ticks
+            .iter()
+            .map(|t| (t.tick.as_i32(), t.liquidity_net.unwrap()))
+            .collect();
+        assert_eq!(ticks, vec![(-30720, 10), (-120, 7), (-60, -8), (60, 9)]);
+        assert!(loaded[1].is_none());
+    }
+
+    /// Assemble `section` of `v3_lens.asm` followed by its `[body]`, labels
+    /// resolved in a second pass once every offset is known
+    fn assemble_lens(section: &str) -> Vec<u8> {
+        use std::collections::HashMap;
+
+        let source = include_str!("v3_lens.asm");
+        let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut current = "";
+        for line in source.lines() {
+            let line = line.split(';').next().unwrap().trim();
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = name;
+                continue;
+            }
+            sections
+                .entry(current)
+                .or_default()
+                .extend(line.split_whitespace());
+        }
+        let tokens: Vec<&str> = [&sections[section][..], &sections["body"][..]].concat();
+
+        let opcode = |name: &str| -> u8 {
+            match name {
+                "ADD" => 0x01,
+                "MUL" => 0x02,
+                "SUB" => 0x03,
+                "SDIV" => 0x05,
+                "SMOD" => 0x07,
+                "LT" => 0x10,
+                "SLT" => 0x12,
+                "SGT" => 0x13,
+                "ISZERO" => 0x15,
+                "AND" => 0x16,
+                "SHL" => 0x1b,
+                "SHR" => 0x1c,
+                "SAR" => 0x1d,
+                "CALLDATASIZE" => 0x36,
+                "CALLDATACOPY" => 0x37,
+                "CODESIZE" => 0x38,
+                "CODECOPY" => 0x39,
+                "RETURNDATASIZE" => 0x3d,
+                "POP" => 0x50,
+                "MLOAD" => 0x51,
+                "MSTORE" => 0x52,
+                "JUMP" => 0x56,
+                "JUMPI" => 0x57,
+                "GAS" => 0x5a,
+                "JUMPDEST" => 0x5b,
+                "DUP1" => 0x80,
+                "SWAP1" => 0x90,
+                "RETURN" => 0xf3,
+                "STATICCALL" => 0xfa,
+                _ => panic!("unknown instruction {name}"),
+            }
+        };
+
+        let mut labels: HashMap<&str, usize> = HashMap::new();
+        let mut code = Vec::new();
+        for pass in 0..2 {
+            code.clear();
+            let mut tokens = tokens.iter();
+            while let Some(&token) = tokens.next() {
+                if let Some(label) = token.strip_suffix(':') {
+                    labels.insert(label, code.len());
+                } else if let Some(size) = token.strip_prefix("PUSH") {
+                    let size: usize = size.parse().unwrap();
+                    let operand = tokens.next().unwrap();
+                    let value = match operand.strip_prefix(':') {
+                        Some(label) => {
+                            let offset = labels.get(label).copied();
+                            assert!(pass == 0 || offset.is_some(), "unknown label {label}");
+                            U256::from(offset.unwrap_or_default())
+                        }
+                        None => U256::from_str(operand).unwrap(),
+                    };
+                    code.push(0x5f + size as u8);
+                    code.extend_from_slice(&value.to_be_bytes::<32>()[32 - size..]);
+                } else {
+                    code.push(opcode(token));
+                }
+            }
+            labels.insert("end", code.len());
+        }
+        code
+    }
+
+    #[test]
+    fn v3_lens_bytecode_matches_source() {
+        assert_eq!(assemble_lens("runtime"), v3_lens::LENS_RUNTIME);
+        assert_eq!(assemble_lens("init"), v3_lens::LENS_INIT);
+    }
+
+    /// What the fake node does with a request
+    enum FakeReply {
+        Result(serde_json::Value),
+        Error(i64, &'static str),
+        Status(u16),
+        Hang,
+    }
+
+    /// JSON-RPC over HTTP node answering every request with `reply(n, request)`,
+    /// `n` counting requests from 0
+    async fn fake_node<F>(reply: F) -> Url
+    where
+        F: Fn(usize, &serde_json::Value) -> FakeReply + Send + Sync + 'static,
+    {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let reply = Arc::new(reply);
+        let seen = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let reply = reply.clone();
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    let body = loop {
+                        let n = socket.read(&mut chunk).await.unwrap_or(0);
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                        let text = String::from_utf8_lossy(&buf).to_string();
+                        let Some(end) = text.find("\r\n\r\n") else {
+                            continue;
+                        };
+                        let length: usize = text[..end]
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if buf.len() >= end + 4 + length {
+                            break buf[end + 4..end + 4 + length].to_vec();
+                        }
+                    };
+
+                    let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    // a batch is answered call by call, each one counts for `n`
+                    let batch = match &request {
+                        serde_json::Value::Array(calls) => calls.clone(),
+                        call => vec![call.clone()],
+                    };
+                    let mut answers = Vec::new();
+                    let mut status = 200;
+                    for request in &batch {
+                        let n = seen.fetch_add(1, Ordering::SeqCst);
+                        match reply(n, request) {
+                            FakeReply::Result(result) => answers.push(
+                                serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                            ),
+                            FakeReply::Error(code, message) => answers.push(
+                                serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "error": {"code": code, "message": message}}),
+                            ),
+                            FakeReply::Status(code) => {
+                                status = code;
+                                break;
+                            }
+                            FakeReply::Hang => {
+                                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                                return;
+                            }
+                        }
+                    }
+                    let body = if status != 200 {
+                        serde_json::Value::Null
+                    } else if request.is_array() {
+                        serde_json::Value::Array(answers)
+                    } else {
+                        answers.remove(0)
+                    };
+                    let body = if body.is_null() {
+                        String::new()
+                    } else {
+                        body.to_string()
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {status} Fake\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    socket.write_all(response.as_bytes()).await.ok();
+                });
+            }
+        });
+        url
+    }
+
+    impl FakeReply {
+        /// Return data of an `eth_call`
+        fn returns(data: impl AsRef<[u8]>) -> Self {
+            Self::Result(alloy::hex::encode_prefixed(data).into())
+        }
+
+        fn revert() -> Self {
+            Self::Error(3, "execution reverted")
+        }
+    }
+
+    /// Target, selector and arguments of the `eth_call` in `request`
+    fn eth_call(request: &serde_json::Value) -> (Address, [u8; 4], Vec<u8>) {
+        let call = &request["params"][0];
+        let to = call["to"].as_str().unwrap().parse().unwrap();
+        let input = call["input"]
+            .as_str()
+            .or(call["data"].as_str())
+            .unwrap_or("0x");
+        let input = alloy::hex::decode(input).unwrap();
+        (to, input[..4].try_into().unwrap(), input[4..].to_vec())
+    }
+
+    /// `fake_node` serving contract reads, every `eth_call` is answered by
+    /// `reply(to, selector, args)` and calls it doesn't know revert.
+    ///
+    /// The quotes the pool tests expect from that state are the pool's own
+    /// Vyper or Solidity math rerun on it, not values read from a chain, the
+    /// ignored `*_anvil` tests check the simulations against live pools
+    async fn fake_contracts<F>(reply: F) -> Url
+    where
+        F: Fn(Address, [u8; 4], &[u8]) -> FakeReply + Send + Sync + 'static,
+    {
+        fake_node(move |_, request| {
+            let (to, selector, args) = eth_call(request);
+            reply(to, selector, &args)
+        })
+        .await
+    }
+
+    /// Header of the block asked for, its hash is the number in the last byte
+    fn fake_block(request: &serde_json::Value) -> FakeReply {
+        let number = request["params"][0].as_str().unwrap().trim_start_matches("0x");
+        let number = u64::from_str_radix(number, 16).unwrap();
+        let mut block = alloy::rpc::types::Block::<alloy::rpc::types::Transaction>::default();
+        block.header.hash = alloy::primitives::B256::with_last_byte(number as u8);
+        block.header.inner.number = number;
+        FakeReply::Result(serde_json::to_value(block).unwrap())
+    }
+
+    /// A V3 pool of tokens 1 and 2 at tick 0 with spacing 60, a liquidity of
+    /// 1000 and no initialized ticks
+    fn fake_v3_pool(selector: [u8; 4]) -> FakeReply {
+        use alloy::sol_types::{SolCall, SolValue};
+
+        let result = match selector {
+            UniV3Pool::slot0Call::SELECTOR => (U256::ONE << 96, [U256::ZERO; 6]).abi_encode(),
+            UniV3Pool::tickSpacingCall::SELECTOR => 60i32.abi_encode(),
+            UniV3Pool::liquidityCall::SELECTOR => 1000u128.abi_encode(),
+            UniV3Pool::feeCall::SELECTOR => 3000u32.abi_encode(),
+            UniV3Pool::token0Call::SELECTOR => Address::with_last_byte(1).abi_encode(),
+            UniV3Pool::token1Call::SELECTOR => Address::with_last_byte(2).abi_encode(),
+            UniV3Pool::tickBitmapCall::SELECTOR => U256::ZERO.abi_encode(),
+            _ => return FakeReply::revert(),
+        };
+        FakeReply::returns(result)
+    }
+
+    /// Log of a mint of 100 around tick 0 of `pool` in block `number` of the
+    /// chain `fake_block` serves
+    fn fake_v3_mint(pool: Address, number: u64) -> alloy::rpc::types::Log {
+        use alloy::primitives::{B256, aliases::I24};
+        use alloy::sol_types::SolEvent;
+
+        let mint = UniV3Pool::Mint {
+            sender: Address::ZERO,
+            owner: Address::ZERO,
+            tickLower: I24::try_from(-60).unwrap(),
+            tickUpper: I24::try_from(60).unwrap(),
+            amount: 100,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        };
+        alloy::rpc::types::Log {
+            inner: alloy::primitives::Log {
+                address: pool,
+                data: mint.encode_log_data(),
+            },
+            block_number: Some(number),
+            block_hash: Some(B256::with_last_byte(number as u8)),
+            log_index: Some(0),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn rpc_retries_and_fails_over() {
+        use std::time::{Duration, Instant};
+
+        // nothing listens on a port that was just released
+        let dead = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap()
+        };
+        let flaky = fake_node(|n, _| match n {
+            0 => FakeReply::Status(503),
+            1 => FakeReply::Error(429, "rate limited"),
+            2 => FakeReply::Hang,
+            _ => FakeReply::Result("0x10".into()),
+        })
+        .await;
+
+        let config = rpc::RpcConfig::new(vec![dead, flaky])
+            .timeout(Duration::from_millis(200))
+            .max_retries(5)
+            .backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let transport = config.clone().transport().unwrap();
+        let provider = ProviderBuilder::new()
+            .connect_client(alloy::rpc::client::RpcClient::new(transport.clone(), false));
+
+        assert_eq!(provider.get_block_number().await.unwrap(), 16);
+        assert_eq!(transport.active(), 1);
+
+        // a revert is an answer, not a failure
+        let reverting = fake_node(|_, _| FakeReply::revert()).await;
+        let provider = rpc::RpcConfig::new(vec![reverting]).connect().unwrap();
+        let err = provider.get_block_number().await.unwrap_err();
+        assert!(err.to_string().contains("execution reverted"), "{err}");
+
+        let hanging = fake_node(|_, _| FakeReply::Hang).await;
+        let provider = rpc::RpcConfig::new(vec![hanging])
+            .timeout(Duration::from_millis(50))
+            .max_retries(1)
+            .backoff(Duration::from_millis(1), Duration::from_millis(1))
+            .connect()
+            .unwrap();
+        let started = Instant::now();
+        let err = provider.get_block_number().await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn v3_src_surfaces_failed_tick_reads() {
+        use alloy::primitives::aliases::I24;
+        use alloy::sol_types::{SolCall, SolValue};
+        use std::time::Duration;
+
+        // pool at tick 0, spacing 60, with ticks 60 and 15300 in word 0 and
+        // -15360 in word -1
+        let pool = move |failing_bitmap: bool| {
+            move |_: Address, selector: [u8; 4], args: &[u8]| {
+                let result = match selector {
+                    UniV3Pool::tickSpacingCall::SELECTOR => 60i32.abi_encode(),
+                    UniV3Pool::slot0Call::SELECTOR => {
+                        (U256::ONE << 96, [U256::ZERO; 6]).abi_encode()
+                    }
+                    UniV3Pool::liquidityCall::SELECTOR => 1000u128.abi_encode(),
+                    UniV3Pool::feeCall::SELECTOR => 3000u32.abi_encode(),
+                    UniV3Pool::token0Call::SELECTOR | UniV3Pool::token1Call::SELECTOR => {
+                        Address::with_last_byte(1).abi_encode()
+                    }
+                    UniV3Pool::tickBitmapCall::SELECTOR if failing_bitmap => {
+                        return FakeReply::Status(500);
+                    }
+                    UniV3Pool::tickBitmapCall::SELECTOR => {
+                        let call = UniV3Pool::tickBitmapCall::abi_decode_raw(args).unwrap();
+                        match call.wordPosition {
+                            0 => (U256::from(2) | U256::ONE << 255usize).abi_encode(),
+                            -1 => U256::ONE.abi_encode(),
+                            _ => U256::ZERO.abi_encode(),
+                        }
+                    }
+                    UniV3Pool::ticksCall::SELECTOR => (7u128, 7i128, [U256::ZERO; 6]).abi_encode(),
+                    _ => return FakeReply::revert(),
+                };
+                FakeReply::returns(result)
+            }
+        };
+
+        let connect = |url: Url| {
+            rpc::RpcConfig::new(vec![url])
+                .max_retries(1)
+                .backoff(Duration::from_millis(1), Duration::from_millis(1))
+                .connect()
+                .unwrap()
+        };
+        let address = Address::repeat_byte(0x44);
+
+        let broken = connect(fake_contracts(pool(true)).await);
+        let err = v3_pool_src::V3PoolSrc::new(address, broken)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, err::PoolError::Rpc(_)), "{err}");
+
+        let transport = rpc::RpcConfig::new(vec![fake_contracts(pool(false)).await])
+            .transport()
+            .unwrap();
+        let before = transport.count();
+        let src = v3_pool_src::V3PoolSrc::new(address, transport.provider())
+            .await
+            .unwrap();
+        // six getters, `factory` and `lmPool` telling the fork, bitmap words
+        // -58..=57 that cover the whole tick range at spacing 60 and one
+        // ticks() read per initialized tick
+        let cost = transport.count().since(&before);
+        assert_eq!(cost.by_method.get("eth_call"), Some(&(6 + 2 + 116 + 3)));
+        assert_eq!(cost.requests, 127);
+        assert_eq!(cost.compute_units, 127 * 26);
+        let ticks: Vec<(I24, Option<i128>)> = src
+            .active_ticks
+            .iter()
+            .map(|t| (t.tick, t.liquidity_net))
+            .collect();
+        let expected: Vec<(I24, Option<i128>)> = [-15360, 60, 15300]
+            .into_iter()
+            .map(|t| (I24::try_from(t).unwrap(), Some(7)))
+            .collect();
+        assert_eq!(ticks, expected);
+    }
+
+    #[tokio::test]
+    async fn v2_src_loads_pair() {
+        use alloy::sol_types::{SolCall, SolValue};
+
+        let pancake = alloy::primitives::address!("0xcA143Ce32Fe78f1f7019d7d551a6402fC5350c73");
+        let biswap = alloy::primitives::address!("0x858E3312ed3A876947EA49d572A7C42DE08af7EE");
+        let node = fake_contracts(move |to, selector, _| {
+            let result = match selector {
+                // 0x77.. is a PancakeSwap pair, 0x88.. and 0x99.. BiSwap pairs
+                IUniswapV2Pair::factoryCall::SELECTOR => match to.0[0] {
+                    0x77 => pancake.abi_encode(),
+                    0x88 | 0x99 => biswap.abi_encode(),
+                    _ => Address::with_last_byte(0xfa).abi_encode(),
+                },
+                IUniswapV2Pair::swapFeeCall::SELECTOR => match to.0[0] {
+                    0x88 => U256::from(2).abi_encode(),
+                    _ => U256::from(1001).abi_encode(),
+                },
+                IUniswapV2Pair::token0Call::SELECTOR => Address::with_last_byte(1).abi_encode(),
+                IUniswapV2Pair::token1Call::SELECTOR => Address::with_last_byte(2).abi_encode(),
+                IUniswapV2Pair::getReservesCall::SELECTOR => {
+                    (1_000_000u128, 2_000_000u128, 1_700_000_000u32).abi_encode()
+                }
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+
+        let transport = rpc::RpcConfig::new(vec![node]).transport().unwrap();
+        let pair = Address::repeat_byte(0x66);
+        let mut src = v2_pool_src::V2PoolSrc::new(pair, transport.provider())
+            .await
+            .unwrap();
+        assert_eq!(transport.count().requests, 4);
+        assert_eq!(src.factory, Address::with_last_byte(0xfa));
+        assert_eq!(src.token0, Address::with_last_byte(1));
+        assert_eq!(src.token1, Address::with_last_byte(2));
+        assert_eq!(src.reserves0, U256::from(1_000_000));
+        assert_eq!(src.reserves1, U256::from(2_000_000));
+        assert_eq!(src.block_timestamp_last, 1_700_000_000);
+
+        // 997 in after the fee: 997 * 2_000_000 / (1_000_000 + 997)
+        let trade = src.into_sim().trade(U256::from(1000), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(1992));
+        assert_eq!(trade.fee, alloy::primitives::aliases::U24::from(3000));
+        // the fee is scaled, not taken off the input first, so one wei still buys something
+        let trade = src.into_sim().trade(U256::ONE, true).unwrap();
+        assert_eq!(trade.amount_out, U256::ONE);
+
+        assert!(!src.apply_fees(&v2_fees::FeeRegistry::with_known()));
+        let mut registry = v2_fees::FeeRegistry::new();
+        registry.insert(src.factory, "pancakeswap", v2_fees::V2Fee::new(25, 10_000));
+        assert!(src.apply_fees(&registry));
+        assert_eq!(src.exchange, "pancakeswap");
+        let trade = src.into_sim().trade(U256::from(1000), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(1993));
+
+        // pairs of known factories load with their fee
+        let src = v2_pool_src::V2PoolSrc::new(Address::repeat_byte(0x77), transport.provider())
+            .await
+            .unwrap();
+        assert_eq!(src.exchange, "pancakeswap");
+        assert_eq!((src.fee, src.fee_denominator), (25, 10_000));
+
+        // and BiSwap pairs read their own fee on every update
+        let before = transport.count();
+        let mut src = v2_pool_src::V2PoolSrc::new(Address::repeat_byte(0x88), transport.provider())
+            .await
+            .unwrap();
+        assert_eq!(transport.count().since(&before).requests, 5);
+        assert_eq!(
+            (src.fee, src.fee_denominator, src.pair_fee),
+            (2, 1_000, true)
+        );
+        src.update().await.unwrap();
+        assert_eq!(transport.count().since(&before).requests, 7);
+        // 1000 * 998 * 2_000_000 / (1_000_000 * 1000 + 1000 * 998)
+        let trade = src.into_sim().trade(U256::from(1000), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(1994));
+
+        let over = v2_pool_src::V2PoolSrc::new(Address::repeat_byte(0x99), transport.provider());
+        assert!(matches!(over.await, Err(err::PoolError::Unsupported { .. })));
+    }
+
+    #[tokio::test]
+    async fn v2_factory_discovers_pairs() {
+        use alloy::primitives::{B256, Bytes};
+        use alloy::sol_types::{SolCall, SolEvent, SolValue};
+
+        let factory = Address::repeat_byte(0xfa);
+        let token = Address::with_last_byte;
+        // pair i holds tokens 2i+1 and 2i+2
+        let pair = |i: u8| Address::repeat_byte(0x10 + i);
+        let node = fake_node(move |_, request| {
+            if request["method"] == "eth_getLogs" {
+                if request["params"][0]["fromBlock"] != "0x64" {
+                    return FakeReply::Result(serde_json::json!([]));
+                }
+                return FakeReply::Result(serde_json::json!([{
+                    "address": factory,
+                    "topics": [
+                        IUniswapV2Factory::PairCreated::SIGNATURE_HASH,
+                        token(5).into_word(),
+                        token(6).into_word(),
+                    ],
+                    "data": Bytes::from((pair(2), U256::from(3)).abi_encode()),
+                    "blockNumber": "0x70",
+                    "blockHash": B256::repeat_byte(1),
+                    "transactionHash": B256::repeat_byte(2),
+                    "transactionIndex": "0x0",
+                    "logIndex": "0x0",
+                    "removed": false,
+                }]));
+            }
+            let (to, selector, args) = eth_call(request);
+            let index = to.0[0].wrapping_sub(0x10);
+            let result = match selector {
+                IUniswapV2Factory::allPairsLengthCall::SELECTOR => U256::from(3).abi_encode(),
+                IUniswapV2Factory::allPairsCall::SELECTOR => {
+                    let i = IUniswapV2Factory::allPairsCall::abi_decode_raw(&args)
+                        .unwrap()
+                        .index;
+                    pair(i.to()).abi_encode()
+                }
+                IUniswapV2Factory::getPairCall::SELECTOR => {
+                    let call = IUniswapV2Factory::getPairCall::abi_decode_raw(&args).unwrap();
+                    match (call.tokenA, call.tokenB) {
+                        (a, b) if a == token(4) && b == token(3) => pair(1).abi_encode(),
+                        _ => Address::ZERO.abi_encode(),
+                    }
+                }
+                IUniswapV2Pair::token0Call::SELECTOR => token(2 * index + 1).abi_encode(),
+                IUniswapV2Pair::token1Call::SELECTOR => token(2 * index + 2).abi_encode(),
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+
+        let transport = rpc::RpcConfig::new(vec![node]).transport().unwrap();
+        let mut fees = v2_fees::FeeRegistry::new();
+        fees.insert(factory, "biswap", v2_fees::V2Fee::new(1, 1_000));
+        let factory_src = v2_factory::V2Factory::new(factory, transport.provider(), &fees, 2);
+        let block = alloy::eips::BlockId::latest();
+
+        let pairs = factory_src.all_pairs(block).await.unwrap();
+        let found: Vec<_> = pairs
+            .iter()
+            .map(|p| (p.address, p.factory, p.token0, p.token1))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (pair(0), factory, token(1), token(2)),
+                (pair(1), factory, token(3), token(4)),
+                (pair(2), factory, token(5), token(6)),
+            ]
+        );
+        // the length, three indices and two tokens per pair
+        assert_eq!(transport.count().by_method["eth_call"], 10);
+        assert!(pairs.iter().all(|p| p.reserves0.is_zero()));
+        // named and priced by the registry entry of their factory
+        assert!(
+            pairs
+                .iter()
+                .all(|p| (p.exchange.as_str(), p.fee, p.fee_denominator) == ("biswap", 1, 1_000))
+        );
+
+        // either token order finds the pair, tokens come back sorted
+        let found = factory_src
+            .get_pair(token(4), token(3), block)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (found.address, found.token0, found.token1, found.fee),
+            (pair(1), token(3), token(4), 1)
+        );
+        assert!(
+            factory_src
+                .get_pair(token(1), token(9), block)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let before = transport.count();
+        let created = factory_src.created_pairs(0, 149, 100).await.unwrap();
+        assert_eq!(transport.count().since(&before).by_method["eth_getLogs"], 2);
+        assert_eq!(created.len(), 1);
+        assert_eq!(
+            (
+                created[0].address,
+                created[0].token0,
+                created[0].token1,
+                created[0].fee
+            ),
+            (pair(2), token(5), token(6), 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn v4_src_reads_state_view() {
+        use alloy::primitives::{
+            B256,
+            aliases::{I24, U24},
+        };
+        use alloy::sol_types::{SolCall, SolValue};
+
+        // ETH/USDC 0.05% on mainnet
+        let key = PoolKey {
+            currency0: Address::ZERO,
+            currency1: Address::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap(),
+            fee: U24::from(500),
+            tickSpacing: I24::try_from(10).unwrap(),
+            hooks: Address::ZERO,
+        };
+        let id = v4_pool_src::pool_id(&key);
+        assert_eq!(
+            id,
+            B256::from_str("0x21c67e77068de97969ba93d4aab21826d33ca12bb9f565d8496e8fda8a82ca27")
+                .unwrap()
+        );
+
+        // initialized at tick 0 with ticks 10 and -2560 (bit 0 of word -1),
+        // anything else is a pool that was never initialized
+        let state_view = Address::repeat_byte(0x4f);
+        let node = fake_contracts(move |to, selector, args| {
+            assert_eq!(to, state_view);
+            let known = args[..32] == id[..];
+            let result = match selector {
+                StateView::getSlot0Call::SELECTOR => {
+                    let price = if known { U256::ONE << 96 } else { U256::ZERO };
+                    (price, 0i32, 0u32, 500u32).abi_encode()
+                }
+                StateView::getLiquidityCall::SELECTOR => 1000u128.abi_encode(),
+                StateView::getTickBitmapCall::SELECTOR => {
+                    let call = StateView::getTickBitmapCall::abi_decode_raw(args).unwrap();
+                    match call.tick {
+                        0 => U256::from(2).abi_encode(),
+                        -1 => U256::ONE.abi_encode(),
+                        _ => U256::ZERO.abi_encode(),
+                    }
+                }
+                StateView::getTickLiquidityCall::SELECTOR => {
+                    let call = StateView::getTickLiquidityCall::abi_decode_raw(args).unwrap();
+                    let net = if call.tick > I24::ZERO { -5 } else { 5 };
+                    (5u128, net as i128).abi_encode()
+                }
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node]).connect().unwrap();
+
+        let src = v4_pool_src::V4PoolSrc::new(key.clone(), state_view, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(src.liquidity, U256::from(1000));
+        assert_eq!(src.lp_fee, U24::from(500));
+        assert!(!src.permissions().affects_swap());
+        let ticks: Vec<(i32, Option<i128>)> = src
+            .active_ticks
+            .iter()
+            .map(|t| (t.tick.as_i32(), t.liquidity_net))
+            .collect();
+        assert_eq!(ticks, vec![(-2560, Some(5)), (10, Some(-5))]);
+
+        let sim = src.into_sim().pool;
+        assert_eq!(sim.address, Address::from_word(id));
+        assert_eq!((sim.token0, sim.token1), (key.currency0, key.currency1));
+        assert_eq!(sim.fee, U24::from(500));
+        assert_eq!(sim.tick_spacing, key.tickSpacing);
+
+        let missing = PoolKey {
+            fee: U24::from(3000),
+            ..key
+        };
+        let err = v4_pool_src::V4PoolSrc::new(missing, state_view, provider)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, err::PoolError::Unsupported { .. }), "{err}");
+    }
+
+    #[tokio::test]
+    async fn curve_src_loads_and_quotes_stableswap() {
+        use alloy::sol_types::{SolCall, SolValue};
+        use curve_pool_sim::get_d;
+
+        // USDC (6 decimals) and DAI with A = 200, a 0.04% fee and half of it
+        // to the admin, on a pool that predates `A_precise` and `stored_rates`
+        let node = fake_contracts(move |to, selector, args| {
+            let arg = || U256::from_be_slice(&args[..32]);
+            let result = match selector {
+                ICurveStableSwap::coinsCall::SELECTOR if arg() < U256::from(2) => arg() + U256::ONE,
+                ICurveStableSwap::balancesCall::SELECTOR if arg() == U256::ZERO => {
+                    U256::from(1_000_000_000_000u64)
+                }
+                ICurveStableSwap::balancesCall::SELECTOR => {
+                    U256::from(1_200_000u64) * U256::from(10).pow(U256::from(18))
+                }
+                ICurveStableSwap::ACall::SELECTOR => U256::from(200),
+                ICurveStableSwap::feeCall::SELECTOR => U256::from(4_000_000),
+                ICurveStableSwap::admin_feeCall::SELECTOR => U256::from(5_000_000_000u64),
+                IERC20Decimals::decimalsCall::SELECTOR if to == Address::with_last_byte(1) => {
+                    U256::from(6)
+                }
+                IERC20Decimals::decimalsCall::SELECTOR => U256::from(18),
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result.abi_encode())
+        })
+        .await;
+
+        let transport = rpc::RpcConfig::new(vec![node]).transport().unwrap();
+        let pool = Address::repeat_byte(0xc0);
+        let src = curve_pool_src::CurvePoolSrc::new(pool, transport.provider())
+            .await
+            .unwrap();
+        assert_eq!(
+            src.coins,
+            vec![Address::with_last_byte(1), Address::with_last_byte(2)]
+        );
+        assert_eq!(src.base_pool, None);
+        assert_eq!(src.amp, U256::from(20_000));
+        let e18 = U256::from(10).pow(U256::from(18));
+        assert_eq!(src.rates, vec![e18 * U256::from(1_000_000_000_000u64), e18]);
+
+        // a balanced pool's invariant is its sum
+        let e24 = e18 * U256::from(1_000_000);
+        assert_eq!(get_d(&[e24, e24], src.amp).unwrap(), e24 * U256::from(2));
+
+        let mut sim = src.into_sim();
+        let dx = U256::from(10_000_000_000u64);
+        let dy = U256::from_str("10004722386888889552827").unwrap();
+        assert_eq!(sim.get_dy(0, 1, dx).unwrap(), dy);
+        assert_eq!(
+            sim.get_dy(1, 0, U256::from(10_000) * e18).unwrap(),
+            U256::from(9_986_336_495u64)
+        );
+        let trade = sim.trade(dx, true).unwrap();
+        assert_eq!(trade.amount_out, dy);
+        assert_eq!(trade.fee, alloy::primitives::aliases::U24::from(400));
+        let admin_fee = U256::from(2_001_745_175_447_957_093u64);
+        assert_eq!(
+            sim.balances,
+            vec![
+                U256::from(1_010_000_000_000u64),
+                U256::from(1_200_000) * e18 - dy - admin_fee
+            ]
+        );
+
+        assert!(matches!(
+            sim.get_dy(0, 0, dx),
+            Err(err::PoolError::Unsupported { .. })
+        ));
+        let any = v_pool_src::AnyPoolSrc::Curve(src);
+        assert_eq!(any.get_address(), pool);
+        assert!(matches!(any.into_sim(), v_pool_sim::AnyPoolSim::Curve(_)));
+    }
+
+    #[tokio::test]
+    async fn curve_crypto_quotes_twocrypto_and_tricrypto() {
+        use alloy::primitives::aliases::U24;
+        use alloy::sol_types::{SolCall, SolValue};
+        use curve_crypto_pool_sim::{CurveCryptoPoolSim, newton_d};
+
+        let e18 = U256::from(10).pow(U256::from(18));
+        let int = |s: &str| U256::from_str(s).unwrap();
+
+        // USDC / WETH priced at 2000 with the pool leaning to USDC
+        let ng_pool = Address::repeat_byte(0xc4);
+        // the same after a ramp, its stored `D` is stale
+        let ramped = Address::repeat_byte(0xc5);
+        let node = fake_contracts(move |to, selector, args| {
+            let arg = || U256::from_be_slice(&args[..32]);
+            let result = match selector {
+                ICurveCryptoSwap::coinsCall::SELECTOR if arg() < U256::from(2) => arg() + U256::ONE,
+                ICurveCryptoSwap::balancesCall::SELECTOR if arg().is_zero() => {
+                    U256::from(5_000_000_000_000u64)
+                }
+                ICurveCryptoSwap::balancesCall::SELECTOR => U256::from(2_400) * e18,
+                ICurveCryptoSwap::price_scaleCall::SELECTOR => U256::from(2_000) * e18,
+                ICurveCryptoSwap::ACall::SELECTOR => U256::from(400_000),
+                ICurveCryptoSwap::gammaCall::SELECTOR => U256::from(145_000_000_000_000u64),
+                ICurveCryptoSwap::DCall::SELECTOR if to == ramped => U256::ONE,
+                ICurveCryptoSwap::DCall::SELECTOR => int("9799370409166293824599101"),
+                ICurveCryptoSwap::mid_feeCall::SELECTOR => U256::from(26_000_000),
+                ICurveCryptoSwap::out_feeCall::SELECTOR => U256::from(45_000_000),
+                ICurveCryptoSwap::fee_gammaCall::SELECTOR => U256::from(230_000_000_000_000u64),
+                ICurveCryptoSwap::future_A_gamma_timeCall::SELECTOR if to == ramped => U256::ONE,
+                ICurveCryptoSwap::future_A_gamma_timeCall::SELECTOR => U256::ZERO,
+                ICurveCryptoSwap::MATHCall::SELECTOR if to == ng_pool => U256::from(0xc4),
+                IERC20Decimals::decimalsCall::SELECTOR if to == Address::with_last_byte(1) => {
+                    U256::from(6)
+                }
+                IERC20Decimals::decimalsCall::SELECTOR => U256::from(18),
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result.abi_encode())
+        })
+        .await;
+
+        let transport = rpc::RpcConfig::new(vec![node]).transport().unwrap();
+        let pool = Address::repeat_byte(0xc2);
+        let src = curve_crypto_pool_src::CurveCryptoPoolSrc::new(pool, transport.provider())
+            .await
+            .unwrap();
+        assert_eq!(
+            src.precisions,
+            vec![U256::from(1_000_000_000_000u64), U256::ONE]
+        );
+        assert_eq!(src.price_scale, vec![U256::from(2_000) * e18]);
+
+        let mut sim = src.into_sim();
+        let xp = sim.xp(&sim.balances).unwrap();
+        assert_eq!(newton_d(sim.ann, sim.gamma, &xp).unwrap(), sim.d);
+        assert_eq!(sim.get_dy(1, 0, e18).unwrap(), U256::from(2_033_399_207u64));
+
+        let trade = sim.trade(U256::from(2_000_000_000u64), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(975_232_159_745_700_588u64));
+        assert_eq!(trade.fee, U24::from(3841));
+        assert_eq!(sim.balances[0], U256::from(5_002_000_000_000u64));
+        assert_eq!(sim.d, int("9799378012432279020260967"));
+
+        // the same state in a -ng pool, quoted through the closed form `get_y`
+        let ng = curve_crypto_pool_src::CurveCryptoPoolSrc::new(ng_pool, transport.provider())
+            .await
+            .unwrap();
+        assert!(ng.ng && !src.ng);
+        let mut ng = ng.into_sim();
+        assert_eq!(ng.get_dy(1, 0, e18).unwrap(), U256::from(2_033_399_207u64));
+        let trade = ng.trade(U256::from(2_000_000_000u64), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(975_232_159_745_703_528u64));
+
+        // `future_A_gamma_time` stays 1 once a ramp is over, `D` is solved
+        // for again from the balances without asking for the block's time
+        let before = transport.count();
+        let ramped = curve_crypto_pool_src::CurveCryptoPoolSrc::new(ramped, transport.provider())
+            .await
+            .unwrap();
+        assert_eq!(ramped.d, int("9799370409166293824599101"));
+        let cost = transport.count().since(&before);
+        assert_eq!(cost.requests, cost.by_method["eth_call"]);
+
+        // USDT / WBTC / WETH, balanced at 30000 and 2000
+        let ann = U256::from(1_707_629);
+        let gamma = U256::from(11_809_167_828_997u64);
+        let mut tricrypto = CurveCryptoPoolSim {
+            address: Address::repeat_byte(0xc3),
+            coins: (1..=3).map(Address::with_last_byte).collect(),
+            balances: vec![
+                U256::from(10_000_000_000_000u64),
+                U256::from(33_300_000_000u64),
+                U256::from(5_000) * e18,
+            ],
+            precisions: vec![
+                U256::from(1_000_000_000_000u64),
+                U256::from(10_000_000_000u64),
+                U256::ONE,
+            ],
+            price_scale: vec![U256::from(30_000) * e18, U256::from(2_000) * e18],
+            ann,
+            gamma,
+            d: U256::ZERO,
+            mid_fee: U256::from(3_000_000),
+            out_fee: U256::from(30_000_000),
+            fee_gamma: U256::from(500_000_000_000_000u64),
+            ng: false,
+        };
+        let xp = tricrypto.xp(&tricrypto.balances).unwrap();
+        tricrypto.d = newton_d(ann, gamma, &xp).unwrap();
+        assert_eq!(tricrypto.d, int("29989999939238717340740592"));
+        assert_eq!(
+            tricrypto
+                .get_dy(0, 2, U256::from(100_000_000_000u64))
+                .unwrap(),
+            int("49764771081611250534")
+        );
+        let mut tricrypto_ng = CurveCryptoPoolSim {
+            ng: true,
+            ..tricrypto.clone()
+        };
+        assert_eq!(
+            tricrypto_ng
+                .get_dy(0, 2, U256::from(100_000_000_000u64))
+                .unwrap(),
+            int("49764771081611256813")
+        );
+        let trade = tricrypto_ng
+            .trade_coins(2, 1, U256::from(10) * e18)
+            .unwrap();
+        assert_eq!(trade.amount_out, U256::from(66_636_421));
+        let trade = tricrypto.trade_coins(2, 1, U256::from(10) * e18).unwrap();
+        assert_eq!(trade.amount_out, U256::from(66_636_421));
+        assert_eq!(trade.token0, Address::with_last_byte(3));
+        assert_eq!(trade.fee, U24::from(333));
+
+        assert!(matches!(
+            tricrypto.get_dy(1, 3, e18),
+            Err(err::PoolError::Unsupported { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn balancer_weighted_and_stable_quotes() {
+        use alloy::primitives::{B256, aliases::U24};
+        use alloy::sol_types::SolCall;
+        use balancer_math::{ONE, log_exp};
+        use balancer_pool_sim::{BalancerKind, BalancerPoolSim};
+
+        let e = |n: u64, decimals: u8| U256::from(n) * U256::from(10).pow(U256::from(decimals));
+
+        // fixed point pow against f64: 2^2.5 and 0.95^1.5 (the 36 decimal ln)
+        assert_eq!(
+            log_exp::pow(e(2, 18), e(25, 17)).unwrap(),
+            U256::from(5_656_854_249_492_380_181u64)
+        );
+        assert_eq!(
+            log_exp::pow(e(95, 16), e(15, 17)).unwrap(),
+            U256::from(925_945_462_756_851_571u64)
+        );
+
+        // 80/20 BAL / WETH with a 1% fee on a pool without `getScalingFactors`
+        let vault = Address::repeat_byte(0xba);
+        let pool = Address::repeat_byte(0xb1);
+        let pool_id = B256::repeat_byte(0x11);
+        let node = fake_contracts(move |to, selector, _| {
+            let result = match selector {
+                IBalancerPool::getPoolIdCall::SELECTOR => {
+                    IBalancerPool::getPoolIdCall::abi_encode_returns(&pool_id)
+                }
+                IBalancerPool::getVaultCall::SELECTOR => {
+                    IBalancerPool::getVaultCall::abi_encode_returns(&vault)
+                }
+                IBalancerVault::getPoolTokensCall::SELECTOR => {
+                    assert_eq!(to, vault);
+                    IBalancerVault::getPoolTokensCall::abi_encode_returns(
+                        &IBalancerVault::getPoolTokensReturn {
+                            tokens: vec![Address::with_last_byte(1), Address::with_last_byte(2)],
+                            balances: vec![e(1_000_000, 18), e(2_000, 18)],
+                            lastChangeBlock: U256::from(1),
+                        },
+                    )
+                }
+                IBalancerPool::getNormalizedWeightsCall::SELECTOR => {
+                    IBalancerPool::getNormalizedWeightsCall::abi_encode_returns(&vec![
+                        e(8, 17),
+                        e(2, 17),
+                    ])
+                }
+                IBalancerPool::getSwapFeePercentageCall::SELECTOR => {
+                    IBalancerPool::getSwapFeePercentageCall::abi_encode_returns(&e(1, 16))
+                }
+                IERC20Decimals::decimalsCall::SELECTOR => {
+                    IERC20Decimals::decimalsCall::abi_encode_returns(&18)
+                }
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+
+        let transport = rpc::RpcConfig::new(vec![node]).transport().unwrap();
+        let mut src = balancer_pool_src::BalancerPoolSrc::new(pool, transport.provider())
+            .await
+            .unwrap();
+        assert_eq!(src.pool_id, pool_id);
+        assert_eq!(src.scaling_factors, vec![ONE, ONE]);
+        assert_eq!(
+            src.kind,
+            BalancerKind::Weighted {
+                weights: vec![e(8, 17), e(2, 17)]
+            }
+        );
+        // updates read the balances, weights and fee, decimals stay
+        let before = transport.count();
+        src.update().await.unwrap();
+        assert_eq!(transport.count().since(&before).requests, 3);
+        assert_eq!(src.scaling_factors, vec![ONE, ONE]);
+
+        let mut sim = src.into_sim();
+        assert_eq!(
+            sim.in_given_out(0, 1, ONE).unwrap(),
+            U256::from_str("126302098145851515152").unwrap()
+        );
+        let trade = sim.trade(e(1_000, 18), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(7_900_436_744_824_630_000u64));
+        assert_eq!(trade.fee, U24::from(10_000));
+        assert_eq!(sim.balances[0], e(1_001_000, 18));
+        // more than 30% of the balance in is refused like on chain
+        assert!(matches!(
+            sim.out_given_in(1, 0, e(700, 18)),
+            Err(err::PoolError::Math(err::MathError::Unsafe { .. }))
+        ));
+
+        // USDC / USDT / DAI at A = 2000 with a 0.01% fee
+        let stable = BalancerPoolSim {
+            address: Address::repeat_byte(0xb2),
+            pool_id: B256::repeat_byte(0x22),
+            tokens: (1..=3).map(Address::with_last_byte).collect(),
+            balances: vec![e(30_000_000, 6), e(25_000_000, 6), e(40_000_000, 18)],
+            scaling_factors: vec![e(1, 30), e(1, 30), ONE],
+            swap_fee: e(1, 14),
+            kind: BalancerKind::Stable {
+                amp: U256::from(2_000_000),
+            },
+        };
+        assert_eq!(
+            stable.out_given_in(0, 2, e(1_000_000, 6)).unwrap(),
+            U256::from_str("1000024690660123155746699").unwrap()
+        );
+        assert_eq!(
+            stable.in_given_out(2, 1, e(500_000, 6)).unwrap(),
+            U256::from_str("500180858149661970947331").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn algebra_src_reads_global_state_and_fees() {
+        use alloy::primitives::aliases::{I24, U24};
+        use alloy::primitives::{B256, I256, LogData};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::{SolCall, SolEvent};
+
+        let tick = |t: i32| I24::try_from(t).unwrap();
+        let signed = |v: i64| I256::try_from(v).unwrap().into_raw();
+        let liquidity = 10u128.pow(18);
+        let below = tick_math::price_from_tick(tick(-1)).unwrap();
+
+        // a V1 pool without `tickSpacing` and a 1.9 pool with a fee each way,
+        // both with liquidity between ticks -600 and 600, and an Integral pool
+        let v1 = Address::repeat_byte(0xa1);
+        let directional = Address::repeat_byte(0xa2);
+        let integral = Address::repeat_byte(0xa3);
+        let node = fake_contracts(move |to, selector, args| {
+            let words: Vec<U256> = match selector {
+                IAlgebraPool::token0Call::SELECTOR => {
+                    vec![Address::with_last_byte(1).into_word().into()]
+                }
+                IAlgebraPool::token1Call::SELECTOR => {
+                    vec![Address::with_last_byte(2).into_word().into()]
+                }
+                IAlgebraPool::tickSpacingCall::SELECTOR if to != v1 => vec![U256::from(60)],
+                IAlgebraPool::liquidityCall::SELECTOR => vec![U256::from(liquidity)],
+                IAlgebraPool::globalStateCall::SELECTOR if to == v1 => {
+                    let mut state = vec![U256::ONE << 96, U256::ZERO, U256::from(500)];
+                    state.extend([U256::ZERO, U256::ZERO, U256::ZERO, U256::ONE]);
+                    state
+                }
+                IAlgebraPool::globalStateCall::SELECTOR if to == integral => {
+                    let mut state = vec![U256::ONE << 96, U256::ZERO, U256::from(500)];
+                    state.extend([U256::ZERO, U256::ZERO, U256::ONE]);
+                    state
+                }
+                IAlgebraPool::globalStateCall::SELECTOR => {
+                    let mut state = vec![below, signed(-1), U256::from(100), U256::from(3000)];
+                    state.extend([U256::ZERO, U256::ZERO, U256::ZERO, U256::ONE]);
+                    state
+                }
+                IAlgebraPool::tickTableCall::SELECTOR => {
+                    match IAlgebraPool::tickTableCall::abi_decode_raw(args)
+                        .unwrap()
+                        .wordPosition
+                    {
+                        0 => vec![U256::ONE << 10],
+                        -1 => vec![U256::ONE << 246],
+                        _ => vec![U256::ZERO],
+                    }
+                }
+                IAlgebraPool::ticksCall::SELECTOR => {
+                    let t = IAlgebraPool::ticksCall::abi_decode_raw(args).unwrap().tick;
+                    let delta = if t < I24::ZERO {
+                        liquidity as i64
+                    } else {
+                        -(liquidity as i64)
+                    };
+                    vec![U256::from(liquidity), signed(delta), U256::ZERO, U256::ZERO]
+                }
+                _ => return FakeReply::revert(),
+            };
+            let data: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes::<32>()).collect();
+            FakeReply::returns(data)
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node])
+            .transport()
+            .unwrap()
+            .provider();
+
+        let src = algebra_pool_src::AlgebraPoolSrc::new(v1, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(src.tick_spacing, tick(60));
+        assert_eq!((src.fee_zto, src.fee_otz), (U24::from(500), U24::from(500)));
+        let loaded: Vec<_> = src
+            .active_ticks
+            .iter()
+            .map(|t| (t.tick, t.liquidity_net))
+            .collect();
+        assert_eq!(
+            loaded,
+            vec![
+                (tick(-600), Some(liquidity as i128)),
+                (tick(600), Some(-(liquidity as i128)))
+            ]
+        );
+
+        // Integral's tick tree isn't the V3 bitmap the ticks are walked on
+        assert!(matches!(
+            algebra_pool_src::AlgebraPoolSrc::new(integral, provider.clone()).await,
+            Err(err::PoolError::Unsupported { .. })
+        ));
+
+        let src = algebra_pool_src::AlgebraPoolSrc::new(directional, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(src.current_tick, tick(-1));
+        assert_eq!(src.x96price, below);
+
+        // each direction is quoted with its own fee on the V3 swap loop
+        let amount = U256::from(10u64.pow(15));
+        let mut sim = src.into_sim();
+        let mut v3 = sim.pool.clone();
+        v3.fee = U24::from(100);
+        let trade = sim.clone().trade(amount, true).unwrap();
+        assert_eq!(trade.fee, U24::from(100));
+        assert_eq!(trade.amount_out, v3.swap(amount, true).unwrap());
+        let mut v3 = sim.pool.clone();
+        v3.fee = U24::from(3000);
+        let trade = sim.trade(amount, false).unwrap();
+        assert_eq!(trade.fee, U24::from(3000));
+        assert_eq!(trade.amount_out, v3.swap(amount, false).unwrap());
+
+        // fee and swap logs keep a synced pool current
+        let log = |index: u64, topics: Vec<B256>, data: Vec<u8>| Log {
+            inner: alloy::primitives::Log {
+                address: directional,
+                data: LogData::new_unchecked(topics, data.into()),
+            },
+            block_number: Some(1),
+            block_hash: Some(B256::with_last_byte(1)),
+            log_index: Some(index),
+            ..Default::default()
+        };
+        let fee = IAlgebraPoolDirectional::Fee {
+            feeZto: 400,
+            feeOtz: 200,
+        };
+        let swap = UniV3Pool::Swap {
+            sender: Address::ZERO,
+            recipient: Address::ZERO,
+            amount0: I256::ZERO,
+            amount1: I256::ZERO,
+            sqrtPriceX96: alloy::primitives::U160::ONE << 96,
+            liquidity,
+            tick: I24::ZERO,
+        };
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track_synced(v_pool_sim::AnyPoolSim::Algebra(src.into_sim()));
+        sync.apply_logs(vec![
+            log(
+                0,
+                vec![IAlgebraPoolDirectional::Fee::SIGNATURE_HASH],
+                fee.encode_data(),
+            ),
+            log(
+                1,
+                vec![UniV3Pool::Swap::SIGNATURE_HASH, B256::ZERO, B256::ZERO],
+                swap.encode_data(),
+            ),
+        ]);
+        let Some(v_pool_sim::AnyPoolSim::Algebra(synced)) = sync.pool(&directional) else {
+            panic!("pool not tracked");
+        };
+        assert_eq!(
+            (synced.fee_zto, synced.fee_otz),
+            (U24::from(400), U24::from(200))
+        );
+        assert_eq!(synced.pool.current_tick, I24::ZERO);
+        assert_eq!(synced.pool.x96price, U256::ONE << 96);
+    }
+
+    #[tokio::test]
+    async fn v3_forks_detect_pancake_and_derive_pools() {
+        use alloy::primitives::aliases::U24;
+        use alloy::primitives::{B256, LogData, U160};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::{SolCall, SolEvent, SolValue};
+        use v3_forks::{KNOWN_DEPLOYMENTS, V3Deployment, V3Fork};
+
+        let weth = Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap();
+        let usdc = Address::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
+        assert_eq!(
+            KNOWN_DEPLOYMENTS[0].pool_address(weth, usdc, U24::from(500)),
+            Address::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640").unwrap()
+        );
+        let wbnb = Address::from_str("0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c").unwrap();
+        let usdt = Address::from_str("0x55d398326f99059fF775485246999027B3197955").unwrap();
+        let pancake = V3Deployment::by_factory(&KNOWN_DEPLOYMENTS[2].factory).unwrap();
+        assert_eq!(pancake.fork, V3Fork::PancakeSwap);
+        assert_eq!(
+            pancake.pool_address(usdt, wbnb, U24::from(500)),
+            Address::from_str("0x36696169C63e42cd08ce11f5deeBbCeBae652050").unwrap()
+        );
+
+        // a Pancake pool with a protocol fee past uint8, found by its factory,
+        // and a pool of a factory we don't know that has an LM pool
+        let by_factory = Address::repeat_byte(0xc1);
+        let by_lm_pool = Address::repeat_byte(0xc2);
+        let node = fake_contracts(move |to, selector, _| {
+            let known = to == by_factory;
+            let result = match selector {
+                UniV3Pool::factoryCall::SELECTOR if known => {
+                    KNOWN_DEPLOYMENTS[2].factory.abi_encode()
+                }
+                UniV3Pool::factoryCall::SELECTOR => Address::repeat_byte(0xfa).abi_encode(),
+                PancakeV3Pool::lmPoolCall::SELECTOR if !known => {
+                    Address::repeat_byte(0x1a).abi_encode()
+                }
+                PancakeV3Pool::slot0Call::SELECTOR => {
+                    PancakeV3Pool::slot0Call::abi_encode_returns(&PancakeV3Pool::slot0Return {
+                        sqrtPriceX96: U160::ONE << 96,
+                        tick: Default::default(),
+                        observationIndex: 0,
+                        observationCardinality: 1,
+                        observationCardinalityNext: 1,
+                        feeProtocol: 32_000_320,
+                        unlocked: true,
+                    })
+                }
+                UniV3Pool::tickSpacingCall::SELECTOR => 10i32.abi_encode(),
+                UniV3Pool::liquidityCall::SELECTOR => 1000u128.abi_encode(),
+                UniV3Pool::feeCall::SELECTOR => 500u32.abi_encode(),
+                UniV3Pool::token0Call::SELECTOR => usdt.abi_encode(),
+                UniV3Pool::token1Call::SELECTOR => wbnb.abi_encode(),
+                UniV3Pool::tickBitmapCall::SELECTOR => U256::ZERO.abi_encode(),
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node])
+            .transport()
+            .unwrap()
+            .provider();
+        for pool in [by_factory, by_lm_pool] {
+            let src = v3_pool_src::V3PoolSrc::new(pool, provider.clone())
+                .await
+                .unwrap();
+            assert_eq!(src.fork, V3Fork::PancakeSwap);
+            assert_eq!(src.x96price, U256::ONE << 96);
+        }
+
+        // Pancake swaps carry the protocol fees and still sync like V3's
+        let swap = PancakeV3Pool::Swap {
+            sender: Address::ZERO,
+            recipient: Address::ZERO,
+            amount0: Default::default(),
+            amount1: Default::default(),
+            sqrtPriceX96: U160::ONE << 95,
+            liquidity: 7,
+            tick: Default::default(),
+            protocolFeesToken0: 1,
+            protocolFeesToken1: 0,
+        };
+        let log = Log {
+            inner: alloy::primitives::Log {
+                address: by_factory,
+                data: LogData::new_unchecked(
+                    vec![PancakeV3Pool::Swap::SIGNATURE_HASH, B256::ZERO, B256::ZERO],
+                    swap.encode_data().into(),
+                ),
+            },
+            block_number: Some(1),
+            block_hash: Some(B256::with_last_byte(1)),
+            log_index: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            pool_sync::PoolLog::decode(&log).unwrap().event,
+            pool_sync::PoolEvent::V3Swap {
+                sqrt_price_x96: U256::ONE << 95,
+                liquidity: U256::from(7),
+                tick: Default::default(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn solidly_stable_and_volatile_quotes() {
+        use alloy::primitives::aliases::U24;
+        use alloy::primitives::{B256, LogData};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::{SolCall, SolEvent, SolValue};
+
+        let e = |n: u64, decimals: u8| U256::from(n) * U256::from(10).pow(U256::from(decimals));
+        let usdc = Address::with_last_byte(1);
+        let dai = Address::with_last_byte(2);
+
+        // a USDC / DAI stable pool priced by a Velodrome V2 style factory
+        // and a WETH / USDC volatile pair of an older one with a fee per
+        // kind
+        let stable = Address::repeat_byte(0x5a);
+        let volatile = Address::repeat_byte(0x5b);
+        let factory = Address::repeat_byte(0xfa);
+        let node = fake_contracts(move |to, selector, args| {
+            let result = match selector {
+                ISolidlyPool::factoryCall::SELECTOR => factory.abi_encode(),
+                ISolidlyPool::metadataCall::SELECTOR if to == stable => {
+                    ISolidlyPool::metadataCall::abi_encode_returns(&ISolidlyPool::metadataReturn {
+                        dec0: e(1, 6),
+                        dec1: e(1, 18),
+                        r0: e(5_000_000, 6),
+                        r1: e(4_800_000, 18),
+                        st: true,
+                        t0: usdc,
+                        t1: dai,
+                    })
+                }
+                ISolidlyPool::metadataCall::SELECTOR => {
+                    ISolidlyPool::metadataCall::abi_encode_returns(&ISolidlyPool::metadataReturn {
+                        dec0: e(1, 18),
+                        dec1: e(1, 6),
+                        r0: e(1_000, 18),
+                        r1: e(3_000_000, 6),
+                        st: false,
+                        t0: Address::with_last_byte(3),
+                        t1: usdc,
+                    })
+                }
+                ISolidlyFactory::getFeeCall::SELECTOR => {
+                    let call = ISolidlyFactory::getFeeCall::abi_decode_raw(args).unwrap();
+                    if call.pool != stable {
+                        return FakeReply::revert();
+                    }
+                    U256::from(5).abi_encode()
+                }
+                ISolidlyFactoryV1::getFeeCall::SELECTOR => U256::from(30).abi_encode(),
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node])
+            .transport()
+            .unwrap()
+            .provider();
+
+        let src = solidly_pool_src::SolidlyPoolSrc::new(stable, provider.clone())
+            .await
+            .unwrap();
+        assert!(src.stable);
+        assert_eq!(src.fee, U256::from(5));
+        let mut sim = src.into_sim();
+        assert_eq!(
+            sim.get_amount_out(e(250_000, 18), false).unwrap(),
+            U256::from(249_873_280_678u64)
+        );
+        assert_eq!(
+            sim.get_amount_out(e(1, 6), true).unwrap(),
+            U256::from(999_483_008_670_040_862u64)
+        );
+        let trade = sim.trade(e(100_000, 6), true).unwrap();
+        assert_eq!(
+            trade.amount_out,
+            U256::from_str("99943632341388864201776").unwrap()
+        );
+        assert_eq!(trade.fee, U24::from(500));
+        // the fee goes to the pool's fee contract, not the reserves
+        assert_eq!(sim.reserves0, e(5_000_000, 6) + e(99_950, 6));
+
+        let src = solidly_pool_src::SolidlyPoolSrc::new(volatile, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(src.fee, U256::from(30));
+        let mut sim = src.into_sim();
+        let trade = sim.trade(e(10, 18), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(29_614_741_031u64));
+
+        // reserves follow the pair's uint256 `Sync` logs, the V2 `Swap` after
+        // each one leaves the pair as it is instead of marking it stale
+        let sync_log = |reserve0, reserve1| ISolidlyPool::Sync { reserve0, reserve1 };
+        let swap = IUniswapV2Pair::Swap {
+            sender: Address::ZERO,
+            amount0In: e(10, 18),
+            amount1In: U256::ZERO,
+            amount0Out: U256::ZERO,
+            amount1Out: e(29_614, 6),
+            to: Address::ZERO,
+        };
+        let log = |index: u64, data: LogData| Log {
+            inner: alloy::primitives::Log {
+                address: volatile,
+                data,
+            },
+            block_number: Some(1),
+            block_hash: Some(B256::with_last_byte(1)),
+            log_index: Some(index),
+            ..Default::default()
+        };
+        let logs = vec![
+            log(0, sync_log(e(990, 18), e(3_030_000, 6)).encode_log_data()),
+            log(1, swap.encode_log_data()),
+            log(2, sync_log(e(1_000, 18), e(3_000_386, 6)).encode_log_data()),
+            log(3, swap.encode_log_data()),
+        ];
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track_synced(v_pool_sim::AnyPoolSim::Solidly(sim));
+        sync.apply_logs(logs);
+        let Some(v_pool_sim::AnyPoolSim::Solidly(synced)) = sync.pool(&volatile) else {
+            panic!("pool not tracked");
+        };
+        assert_eq!(
+            (synced.reserves0, synced.reserves1),
+            (e(1_000, 18), e(3_000_386, 6))
+        );
+    }
+
+    #[tokio::test]
+    async fn lb_pair_quotes_and_follows_logs() {
+        use alloy::primitives::aliases::{U24, U40};
+        use alloy::primitives::{B256, LogData};
+        use alloy::rpc::types::Log;
+        use alloy::sol_types::{SolCall, SolEvent, SolValue};
+        use lb_pool_sim::{Bin, LbSwapOut};
+
+        let e18 = |n: u64| U256::from(n) * U256::from(10).pow(U256::from(18));
+        let int = |s: &str| U256::from_str(s).unwrap();
+        let active = 1u32 << 23;
+        let now = 1_700_000_000u64;
+        let bins = [
+            (active - 2, 0, 2_000),
+            (active - 1, 0, 1_500),
+            (active, 1_000, 800),
+            (active + 1, 1_200, 0),
+            (active + 2, 900, 0),
+        ];
+
+        // bins of 25 basis points around the bin priced at 1, the volatility
+        // decaying since 100 seconds ago
+        let pair = Address::repeat_byte(0x1b);
+        let node = fake_node(move |_, request| {
+            if request["method"] == "eth_getBlockByNumber" {
+                let mut block =
+                    alloy::rpc::types::Block::<alloy::rpc::types::Transaction>::default();
+                block.header.inner.timestamp = now;
+                return FakeReply::Result(serde_json::to_value(block).unwrap());
+            }
+            let (_, selector, args) = eth_call(request);
+            let result = match selector {
+                ILBPair::getTokenXCall::SELECTOR => Address::with_last_byte(1).abi_encode(),
+                ILBPair::getTokenYCall::SELECTOR => Address::with_last_byte(2).abi_encode(),
+                ILBPair::getBinStepCall::SELECTOR => U256::from(25).abi_encode(),
+                ILBPair::getActiveIdCall::SELECTOR => U256::from(active).abi_encode(),
+                ILBPair::getStaticFeeParametersCall::SELECTOR => {
+                    ILBPair::getStaticFeeParametersCall::abi_encode_returns(
+                        &ILBPair::getStaticFeeParametersReturn {
+                            baseFactor: 5_000,
+                            filterPeriod: 30,
+                            decayPeriod: 600,
+                            reductionFactor: 5_000,
+                            variableFeeControl: U24::from(40_000),
+                            protocolShare: 1_000,
+                            maxVolatilityAccumulator: U24::from(350_000),
+                        },
+                    )
+                }
+                ILBPair::getVariableFeeParametersCall::SELECTOR => {
+                    ILBPair::getVariableFeeParametersCall::abi_encode_returns(
+                        &ILBPair::getVariableFeeParametersReturn {
+                            volatilityAccumulator: U24::from(20_000),
+                            volatilityReference: U24::from(10_000),
+                            idReference: U24::from(active + 1),
+                            timeOfLastUpdate: U40::from(now - 100),
+                        },
+                    )
+                }
+                ILBPair::getNextNonEmptyBinCall::SELECTOR => {
+                    let call = ILBPair::getNextNonEmptyBinCall::abi_decode_raw(&args).unwrap();
+                    let id: u32 = call.id.to();
+                    let next = match call.swapForY {
+                        true => bins.iter().rev().find(|b| b.0 < id).map_or(0, |b| b.0),
+                        false => bins.iter().find(|b| b.0 > id).map_or(0xffffff, |b| b.0),
+                    };
+                    U256::from(next).abi_encode()
+                }
+                ILBPair::getBinCall::SELECTOR => {
+                    let id: u32 = ILBPair::getBinCall::abi_decode_raw(&args).unwrap().id.to();
+                    let (_, x, y) = bins.iter().find(|b| b.0 == id).unwrap();
+                    (e18(*x), e18(*y)).abi_encode()
+                }
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node])
+            .transport()
+            .unwrap()
+            .provider();
+
+        let src = lb_pool_src::LbPoolSrc::new(pair, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(src.timestamp, now);
+        assert_eq!(src.bins.len(), 5);
+        assert_eq!(src.params.id_reference, active + 1);
+        let sim = src.into_sim();
+        // 0.125% base fee and the variable fee of a volatility halved
+        assert_eq!(sim.fee_pips(), U24::from(1275));
+
+        // X for Y empties the active bin and takes part of the next
+        let mut traded = sim.clone();
+        let quote = traded.get_swap_out(e18(2_000), true).unwrap();
+        assert_eq!(
+            quote,
+            LbSwapOut {
+                amount_in_left: U256::ZERO,
+                amount_out: int("1994374141244550642466"),
+                fee: int("2639923402337980927"),
+            }
+        );
+        let trade = traded.trade(e18(2_000), true).unwrap();
+        assert_eq!(trade.amount_out, quote.amount_out);
+        assert_eq!(traded.active_id, active - 1);
+        // the protocol's tenth of the fee leaves the bins
+        assert_eq!(
+            traded.bins[&active],
+            Bin {
+                reserve_x: int("1800919171944228891838"),
+                reserve_y: U256::ZERO,
+            }
+        );
+        assert_eq!(
+            traded.bins[&(active - 1)],
+            Bin {
+                reserve_x: int("1198816835715537310070"),
+                reserve_y: int("305625858755449357534"),
+            }
+        );
+        assert_eq!(traded.params.volatility_accumulator, 20_000);
+        assert_eq!(traded.params.id_reference, active);
+
+        let mut other_way = sim.clone();
+        let trade = other_way.trade(e18(1_500), false).unwrap();
+        assert_eq!(trade.amount_out, int("1496808075558182068670"));
+        assert_eq!(other_way.active_id, active + 1);
+
+        // past the loaded bins the swap fails rather than pricing on
+        // liquidity that wasn't read
+        assert!(matches!(
+            sim.clone().trade(e18(10_000), false),
+            Err(err::PoolError::OutOfTicks { .. })
+        ));
+
+        // the same swap logged bin by bin lands on the same state
+        let packed = |x: U256, y: U256| B256::from((y << 128) | x);
+        let lb_log = |index: u64, data: LogData, timestamp: Option<u64>| Log {
+            inner: alloy::primitives::Log {
+                address: pair,
+                data,
+            },
+            block_number: Some(1),
+            block_hash: Some(B256::with_last_byte(1)),
+            block_timestamp: timestamp,
+            log_index: Some(index),
+            ..Default::default()
+        };
+        let swap_log = |index: u64, id: u32, amount_in: &str, amount_out: U256, volatility: u32| {
+            let e = ILBPair::Swap {
+                sender: Address::ZERO,
+                to: Address::ZERO,
+                id: U24::from(id),
+                amountsIn: packed(int(amount_in), U256::ZERO),
+                amountsOut: packed(U256::ZERO, amount_out),
+                volatilityAccumulator: U24::from(volatility),
+                totalFees: B256::ZERO,
+                protocolFees: B256::ZERO,
+            };
+            lb_log(index, e.encode_log_data(), Some(now))
+        };
+        let deposit = ILBPair::DepositedToBins {
+            sender: Address::ZERO,
+            to: Address::ZERO,
+            ids: vec![U256::from(active + 1), U256::from(active + 50)],
+            amounts: vec![packed(e18(5), U256::ZERO), packed(e18(5), U256::ZERO)],
+        };
+        let withdrawal = ILBPair::WithdrawnFromBins {
+            sender: Address::ZERO,
+            to: Address::ZERO,
+            ids: vec![U256::from(active + 2)],
+            amounts: vec![packed(e18(100), U256::ZERO)],
+        };
+        let logs = vec![
+            swap_log(0, active, "800919171944228891838", e18(800), 10_000),
+            swap_log(
+                1,
+                active - 1,
+                "1198816835715537310070",
+                int("1194374141244550642466"),
+                20_000,
+            ),
+            lb_log(2, deposit.encode_log_data(), None),
+            lb_log(3, withdrawal.encode_log_data(), None),
+        ];
+        let mut sync = pool_sync::PoolSync::new(provider, 1_000, 64);
+        sync.track_synced(v_pool_sim::AnyPoolSim::Lb(sim));
+        sync.apply_logs(logs);
+        let Some(v_pool_sim::AnyPoolSim::Lb(synced)) = sync.pool(&pair) else {
+            panic!("pool not tracked");
+        };
+        assert_eq!(synced.active_id, traded.active_id);
+        assert_eq!(synced.params, traded.params);
+        assert_eq!(synced.bins[&active], traded.bins[&active]);
+        assert_eq!(synced.bins[&(active - 1)], traded.bins[&(active - 1)]);
+        assert_eq!(synced.bins[&(active + 1)].reserve_x, e18(1_205));
+        assert_eq!(synced.bins[&(active + 2)].reserve_x, e18(800));
+        // outside the window nothing is tracked
+        assert!(!synced.bins.contains_key(&(active + 50)));
+
+        // a composition fee moves state the logs don't carry, the pair is
+        // left alone until it is refetched
+        let fees = ILBPair::CompositionFees {
+            sender: Address::ZERO,
+            id: U24::from(active - 1),
+            totalFees: B256::ZERO,
+            protocolFees: B256::ZERO,
+        };
+        sync.apply_logs(vec![
+            lb_log(4, fees.encode_log_data(), None),
+            lb_log(5, withdrawal.encode_log_data(), None),
+        ]);
+        let Some(v_pool_sim::AnyPoolSim::Lb(synced)) = sync.pool(&pair) else {
+            panic!("pool not tracked");
+        };
+        assert_eq!(synced.bins[&(active + 2)].reserve_x, e18(800));
+    }
+
+    #[tokio::test]
+    async fn erc4626_vaults_deposit_and_redeem_like_their_previews() {
+        use alloy::sol_types::{SolCall, SolValue};
+        use erc4626_sim::{Erc4626Sim, VaultMath};
+
+        let e18 = |n: u64| U256::from(n) * U256::from(10).pow(U256::from(18));
+        let int = |s: &str| U256::from_str(s).unwrap();
+        let asset = Address::with_last_byte(0xda);
+        let total_assets = e18(1_050_000) + U256::from(123);
+        let total_supply = e18(1_000_000);
+
+        // the same totals behind an OpenZeppelin vault, a Solmate one and
+        // one taking a deposit fee
+        let oz = Address::repeat_byte(0x46);
+        let solmate = Address::repeat_byte(0x47);
+        let with_fee = Address::repeat_byte(0x48);
+        let node = fake_contracts(move |to, selector, _| {
+            let result = match selector {
+                IERC4626::assetCall::SELECTOR => asset.abi_encode(),
+                IERC4626::decimalsCall::SELECTOR => U256::from(18).abi_encode(),
+                IERC4626::totalAssetsCall::SELECTOR => total_assets.abi_encode(),
+                IERC4626::totalSupplyCall::SELECTOR => total_supply.abi_encode(),
+                IERC4626::previewDepositCall::SELECTOR if to == with_fee => {
+                    e18(999_000).abi_encode()
+                }
+                IERC4626::previewDepositCall::SELECTOR => e18(1_000_000).abi_encode(),
+                IERC4626::previewRedeemCall::SELECTOR if to == oz => {
+                    int("1050000000000000000000122").abi_encode()
+                }
+                IERC4626::previewRedeemCall::SELECTOR => total_assets.abi_encode(),
+                _ => return FakeReply::revert(),
+            };
+            FakeReply::returns(result)
+        })
+        .await;
+        let provider = rpc::RpcConfig::new(vec![node])
+            .transport()
+            .unwrap()
+            .provider();
+
+        let src = erc4626_src::Erc4626Src::new(oz, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(src.math, VaultMath::Virtual { shares: U256::ONE });
+        let plain = erc4626_src::Erc4626Src::new(solmate, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(plain.math, VaultMath::Plain);
+        assert!(matches!(
+            erc4626_src::Erc4626Src::new(with_fee, provider).await,
+            Err(err::PoolError::Unsupported { .. })
+        ));
+
+        // in through the asset, out as shares and back, the vault keeps the
+        // rounding
+        let mut vault = v_pool_sim::AnyPoolSim::Erc4626(src.into_sim());
+        assert_eq!(vault.get_tokens(), [asset, oz]);
+        let deposit = vault.trade(e18(1_000), vault.is_0(&asset)).unwrap();
+        assert_eq!(deposit.amount_out, int("952380952380952380952"));
+        assert_eq!(deposit.fee, alloy::primitives::aliases::U24::ZERO);
+        let redeem = vault.trade(deposit.amount_out, false).unwrap();
+        assert_eq!(redeem.amount_out, int("999999999999999999999"));
+        assert_eq!(
+            plain.into_sim().trade(e18(1_000), true).unwrap().amount_out,
+            int("952380952380952380952")
+        );
+
+        // an empty vault with three decimals of offset still prices shares
+        let mut empty = Erc4626Sim {
+            address: oz,
+            asset,
+            total_assets: U256::ZERO,
+            total_supply: U256::ZERO,
+            math: VaultMath::Virtual {
+                shares: U256::from(1_000),
+            },
+        };
+        assert_eq!(empty.trade(e18(1), true).unwrap().amount_out, e18(1_000));
+        assert!(matches!(
+            empty.trade(e18(1_001), false),
+            Err(err::PoolError::NoLiquidity { .. })
+        ));
+    }
+
+    #[test]
+    fn v4_hooks_gate_and_shape_swaps() {
+        use alloy::primitives::{
+            B256,
+            aliases::{I24, U24},
+        };
+        use std::sync::Arc;
+        use v4_hooks::{BeforeSwap, HookModel, HookPermissions};
+
+        let tick = |tick: i32, net: i128| tick_math::Tick {
+            tick: I24::try_from(tick).unwrap(),
+            liquidity_net: Some(net),
+        };
+        let v3 = v3_pool_sim::V3PoolSim {
+            address: Address::repeat_byte(0x79),
+            token0: Address::ZERO,
+            token1: Address::with_last_byte(2),
+            fee: U24::from(3000),
+            current_tick: I24::ZERO,
+            active_ticks: vec![tick(-6000, 10i128.pow(18)), tick(6000, -(10i128.pow(18)))],
+            tick_spacing: I24::try_from(60).unwrap(),
+            liquidity: U256::from(10u128.pow(18)),
+            x96price: U256::ONE << 96,
+            tax0: Default::default(),
+            tax1: Default::default(),
+        };
+        let amount = U256::from(10u64.pow(12));
+        let plain = |fee: u32, amount: U256| {
+            let mut v3 = v3.clone();
+            v3.fee = U24::from(fee);
+            v3.trade(amount, true).unwrap().amount_out
+        };
+
+        // a hook that only runs after initialize leaves swaps alone
+        let hooks = Address::left_padding_from(&[0xab, 0x10, 0x00]);
+        let permissions = HookPermissions::from_address(hooks);
+        assert!(permissions.contains(HookPermissions::AFTER_INITIALIZE));
+        assert!(!permissions.affects_swap());
+        let mut sim = v4_pool_sim::V4PoolSim {
+            pool: v3.clone(),
+            id: B256::ZERO,
+            hooks,
+            permissions,
+            key_fee: U24::from(v4_hooks::DYNAMIC_FEE_FLAG),
+            lp_fee: U24::from(3000),
+            protocol_fee: U24::ZERO,
+            hook_model: None,
+        };
+        // the dynamic fee comes from slot0
+        let trade = sim.clone().trade(amount, true).unwrap();
+        assert_eq!(trade.fee, U24::from(3000));
+        assert_eq!(trade.amount_out, plain(3000, amount));
+
+        // each direction has its own protocol fee on top
+        sim.protocol_fee = U24::from((200 << 12) | 100);
+        assert_eq!(
+            sim.clone().trade(amount, true).unwrap().fee,
+            U24::from(3100)
+        );
+        assert_eq!(
+            sim.clone().trade(amount, false).unwrap().fee,
+            U24::from(3200)
+        );
+        sim.protocol_fee = U24::ZERO;
+
+        // beforeSwap set: refused until the hook is modeled
+        sim.permissions = HookPermissions(HookPermissions::BEFORE_SWAP);
+        assert!(matches!(
+            sim.clone().trade(amount, true),
+            Err(err::PoolError::Unsupported { .. })
+        ));
+
+        #[derive(Debug)]
+        struct TenPercentHook;
+        impl HookModel for TenPercentHook {
+            fn before_swap(
+                &self,
+                _pool: &v3_pool_sim::V3PoolSim,
+                amount_in: U256,
+                _from0: bool,
+            ) -> Result<BeforeSwap, err::PoolError> {
+                Ok(BeforeSwap {
+                    lp_fee: Some(U24::from(100 | v4_hooks::OVERRIDE_FEE_FLAG)),
+                    amount_taken: amount_in / U256::from(10),
+                })
+            }
+        }
+        let trade = sim
+            .with_hook_model(Arc::new(TenPercentHook))
+            .trade(amount, true)
+            .unwrap();
+        assert_eq!(trade.fee, U24::from(100));
+        assert_eq!(trade.amount_in, amount);
+        assert_eq!(
+            trade.amount_out,
+            plain(100, amount * U256::from(9) / U256::from(10))
+        );
+    }
+
+    #[test]
+    fn v2_fees_follow_the_factory() {
+        use v2_fees::{FeeRegistry, V2Fee};
+
+        let mut registry = FeeRegistry::with_known();
+        let pancake = registry
+            .get(&Address::from_str("0xcA143Ce32Fe78f1f7019d7d551a6402fC5350c73").unwrap())
+            .unwrap();
+        assert_eq!(pancake.fee, V2Fee::new(25, 10_000));
+
+        let factory = Address::with_last_byte(0xfa);
+        registry.insert(factory, "pancakeswap", V2Fee::new(25, 10_000));
+
+        let sim = v2_pool_sim::V2PoolSim::new(
+            "uniswap".into(),
+            "v2".into(),
+            3000,
+            Address::repeat_byte(0x66),
+            Address::with_last_byte(1),
+            Address::with_last_byte(2),
+            U256::from(1_000_000),
+            U256::from(2_000_000),
+        );
+        let dex = registry.get(&factory).unwrap();
+        let mut pancake = sim.clone().with_fee(dex.fee.fee, dex.fee.denominator).unwrap();
+
+        // 1000 * 9975 * 2_000_000 / (1_000_000 * 10_000 + 1000 * 9975)
+        let trade = pancake.trade(U256::from(1000), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(1993));
+        assert_eq!(trade.fee, alloy::primitives::aliases::U24::from(2500));
+        // the whole input stays in the pair, fee included
+        assert_eq!(pancake.reserves0, U256::from(1_001_000));
+        assert_eq!(pancake.reserves1, U256::from(2_000_000 - 1993));
+
+        // a fee has to leave part of the input to swap
+        for (fee, denominator) in [(0, 0), (3, 0), (1000, 1000), (1001, 1000)] {
+            assert!(matches!(
+                sim.clone().with_fee(fee, denominator),
+                Err(err::PoolError::Unsupported { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn v2_exact_output_quotes() {
+        let sim = v2_pool_sim::V2PoolSim::new(
+            "uniswap".into(),
+            "v2".into(),
+            3000,
+            Address::repeat_byte(0x66),
+            Address::with_last_byte(1),
+            Address::with_last_byte(2),
+            U256::from(1_000_000),
+            U256::from(2_000_000),
+        );
+
+        // exact output goes through the same curve: what trade() gives for an
+        // input is reachable with at most that input, and one less falls short
+        for amount_in in [1u64, 1000, 123_457, 999_999] {
+            let sim = sim.clone().with_fee(25, 10_000).unwrap();
+            let out = sim.clone().trade(U256::from(amount_in), false).unwrap().amount_out;
+            if out.is_zero() {
+                continue;
+            }
+            let needed = sim.quote_in(out, false).unwrap();
+            assert!(needed <= U256::from(amount_in));
+            let short = sim.clone().trade(needed - U256::ONE, false).unwrap();
+            assert!(short.amount_out < out);
+        }
+
+        let mut exact = sim.clone();
+        // 2_000_000 * 1000 * 1_000_000 / (999_000 * 997_000) + 1
+        let trade = exact.trade_exact_out(U256::from(1000), false).unwrap();
+        assert_eq!(trade.amount_in, U256::from(2009));
+        assert_eq!(exact.reserves1, U256::from(2_002_009));
+        assert_eq!(exact.reserves0, U256::from(999_000));
+        assert!(matches!(
+            sim.quote_in(U256::from(1_000_000), false),
+            Err(err::PoolError::NoLiquidity { .. })
+        ));
+    }
+
+    #[test]
+    fn token_taxes_apply_to_v2_and_v3() {
+        use alloy::primitives::aliases::{I24, U24};
+        use std::sync::Arc;
+        use token_tax::{TokenTaxes, TransferKind, TransferTax};
+
+        let (token0, token1) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        let mut taxes = TokenTaxes::new();
+        taxes.insert(
+            token0,
+            TransferTax::Percent {
+                buy_bps: 0,
+                sell_bps: 500,
+                transfer_bps: 0,
+            },
+        );
+        taxes.insert(token1, TransferTax::flat(1000));
+
+        let mut v2 = v2_pool_sim::V2PoolSim::new(
+            "uniswap".into(),
+            "v2".into(),
+            3000,
+            Address::repeat_byte(0x77),
+            token0,
+            token1,
+            U256::from(1_000_000),
+            U256::from(2_000_000),
+        );
+        v2.apply_taxes(&taxes);
+
+        // the pair only sees 950 of the 1000 sent, the trader 90% of its output
+        let trade = v2.clone().trade(U256::from(1000), true).unwrap();
+        assert_eq!(trade.amount_in, U256::from(1000));
+        assert_eq!(trade.amount_in_net, U256::from(950));
+        assert_eq!(trade.amount_out, U256::from(1892));
+        assert_eq!(trade.amount_out_net, U256::from(1703));
+
+        // 900 net needs 1000 out of the pair, 502 in and 529 sent for that
+        let mut exact = v2.clone();
+        let trade = exact.trade_exact_out(U256::from(900), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(1000));
+        assert_eq!(trade.amount_out_net, U256::from(900));
+        assert_eq!(trade.amount_in, U256::from(529));
+        assert_eq!(trade.amount_in_net, U256::from(503));
+        assert_eq!(exact.reserves0, U256::from(1_000_503));
+
+        // a custom tax runs for exact input but can't be inverted
+        let mut custom = TokenTaxes::new();
+        custom.insert(
+            token1,
+            TransferTax::Custom(Arc::new(|amount, kind| match kind {
+                TransferKind::Buy => amount / U256::from(2),
+                _ => amount,
+            })),
+        );
+        v2.apply_taxes(&custom);
+        let trade = v2.clone().trade(U256::from(1000), true).unwrap();
+        assert_eq!(trade.amount_out, U256::from(1992));
+        assert_eq!(trade.amount_out_net, U256::from(996));
+        assert!(matches!(
+            v2.trade_exact_out(U256::from(900), true),
+            Err(err::PoolError::Unsupported { .. })
+        ));
+
+        let tick = |tick: i32, net: i128| tick_math::Tick {
+            tick: I24::try_from(tick).unwrap(),
+            liquidity_net: Some(net),
+        };
+        let mut v3 = v3_pool_sim::V3PoolSim {
+            address: Address::repeat_byte(0x78),
+            token0,
+            token1,
+            fee: U24::from(3000),
+            current_tick: I24::ZERO,
+            active_ticks: vec![tick(-6000, 10i128.pow(18)), tick(6000, -(10i128.pow(18)))],
+            tick_spacing: I24::try_from(60).unwrap(),
+            liquidity: U256::from(10u128.pow(18)),
+            x96price: U256::ONE << 96,
+            tax0: Default::default(),
+            tax1: Default::default(),
+        };
+        let untaxed = v3
+            .clone()
+            .trade(U256::from(10u64.pow(9) * 9), false)
+            .unwrap();
+        v3.apply_taxes(&taxes);
+        // token1 pays 10% going in, so the pool swaps what a plain 9e9 would
+        let trade = v3.trade(U256::from(10u64.pow(10)), false).unwrap();
+        assert_eq!(trade.amount_in_net, untaxed.amount_in);
+        assert_eq!(trade.amount_out, untaxed.amount_out);
+        // token0 has no buy tax
+        assert_eq!(trade.amount_out_net, trade.amount_out);
+        assert_eq!(untaxed.amount_in_net, untaxed.amount_in);
+    }
+
+    #[test]
+    fn currencies_tell_native_from_wrapped() {
+        use currency::{Currency, WrapSim};
+
+        let weth = currency::wrapped_native(1).unwrap();
+        let usdc = Address::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48").unwrap();
+        assert_eq!(Currency::from(Address::ZERO), Currency::Native);
+        assert_eq!(Currency::from(weth), Currency::Token(weth));
+        assert_eq!(Currency::Native.address(), Address::ZERO);
+        assert_eq!(currency::wrapped_native(8453), currency::wrapped_native(10));
+
+        // native in, through the wrap edge into a WETH/USDC pair
+        let mut wrap = v_pool_sim::AnyPoolSim::Wrap(WrapSim::for_chain(1).unwrap());
+        let mut pair = v_pool_sim::AnyPoolSim::V2(v2_pool_sim::V2PoolSim::new(
+            "uniswap".into(),
+            "v2".into(),
+            3000,
+            Address::repeat_byte(0x88),
+            usdc,
+            weth,
+            U256::from(2_000_000),
+            U256::from(1_000_000),
+        ));
+        assert_eq!(
+            wrap.get_currencies(),
+            [Currency::Native, Currency::Token(weth)]
+        );
+        assert_eq!(wrap.get_address(), weth);
+
+        let wrapped = wrap
+            .trade(U256::from(1000), wrap.is_0(&Address::ZERO))
+            .unwrap();
+        assert_eq!(wrapped.currency_in(), Currency::Native);
+        assert_eq!(wrapped.currency_out(), Currency::Token(weth));
+        assert_eq!(wrapped.amount_out_net, U256::from(1000));
+        assert_eq!(wrapped.fee, alloy::primitives::aliases::U24::ZERO);
+
+        let sold = pair
+            .trade(wrapped.amount_out_net, pair.is_0(&weth))
+            .unwrap();
+        assert_eq!(sold.currency_in(), wrapped.currency_out());
+        assert_eq!(sold.currency_out(), Currency::Token(usdc));
+        assert_eq!(sold.amount_out, U256::from(1992));
+
+        // and back out of WETH
+        let unwrapped = wrap.trade(U256::from(5), false).unwrap();
+        assert_eq!(unwrapped.currency_out(), Currency::Native);
+        assert_eq!(unwrapped.amount_out, U256::from(5));
+
+        // Curve pools hold native as `NATIVE_COIN`, like ETH/stETH
+        let steth = Address::from_str("0xae7ab96520DE3A18E5e111B5EaAb095312D7fE84").unwrap();
+        let curve = v_pool_sim::AnyPoolSim::Curve(curve_pool_sim::CurvePoolSim {
+            address: Address::repeat_byte(0xdc),
+            coins: vec![curve_pool_src::NATIVE_COIN, steth],
+            balances: vec![U256::ZERO; 2],
+            rates: vec![U256::from(10).pow(U256::from(18)); 2],
+            amp: U256::ZERO,
+            fee: U256::ZERO,
+            admin_fee: U256::ZERO,
+        });
+        assert_eq!(
+            curve.get_currencies(),
+            [Currency::Native, Currency::Token(steth)]
+        );
+        // and trades through them agree
+        let sold = trade::Trade {
+            token0: curve_pool_src::NATIVE_COIN,
+            token1: steth,
+            pool: curve.get_address(),
+            from0: true,
+            ..unwrapped
+        };
+        assert_eq!(sold.currency_in(), Currency::Native);
+        assert_eq!(sold.currency_out(), Currency::Token(steth));
+    }
+
+    #[test]
+    fn sim_errors_name_the_failure() {
+        use alloy::primitives::aliases::{I24, U24};
+        use err::{MathError, PoolError};
+
+        let pool = Address::repeat_byte(0x55);
+        let mut v3 = v3_pool_sim::V3PoolSim {
+            address: pool,
+            token0: Address::repeat_byte(1),
+            token1: Address::repeat_byte(2),
+            fee: U24::from(3000),
+            current_tick: I24::ZERO,
+            active_ticks: vec![],
+            tick_spacing: I24::try_from(60).unwrap(),
+            liquidity: U256::from(10u128.pow(18)),
+            x96price: U256::ONE << 96,
+            tax0: Default::default(),
+            tax1: Default::default(),
+        };
+        let amount = U256::from(10u128.pow(30));
+        assert!(matches!(
+            v3.clone().trade(amount, true),
+            Err(PoolError::OutOfTicks { from0: true, .. })
+        ));
+
+        let tick = I24::try_from(60).unwrap();
+        v3.active_ticks = vec![tick_math::Tick {
+            tick,
+            liquidity_net: None,
+        }];
+        assert!(matches!(
+            v3.trade(amount, true),
+            Err(PoolError::MissingTick { tick: t, .. }) if t == tick
+        ));
+
+        let mut v2 = v2_pool_sim::V2PoolSim::new(
+            "uniswap".into(),
+            "v2".into(),
+            3000,
+            pool,
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            U256::ZERO,
+            U256::ZERO,
+        );
+        assert!(matches!(
+            v2.trade(amount, true),
+            Err(PoolError::NoLiquidity { .. })
+        ));
+
+        assert_eq!(
+            tick_math::price_from_tick(I24::try_from(887273).unwrap()),
+            Err(MathError::TickOutOfRange(I24::try_from(887273).unwrap()))
+        );
+        for t in [-887272, -100, -1, 0, 1, 12345, 887271] {
+            let tick = I24::try_from(t).unwrap();
+            let price = tick_math::price_from_tick(tick).unwrap();
+            assert_eq!(tick_math::tick_from_price(price), Ok(tick));
+        }
+        assert!(matches!(
+            tick_math::tick_from_price(U256::ZERO),
+            Err(MathError::PriceOutOfBounds { .. })
+        ));
+        assert_eq!(
+            tick_math::update_liquidity(U256::ONE, -2),
+            Err(MathError::Overflow {
+                op: "liquidity update"
+            })
+        );
+    }
+
+    #[test]
+    fn tick_math_rounds_to_the_tick_at_or_below_a_price() {
+        use alloy::primitives::aliases::I24;
+
+        let tick = |t: i32| I24::try_from(t).unwrap();
+        // getTickAtSqrtRatio returns the greatest tick whose price is at or
+        // below the given one, negative ticks included
+        for t in [-887271, -200_000, -60, -1, 0, 1, 60, 200_000, 887271] {
+            let price = tick_math::price_from_tick(tick(t)).unwrap();
+            assert_eq!(tick_math::tick_from_price(price), Ok(tick(t)));
+            assert_eq!(
+                tick_math::tick_from_price(price - U256::ONE),
+                Ok(tick(t - 1))
+            );
+            assert_eq!(tick_math::tick_from_price(price + U256::ONE), Ok(tick(t)));
+        }
+
+        // sitting on the next price already nothing is left to swap
+        let price = tick_math::price_from_tick(tick(-60)).unwrap();
+        let liquidity = U256::from(10u128.pow(18));
+        for from0 in [true, false] {
+            assert_eq!(
+                tick_math::compute_amount_possible(from0, &liquidity, &price, &price),
+                Ok(U256::ZERO)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rpc_rate_limit_spaces_requests() {
+        use std::time::{Duration, Instant};
+
+        let node = fake_node(|_, _| FakeReply::Result("0x10".into())).await;
+        let transport = rpc::RpcConfig::new(vec![node])
+            .limit(rpc::RateLimit::requests(20))
+            .transport()
+            .unwrap();
+        let provider = transport.provider();
+
+        let started = Instant::now();
+        for _ in 0..5 {
+            provider.get_block_number().await.unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // 10 CU per eth_blockNumber at 100 CU/s is ten a second
+        let node = fake_node(|_, _| FakeReply::Result("0x10".into())).await;
+        let provider = rpc::RpcConfig::new(vec![node])
+            .limit(rpc::RateLimit::compute_units(100))
+            .connect()
+            .unwrap();
+        let started = Instant::now();
+        for _ in 0..3 {
+            provider.get_block_number().await.unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(transport.count().requests, 5);
+
+        // a budget of 0 is refused up front instead of dividing by it
+        let url = Url::from_str("http://127.0.0.1:1").unwrap();
+        for limit in [
+            rpc::RateLimit::requests(0),
+            rpc::RateLimit::compute_units(0),
+        ] {
+            let config = rpc::RpcConfig::new(vec![url.clone()]).limit(limit);
+            assert!(config.transport().is_err());
+        }
+    }
+
+    // anvil --fork-url https://binance.llamarpc.com
+    #[tokio::test]
+    #[ignore = "needs a local anvil node"]
+    async fn pool_sync_anvil() {
+        let provider =
+            ProviderBuilder::new().connect_http(Url::from_str("http://127.0.0.1:8545").unwrap());
+        let address = Address::from_str("0x0f338Ec12d3f7C3D77A4B9fcC1f95F3FB6AD0EA6").unwrap();
+
+        let v3 = v3_pool_src::V3PoolSrc::new(address, provider.clone())
+            .await
+            .unwrap();
+        let mut sync = pool_sync::PoolSync::new(provider.clone(), 1_000, journal::reorg_depth(56));
+        sync.track(v_pool_sim::AnyPoolSim::V3(v3.into_sim()));
+
+        let first = sync.sync().await.unwrap();
+        let _: String = provider.raw_request("evm_mine".into(), ()).await.unwrap();
+        let second = sync.sync().await.unwrap();
+
+        assert!(second > first);
+        assert_eq!(sync.last_block(), Some(second));
+
+        // replace the last block with a sibling, the engine has to unwind it
+        let snapshot: String = provider.raw_request("evm_snapshot".into(), ()).await.unwrap();
+        let _: String = provider.raw_request("evm_mine".into(), ()).await.unwrap();
+        sync.sync().await.unwrap();
+        let _: bool = provider
+            .raw_request("evm_revert".into(), (snapshot,))
+            .await
+            .unwrap();
+        let _: String = provider.raw_request("evm_mine".into(), ()).await.unwrap();
+        let _: String = provider.raw_request("evm_mine".into(), ()).await.unwrap();
+        let third = sync.sync().await.unwrap();
+        assert_eq!(third, second + 2);
+
+        let Some(v_pool_sim::AnyPoolSim::V3(sim)) = sync.pool(&address) else {
+            panic!("pool not tracked");
+        };
+        let slot0 = UniV3Pool::new(address, provider).slot0().call().await.unwrap();
+        assert_eq!(sim.x96price, U256::from(slot0.sqrtPriceX96));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a mainnet fork on anvil at 127.0.0.1:8545"]
+    async fn v3_lens_anvil() {
+        use alloy::primitives::aliases::I24;
+
+        let provider =
+            ProviderBuilder::new().connect_http(Url::from_str("http://127.0.0.1:8545").unwrap());
+        let address = Address::from_str("0x0f338Ec12d3f7C3D77A4B9fcC1f95F3FB6AD0EA6").unwrap();
+        // no code there, slot0 returns nothing
+        let missing = Address::repeat_byte(0x33);
+        let pool = UniV3Pool::new(address, provider.clone());
+        let slot0 = pool.slot0().call().await.unwrap();
+        let spacing = pool.tickSpacing().call().await.unwrap();
+
+        for mode in [
+            v3_lens::LensMode::StateOverride,
+            v3_lens::LensMode::Constructor,
+        ] {
+            let lens = v3_lens::V3Lens::new(provider.clone(), mode, 2, 10);
+            let loaded = lens
+                .load(&[address, missing], alloy::eips::BlockId::latest())
+                .await
+                .unwrap();
+            assert!(loaded[1].is_none());
+
+            let state = loaded[0].as_ref().unwrap();
+            assert_eq!(state.x96price, U256::from(slot0.sqrtPriceX96));
+            assert_eq!(state.current_tick, slot0.tick);
+            assert_eq!(state.tick_spacing, spacing);
+            let liquidity = pool.liquidity().call().await.unwrap();
+            assert_eq!(state.liquidity, U256::from(liquidity));
+            assert_eq!(state.bitmap.len(), 5);
+            for (word, bits) in &state.bitmap {
+                let read = pool.tickBitmap(*word).call().await.unwrap();
+                assert_eq!(*bits, read);
+            }
+            for tick in &state.active_ticks {
+                let read = pool.ticks(tick.tick).call().await.unwrap();
+                assert_eq!(tick.liquidity_net, Some(read.liquidityNet));
+                assert_eq!(tick.tick % spacing, I24::ZERO);
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a mainnet fork on anvil at 127.0.0.1:8545"]
+    async fn curve_crypto_anvil() {
+        let provider =
+            ProviderBuilder::new().connect_http(Url::from_str("http://127.0.0.1:8545").unwrap());
+        // tricrypto2 and crv/eth run the v1 math, TricryptoUSDC the -ng one
+        let pools = [
+            ("0xD51a44d3FaE010294C616388b506AcdA1bfAAE46", false),
+            ("0x8301AE4fc9c624d1D396cbDAa1ed877821D7C511", false),
+            ("0x7F86Bf177Dd4F3494b841a37e810A34dD56c829B", true),
+        ];
+        for (address, ng) in pools {
+            let address = Address::from_str(address).unwrap();
+            let src = curve_crypto_pool_src::CurveCryptoPoolSrc::new(address, provider.clone())
+                .await
+                .unwrap();
+            assert_eq!(src.ng, ng);
+            let sim = src.into_sim();
+            for (i, j) in [(0, 1), (1, 0)] {
+                let dx = sim.balances[i] / U256::from(1_000);
+                let quoted = src
+                    .contract
+                    .get_dy(U256::from(i), U256::from(j), dx)
+                    .call()
+                    .await
+                    .unwrap();
+                assert_eq!(sim.get_dy(i, j, dx).unwrap(), quoted);
+            }
+        }
+    }
 }
@@ -0,0 +1,227 @@
+//! Reads pool state straight out of storage slots with `eth_getStorageAt`,
+//! and checks it against a block's state root with `eth_getProof`.
+//!
+//! Only swap state lives in storage, immutables (tokens, fee, tick spacing)
+//! still have to come from the contract getters.
+
+use alloy::consensus::TrieAccount;
+use alloy::eips::BlockId;
+use alloy::primitives::aliases::I24;
+use alloy::primitives::{Address, B256, I256, U256, keccak256};
+use alloy_provider::Provider;
+use alloy_trie::{Nibbles, proof::verify_proof};
+use anyhow::anyhow;
+
+use crate::v2_pool_sim::V2PoolSim;
+use crate::v3_pool_sim::V3PoolSim;
+use crate::v3_pool_src::Rpc;
+
+/// `reserve0`, `reserve1` and `blockTimestampLast` share this slot in every V2 pair
+pub const V2_RESERVES_SLOT: u64 = 8;
+
+/// Storage slots of a V3 fork, `slot0` always starts at slot 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct V3Layout {
+    pub liquidity: u64,
+    pub ticks: u64,
+    pub tick_bitmap: u64,
+}
+
+pub const UNISWAP_V3_LAYOUT: V3Layout = V3Layout {
+    liquidity: 4,
+    ticks: 5,
+    tick_bitmap: 6,
+};
+
+/// Pancake's `feeProtocol` is a uint32, which pushes it and `unlocked` out of
+/// slot 0 into slot 1 and shifts everything after by one
+pub const PANCAKE_V3_LAYOUT: V3Layout = V3Layout {
+    liquidity: 5,
+    ticks: 6,
+    tick_bitmap: 7,
+};
+
+/// Slot of `mapping[key]` for a mapping declared at `slot`
+pub fn mapping_slot(key: B256, slot: u64) -> U256 {
+    let mut preimage = [0u8; 64];
+    preimage[..32].copy_from_slice(key.as_slice());
+    preimage[32..].copy_from_slice(&U256::from(slot).to_be_bytes::<32>());
+    keccak256(preimage).into()
+}
+
+/// Slot holding `liquidityGross` and `liquidityNet` of `ticks[tick]`
+pub fn tick_slot(layout: &V3Layout, tick: I24) -> U256 {
+    let key = B256::from(I256::try_from(tick.as_i32()).unwrap().into_raw());
+    mapping_slot(key, layout.ticks)
+}
+
+pub fn bitmap_slot(layout: &V3Layout, word: i16) -> U256 {
+    let key = B256::from(I256::try_from(word).unwrap().into_raw());
+    mapping_slot(key, layout.tick_bitmap)
+}
+
+/// `sqrtPriceX96` and `tick` from the first slot0 word
+pub fn decode_slot0(word: U256) -> (U256, I24) {
+    let sqrt_price_x96 = word & ((U256::ONE << 160) - U256::ONE);
+    let tick = I24::from_raw(((word >> 160usize) & U256::from(0xFFFFFF)).to());
+    (sqrt_price_x96, tick)
+}
+
+/// `liquidityGross` and `liquidityNet` from the first word of a tick
+pub fn decode_tick(word: U256) -> (u128, i128) {
+    let gross: u128 = (word & U256::from(u128::MAX)).to();
+    let net = (word >> 128usize).to::<u128>() as i128;
+    (gross, net)
+}
+
+/// `reserve0`, `reserve1` and `blockTimestampLast` from the packed V2 slot
+pub fn decode_reserves(word: U256) -> (U256, U256, u32) {
+    let mask = (U256::ONE << 112) - U256::ONE;
+    let reserve0 = word & mask;
+    let reserve1 = (word >> 112usize) & mask;
+    let timestamp = (word >> 224usize).to::<u32>();
+    (reserve0, reserve1, timestamp)
+}
+
+/// Storage reads pinned to one block
+#[derive(Debug, Clone)]
+pub struct StorageReader {
+    provider: Rpc,
+    block: BlockId,
+}
+
+impl StorageReader {
+    pub fn new(provider: Rpc, block: BlockId) -> Self {
+        Self { provider, block }
+    }
+
+    pub async fn slot(&self, address: Address, slot: U256) -> Result<U256, anyhow::Error> {
+        Ok(self
+            .provider
+            .get_storage_at(address, slot)
+            .block_id(self.block)
+            .await?)
+    }
+
+    pub async fn v2_reserves(&self, pair: Address) -> Result<(U256, U256, u32), anyhow::Error> {
+        Ok(decode_reserves(
+            self.slot(pair, U256::from(V2_RESERVES_SLOT)).await?,
+        ))
+    }
+
+    pub async fn v3_slot0(&self, pool: Address) -> Result<(U256, I24), anyhow::Error> {
+        Ok(decode_slot0(self.slot(pool, U256::ZERO).await?))
+    }
+
+    pub async fn v3_liquidity(
+        &self,
+        pool: Address,
+        layout: &V3Layout,
+    ) -> Result<U256, anyhow::Error> {
+        let word = self.slot(pool, U256::from(layout.liquidity)).await?;
+        Ok(word & U256::from(u128::MAX))
+    }
+
+    pub async fn v3_tick_bitmap(
+        &self,
+        pool: Address,
+        layout: &V3Layout,
+        word: i16,
+    ) -> Result<U256, anyhow::Error> {
+        self.slot(pool, bitmap_slot(layout, word)).await
+    }
+
+    pub async fn v3_liquidity_net(
+        &self,
+        pool: Address,
+        layout: &V3Layout,
+        tick: I24,
+    ) -> Result<i128, anyhow::Error> {
+        let (_, net) = decode_tick(self.slot(pool, tick_slot(layout, tick)).await?);
+        Ok(net)
+    }
+
+    /// Refresh reserves of a loaded V2 pool, one slot read
+    pub async fn refresh_v2(&self, sim: &mut V2PoolSim) -> Result<(), anyhow::Error> {
+        let (reserve0, reserve1, _) = self.v2_reserves(sim.address).await?;
+        sim.apply_sync(reserve0, reserve1);
+        Ok(())
+    }
+
+    /// Refresh price, liquidity and the net liquidity of every loaded tick
+    pub async fn refresh_v3(
+        &self,
+        sim: &mut V3PoolSim,
+        layout: &V3Layout,
+    ) -> Result<(), anyhow::Error> {
+        let (sqrt_price_x96, tick) = self.v3_slot0(sim.address).await?;
+        let liquidity = self.v3_liquidity(sim.address, layout).await?;
+        for t in sim.active_ticks.iter_mut() {
+            t.liquidity_net = Some(self.v3_liquidity_net(sim.address, layout, t.tick).await?);
+        }
+        sim.apply_swap(sqrt_price_x96, liquidity, tick);
+        Ok(())
+    }
+
+    /// State root of the pinned block
+    pub async fn state_root(&self) -> Result<B256, anyhow::Error> {
+        let block = self
+            .provider
+            .get_block(self.block)
+            .await?
+            .ok_or_else(|| anyhow!("block {:?} not found", self.block))?;
+        Ok(block.header.state_root)
+    }
+
+    /// Read `slots` with `eth_getProof` and verify the account and every
+    /// storage value against `state_root`
+    pub async fn proven_slots(
+        &self,
+        address: Address,
+        slots: &[U256],
+        state_root: B256,
+    ) -> Result<Vec<U256>, anyhow::Error> {
+        let keys: Vec<B256> = slots.iter().map(|s| B256::from(*s)).collect();
+        let proof = self
+            .provider
+            .get_proof(address, keys.clone())
+            .block_id(self.block)
+            .await?;
+
+        let account = TrieAccount {
+            nonce: proof.nonce,
+            balance: proof.balance,
+            storage_root: proof.storage_hash,
+            code_hash: proof.code_hash,
+        };
+        verify_proof(
+            state_root,
+            Nibbles::unpack(keccak256(address)),
+            Some(alloy::rlp::encode(account)),
+            &proof.account_proof,
+        )
+        .map_err(|e| anyhow!("account proof of {address}: {e}"))?;
+
+        if proof.storage_proof.len() != keys.len() {
+            return Err(anyhow!(
+                "asked for {} storage proofs, got {}",
+                keys.len(),
+                proof.storage_proof.len()
+            ));
+        }
+
+        let mut values = Vec::with_capacity(keys.len());
+        for (key, storage) in keys.iter().zip(&proof.storage_proof) {
+            let expected = (!storage.value.is_zero()).then(|| alloy::rlp::encode(storage.value));
+            verify_proof(
+                proof.storage_hash,
+                Nibbles::unpack(keccak256(key)),
+                expected,
+                &storage.proof,
+            )
+            .map_err(|e| anyhow!("storage proof of {address} slot {key}: {e}"))?;
+            values.push(storage.value);
+        }
+        Ok(values)
+    }
+}
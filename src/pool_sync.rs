@@ -0,0 +1,859 @@
+//! Keeps simulated pools in step with the chain by replaying their logs
+//! instead of reloading every pool on every block.
+
+use std::collections::{HashMap, HashSet};
+
+use alloy::eips::BlockId;
+use alloy::primitives::aliases::{I24, U24};
+use alloy::primitives::{Address, B256, U256};
+use alloy::rpc::types::{Filter, Log};
+use alloy::sol_types::SolEvent;
+use alloy_provider::Provider;
+use anyhow::anyhow;
+
+use crate::algebra_pool_sim::AlgebraPoolSim;
+use crate::algebra_pool_src::AlgebraPoolSrc;
+use crate::balancer_pool_src::BalancerPoolSrc;
+use crate::curve_crypto_pool_src::CurveCryptoPoolSrc;
+use crate::curve_pool_src::CurvePoolSrc;
+use crate::journal::StateJournal;
+use crate::lb_pool_sim::decode_amounts;
+use crate::lb_pool_src::LbPoolSrc;
+use crate::solidly_pool_src::SolidlyPoolSrc;
+use crate::v_pool_sim::AnyPoolSim;
+use crate::v_pool_src::AnyPoolSrc;
+use crate::v3_pool_src::{Rpc, V3PoolSrc};
+use crate::v4_hooks::is_dynamic_fee;
+use crate::v4_pool_sim::V4PoolSim;
+use crate::v4_pool_src::V4PoolSrc;
+use crate::{
+    IAlgebraPool, IAlgebraPoolDirectional, IERC4626, ILBPair, ISolidlyPool, IUniswapV2Pair,
+    PancakeV3Pool, PoolKey, PoolManager, UniV3Pool,
+};
+
+/// A pool state change decoded from a log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolEvent {
+    V2Swap {
+        amount0_in: U256,
+        amount1_in: U256,
+        amount0_out: U256,
+        amount1_out: U256,
+    },
+    V2Mint {
+        amount0: U256,
+        amount1: U256,
+    },
+    V2Burn {
+        amount0: U256,
+        amount1: U256,
+    },
+    V2Sync {
+        reserve0: U256,
+        reserve1: U256,
+    },
+    V3Initialize {
+        sqrt_price_x96: U256,
+        tick: I24,
+    },
+    V3Swap {
+        sqrt_price_x96: U256,
+        liquidity: U256,
+        tick: I24,
+    },
+    V3Mint {
+        tick_lower: I24,
+        tick_upper: I24,
+        amount: u128,
+    },
+    V3Burn {
+        tick_lower: I24,
+        tick_upper: I24,
+        amount: u128,
+    },
+    /// Fees leaving the pool, the swap state is untouched
+    V3Collect,
+    /// A new pool in the V4 PoolManager, with everything needed to load it
+    V4Initialize {
+        key: PoolKey,
+        sqrt_price_x96: U256,
+        tick: I24,
+    },
+    /// Liquidity added to or removed from a V4 position
+    V4ModifyLiquidity {
+        tick_lower: I24,
+        tick_upper: I24,
+        liquidity_delta: i128,
+    },
+    V4Swap {
+        sqrt_price_x96: U256,
+        liquidity: U256,
+        tick: I24,
+        /// Swap fee including the protocol's
+        fee: U24,
+    },
+    /// New fees of an Algebra pool, the same both ways but on 1.9 pools
+    AlgebraFee {
+        fee_zto: U24,
+        fee_otz: U24,
+    },
+    /// One bin of a Liquidity Book swap, amounts as `[x, y]`
+    LbSwap {
+        id: u32,
+        amounts_in: [U256; 2],
+        amounts_out: [U256; 2],
+        volatility_accumulator: u32,
+        /// Time of the block, when the node returns it with the log
+        timestamp: Option<u64>,
+    },
+    /// Liquidity added to Liquidity Book bins, or flash loan fees left in
+    /// the active one
+    LbDeposit {
+        ids: Vec<u32>,
+        amounts: Vec<[U256; 2]>,
+    },
+    LbWithdraw {
+        ids: Vec<u32>,
+        amounts: Vec<[U256; 2]>,
+    },
+    /// Fee on a deposit changing the active bin's composition, part of it
+    /// leaves the bin and the volatility moves
+    LbCompositionFees,
+}
+
+/// A decoded event with its position in the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolLog {
+    /// Address of the emitting pool, for PoolManager events the low 20 bytes
+    /// of the `PoolId` that `AnyPoolSim::V4` is keyed by
+    pub pool: Address,
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub log_index: u64,
+    pub event: PoolEvent,
+}
+
+impl PoolLog {
+    /// Topics of every event the sync engine understands
+    pub fn signatures() -> Vec<B256> {
+        vec![
+            IUniswapV2Pair::Swap::SIGNATURE_HASH,
+            IUniswapV2Pair::Mint::SIGNATURE_HASH,
+            IUniswapV2Pair::Burn::SIGNATURE_HASH,
+            IUniswapV2Pair::Sync::SIGNATURE_HASH,
+            ISolidlyPool::Sync::SIGNATURE_HASH,
+            UniV3Pool::Initialize::SIGNATURE_HASH,
+            UniV3Pool::Swap::SIGNATURE_HASH,
+            PancakeV3Pool::Swap::SIGNATURE_HASH,
+            UniV3Pool::Mint::SIGNATURE_HASH,
+            UniV3Pool::Burn::SIGNATURE_HASH,
+            UniV3Pool::Collect::SIGNATURE_HASH,
+            PoolManager::Initialize::SIGNATURE_HASH,
+            PoolManager::ModifyLiquidity::SIGNATURE_HASH,
+            PoolManager::Swap::SIGNATURE_HASH,
+            IAlgebraPool::Fee::SIGNATURE_HASH,
+            IAlgebraPoolDirectional::Fee::SIGNATURE_HASH,
+            ILBPair::Swap::SIGNATURE_HASH,
+            ILBPair::DepositedToBins::SIGNATURE_HASH,
+            ILBPair::WithdrawnFromBins::SIGNATURE_HASH,
+            ILBPair::CompositionFees::SIGNATURE_HASH,
+            ILBPair::FlashLoan::SIGNATURE_HASH,
+        ]
+    }
+
+    /// Decode a log, `None` for pending logs and unknown events
+    pub fn decode(log: &Log) -> Option<Self> {
+        let block_number = log.block_number?;
+        let block_hash = log.block_hash?;
+        let log_index = log.log_index?;
+        let data = log.data();
+        let mut pool = log.address();
+
+        let event = match *log.topic0()? {
+            IUniswapV2Pair::Swap::SIGNATURE_HASH => {
+                let e = IUniswapV2Pair::Swap::decode_log_data(data).ok()?;
+                PoolEvent::V2Swap {
+                    amount0_in: e.amount0In,
+                    amount1_in: e.amount1In,
+                    amount0_out: e.amount0Out,
+                    amount1_out: e.amount1Out,
+                }
+            }
+            IUniswapV2Pair::Mint::SIGNATURE_HASH => {
+                let e = IUniswapV2Pair::Mint::decode_log_data(data).ok()?;
+                PoolEvent::V2Mint {
+                    amount0: e.amount0,
+                    amount1: e.amount1,
+                }
+            }
+            IUniswapV2Pair::Burn::SIGNATURE_HASH => {
+                let e = IUniswapV2Pair::Burn::decode_log_data(data).ok()?;
+                PoolEvent::V2Burn {
+                    amount0: e.amount0,
+                    amount1: e.amount1,
+                }
+            }
+            IUniswapV2Pair::Sync::SIGNATURE_HASH => {
+                let e = IUniswapV2Pair::Sync::decode_log_data(data).ok()?;
+                PoolEvent::V2Sync {
+                    reserve0: U256::from(e.reserve0),
+                    reserve1: U256::from(e.reserve1),
+                }
+            }
+            // Solidly pairs log reserves as uint256, their swaps, mints and
+            // burns have V2's signatures and decode as V2 events
+            ISolidlyPool::Sync::SIGNATURE_HASH => {
+                let e = ISolidlyPool::Sync::decode_log_data(data).ok()?;
+                PoolEvent::V2Sync {
+                    reserve0: e.reserve0,
+                    reserve1: e.reserve1,
+                }
+            }
+            UniV3Pool::Initialize::SIGNATURE_HASH => {
+                let e = UniV3Pool::Initialize::decode_log_data(data).ok()?;
+                PoolEvent::V3Initialize {
+                    sqrt_price_x96: U256::from(e.sqrtPriceX96),
+                    tick: e.tick,
+                }
+            }
+            UniV3Pool::Swap::SIGNATURE_HASH => {
+                let e = UniV3Pool::Swap::decode_log_data(data).ok()?;
+                PoolEvent::V3Swap {
+                    sqrt_price_x96: U256::from(e.sqrtPriceX96),
+                    liquidity: U256::from(e.liquidity),
+                    tick: e.tick,
+                }
+            }
+            // Pancake logs the protocol's cut too, the rest is the same
+            PancakeV3Pool::Swap::SIGNATURE_HASH => {
+                let e = PancakeV3Pool::Swap::decode_log_data(data).ok()?;
+                PoolEvent::V3Swap {
+                    sqrt_price_x96: U256::from(e.sqrtPriceX96),
+                    liquidity: U256::from(e.liquidity),
+                    tick: e.tick,
+                }
+            }
+            UniV3Pool::Mint::SIGNATURE_HASH => {
+                let e = UniV3Pool::Mint::decode_log_data(data).ok()?;
+                PoolEvent::V3Mint {
+                    tick_lower: e.tickLower,
+                    tick_upper: e.tickUpper,
+                    amount: e.amount,
+                }
+            }
+            UniV3Pool::Burn::SIGNATURE_HASH => {
+                let e = UniV3Pool::Burn::decode_log_data(data).ok()?;
+                PoolEvent::V3Burn {
+                    tick_lower: e.tickLower,
+                    tick_upper: e.tickUpper,
+                    amount: e.amount,
+                }
+            }
+            UniV3Pool::Collect::SIGNATURE_HASH => PoolEvent::V3Collect,
+            PoolManager::Initialize::SIGNATURE_HASH => {
+                let e = PoolManager::Initialize::decode_log_data(data).ok()?;
+                pool = Address::from_word(e.id);
+                PoolEvent::V4Initialize {
+                    key: PoolKey {
+                        currency0: e.currency0,
+                        currency1: e.currency1,
+                        fee: e.fee,
+                        tickSpacing: e.tickSpacing,
+                        hooks: e.hooks,
+                    },
+                    sqrt_price_x96: U256::from(e.sqrtPriceX96),
+                    tick: e.tick,
+                }
+            }
+            PoolManager::ModifyLiquidity::SIGNATURE_HASH => {
+                let e = PoolManager::ModifyLiquidity::decode_log_data(data).ok()?;
+                pool = Address::from_word(e.id);
+                PoolEvent::V4ModifyLiquidity {
+                    tick_lower: e.tickLower,
+                    tick_upper: e.tickUpper,
+                    liquidity_delta: i128::try_from(e.liquidityDelta).ok()?,
+                }
+            }
+            PoolManager::Swap::SIGNATURE_HASH => {
+                let e = PoolManager::Swap::decode_log_data(data).ok()?;
+                pool = Address::from_word(e.id);
+                PoolEvent::V4Swap {
+                    sqrt_price_x96: U256::from(e.sqrtPriceX96),
+                    liquidity: U256::from(e.liquidity),
+                    tick: e.tick,
+                    fee: e.fee,
+                }
+            }
+            IAlgebraPool::Fee::SIGNATURE_HASH => {
+                let e = IAlgebraPool::Fee::decode_log_data(data).ok()?;
+                PoolEvent::AlgebraFee {
+                    fee_zto: U24::from(e.fee),
+                    fee_otz: U24::from(e.fee),
+                }
+            }
+            IAlgebraPoolDirectional::Fee::SIGNATURE_HASH => {
+                let e = IAlgebraPoolDirectional::Fee::decode_log_data(data).ok()?;
+                PoolEvent::AlgebraFee {
+                    fee_zto: U24::from(e.feeZto),
+                    fee_otz: U24::from(e.feeOtz),
+                }
+            }
+            ILBPair::Swap::SIGNATURE_HASH => {
+                let e = ILBPair::Swap::decode_log_data(data).ok()?;
+                PoolEvent::LbSwap {
+                    id: e.id.to(),
+                    amounts_in: decode_amounts(e.amountsIn),
+                    amounts_out: decode_amounts(e.amountsOut),
+                    volatility_accumulator: e.volatilityAccumulator.to(),
+                    timestamp: log.block_timestamp,
+                }
+            }
+            ILBPair::DepositedToBins::SIGNATURE_HASH => {
+                let e = ILBPair::DepositedToBins::decode_log_data(data).ok()?;
+                PoolEvent::LbDeposit {
+                    ids: lb_ids(&e.ids)?,
+                    amounts: e.amounts.into_iter().map(decode_amounts).collect(),
+                }
+            }
+            ILBPair::WithdrawnFromBins::SIGNATURE_HASH => {
+                let e = ILBPair::WithdrawnFromBins::decode_log_data(data).ok()?;
+                PoolEvent::LbWithdraw {
+                    ids: lb_ids(&e.ids)?,
+                    amounts: e.amounts.into_iter().map(decode_amounts).collect(),
+                }
+            }
+            ILBPair::CompositionFees::SIGNATURE_HASH => PoolEvent::LbCompositionFees,
+            // the fees minus the protocol's stay in the active bin
+            ILBPair::FlashLoan::SIGNATURE_HASH => {
+                let e = ILBPair::FlashLoan::decode_log_data(data).ok()?;
+                let [total_x, total_y] = decode_amounts(e.totalFees);
+                let [protocol_x, protocol_y] = decode_amounts(e.protocolFees);
+                PoolEvent::LbDeposit {
+                    ids: vec![e.activeId.to()],
+                    amounts: vec![[
+                        total_x.saturating_sub(protocol_x),
+                        total_y.saturating_sub(protocol_y),
+                    ]],
+                }
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            pool,
+            block_number,
+            block_hash,
+            log_index,
+            event,
+        })
+    }
+}
+
+/// Bin ids of a Liquidity Book log, `None` if one doesn't fit a uint24
+fn lb_ids(ids: &[U256]) -> Option<Vec<u32>> {
+    ids.iter()
+        .map(|id| u32::try_from(*id).ok().filter(|id| *id < 1 << 24))
+        .collect()
+}
+
+/// Pools whose logs aren't decoded, Curve and Balancer, and vaults whose
+/// assets grow without any log, which are reread at every sync instead
+fn reread_every_sync(pool: &AnyPoolSim) -> bool {
+    matches!(
+        pool,
+        AnyPoolSim::Curve(_)
+            | AnyPoolSim::CurveCrypto(_)
+            | AnyPoolSim::Balancer(_)
+            | AnyPoolSim::Erc4626(_)
+    )
+}
+
+/// Event driven synchronization of a set of tracked pools.
+///
+/// Every `sync` polls `eth_getLogs` from the last applied block up to the head
+/// and applies the logs in block / log-index order. Pools that were just
+/// tracked, or whose state can't be trusted anymore (a swap left the loaded
+/// tick window, the engine fell too far behind, a log request failed), are
+/// refetched in full at the new head instead.
+///
+/// Every change is journaled per block; when the hash of the last applied
+/// block no longer matches the chain, the pools are rolled back to the newest
+/// journaled block that is still canonical and the new blocks are replayed.
+#[derive(Debug)]
+pub struct PoolSync {
+    provider: Rpc,
+    pools: HashMap<Address, AnyPoolSim>,
+    stale: HashSet<Address>,
+    /// Sources of the pools reread every sync, what never changes in them
+    /// is only read when they are first loaded
+    sources: HashMap<Address, AnyPoolSrc>,
+    journal: StateJournal,
+    last_block: Option<u64>,
+    max_block_range: u64,
+    /// PoolManager and StateView, V4 pools can only be synced with both
+    v4: Option<(Address, Address)>,
+    /// Keys from `Initialize` events of V4 pools that aren't tracked
+    discovered: Vec<PoolKey>,
+}
+
+impl PoolSync {
+    /// `max_block_range` is the widest `eth_getLogs` range the node will serve,
+    /// falling further behind than that triggers a full refetch.
+    /// `reorg_depth` is how many blocks are journaled, see `journal::reorg_depth`
+    pub fn new(provider: Rpc, max_block_range: u64, reorg_depth: u64) -> Self {
+        Self {
+            provider,
+            pools: HashMap::new(),
+            stale: HashSet::new(),
+            sources: HashMap::new(),
+            journal: StateJournal::new(reorg_depth),
+            last_block: None,
+            max_block_range,
+            v4: None,
+            discovered: Vec::new(),
+        }
+    }
+
+    /// Follow V4 pools through the PoolManager's logs, refetching them
+    /// through `state_view`
+    pub fn with_v4(mut self, pool_manager: Address, state_view: Address) -> Self {
+        self.v4 = Some((pool_manager, state_view));
+        self
+    }
+
+    /// Start tracking the V4 pool of `key`, loaded on the next `sync`
+    pub fn track_v4(&mut self, key: &PoolKey) {
+        self.track(AnyPoolSim::V4(V4PoolSim::new(key)));
+    }
+
+    /// V4 pools initialized since the last call, in chain order
+    pub fn take_discovered(&mut self) -> Vec<PoolKey> {
+        std::mem::take(&mut self.discovered)
+    }
+
+    /// Start tracking a pool, its state is refetched on the next `sync`
+    pub fn track(&mut self, pool: AnyPoolSim) {
+        let address = pool.get_address();
+        self.pools.insert(address, pool);
+        self.sources.remove(&address);
+        self.stale.insert(address);
+    }
+
+    /// Track a pool whose state is already current as of `last_block`
+    pub fn track_synced(&mut self, pool: AnyPoolSim) {
+        self.pools.insert(pool.get_address(), pool);
+    }
+
+    pub fn untrack(&mut self, address: &Address) -> Option<AnyPoolSim> {
+        self.stale.remove(address);
+        self.sources.remove(address);
+        self.pools.remove(address)
+    }
+
+    /// Last block whose logs are fully applied to every tracked pool
+    pub fn last_block(&self) -> Option<u64> {
+        self.last_block
+    }
+
+    pub fn pool(&self, address: &Address) -> Option<&AnyPoolSim> {
+        self.pools.get(address)
+    }
+
+    pub fn pools(&self) -> &HashMap<Address, AnyPoolSim> {
+        &self.pools
+    }
+
+    /// Bring every tracked pool up to the current head, returns the last applied block
+    pub async fn sync(&mut self) -> Result<u64, anyhow::Error> {
+        let head = self.provider.get_block_number().await?;
+        self.sync_to(head).await?;
+        Ok(self.last_block.unwrap_or(head))
+    }
+
+    pub fn journal(&self) -> &StateJournal {
+        &self.journal
+    }
+
+    /// Bring every tracked pool up to `head`
+    pub async fn sync_to(&mut self, head: u64) -> Result<(), anyhow::Error> {
+        self.unwind_reorg().await?;
+        if self.last_block.is_some_and(|last| head <= last) {
+            return Ok(());
+        }
+
+        // logs aren't idempotent, nothing is applied before the head's hash
+        // is known and a failed refetch undoes them, the next call replays
+        // the same logs onto the same state
+        let hash = self.block_hash(head).await?;
+        match self.last_block {
+            Some(last) if head - last <= self.max_block_range => {
+                match self.fetch_logs(last + 1, head).await {
+                    Ok(logs) => self.apply_logs(logs),
+                    Err(_) => self.stale.extend(self.pools.keys().copied()),
+                }
+            }
+            _ => self.stale.extend(self.pools.keys().copied()),
+        }
+        let reread = self
+            .pools
+            .iter()
+            .filter(|(_, pool)| reread_every_sync(pool));
+        self.stale.extend(reread.map(|(address, _)| *address));
+
+        self.journal.mark(head, hash);
+        if let Err(err) = self.refetch_stale(head, hash).await {
+            match self.last_block {
+                Some(last) => self.roll_back(last),
+                None => self.journal.clear(),
+            }
+            return Err(err);
+        }
+        self.journal.prune(head);
+        self.last_block = Some(head);
+        Ok(())
+    }
+
+    async fn block_hash(&self, number: u64) -> Result<B256, anyhow::Error> {
+        let block = self
+            .provider
+            .get_block_by_number(number.into())
+            .await?
+            .ok_or_else(|| anyhow!("block {number} not found"))?;
+        Ok(block.header.hash)
+    }
+
+    /// Roll the pools back to the newest journaled block still on the canonical
+    /// chain, or refetch everything when the reorg is deeper than the journal
+    async fn unwind_reorg(&mut self) -> Result<(), anyhow::Error> {
+        let Some(last) = self.last_block else {
+            return Ok(());
+        };
+        let canonical = self.block_hash(last).await?;
+        if self.journal.hash_at(last) == Some(canonical) {
+            return Ok(());
+        }
+
+        let mut ancestor = None;
+        for (number, hash) in self.journal.blocks() {
+            if self.block_hash(number).await? == hash {
+                ancestor = Some(number);
+                break;
+            }
+        }
+
+        match ancestor {
+            Some(ancestor) => {
+                self.roll_back(ancestor);
+                self.last_block = Some(ancestor);
+            }
+            None => {
+                self.journal.clear();
+                self.stale.extend(self.pools.keys().copied());
+                self.last_block = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Put the pools back as they were at `ancestor`, those refetched since
+    /// are refetched again
+    fn roll_back(&mut self, ancestor: u64) {
+        for entry in self.journal.rollback(ancestor) {
+            for (address, prior) in entry.prior {
+                if let Some(pool) = self.pools.get_mut(&address) {
+                    *pool = prior;
+                }
+            }
+            self.stale.extend(entry.refetched);
+        }
+    }
+
+    async fn fetch_logs(&self, from: u64, to: u64) -> Result<Vec<Log>, anyhow::Error> {
+        if self.pools.is_empty() {
+            return Ok(Vec::new());
+        }
+        // V4 pools have no address of their own, their logs come from the
+        // manager, wrapping logs nothing worth following and the rest are
+        // reread
+        let mut addresses: Vec<Address> = self
+            .pools
+            .iter()
+            .filter(|(_, pool)| {
+                !matches!(pool, AnyPoolSim::V4(_) | AnyPoolSim::Wrap(_)) && !reread_every_sync(pool)
+            })
+            .map(|(address, _)| *address)
+            .collect();
+        addresses.extend(self.v4.map(|(pool_manager, _)| pool_manager));
+        let filter = Filter::new()
+            .from_block(from)
+            .to_block(to)
+            .address(addresses)
+            .event_signature(PoolLog::signatures());
+        Ok(self.provider.get_logs(&filter).await?)
+    }
+
+    /// Apply raw logs to the tracked pools in chain order, unknown logs are skipped
+    pub fn apply_logs(&mut self, logs: Vec<Log>) {
+        let mut decoded: Vec<PoolLog> = logs
+            .iter()
+            .filter(|log| !log.removed)
+            .filter_map(PoolLog::decode)
+            .collect();
+        decoded.sort_by_key(|l| (l.block_number, l.log_index));
+
+        for log in decoded {
+            self.apply(&log);
+        }
+    }
+
+    /// Apply a single decoded event to its pool
+    pub fn apply(&mut self, log: &PoolLog) {
+        if self.stale.contains(&log.pool) {
+            return;
+        }
+        let Some(pool) = self.pools.get_mut(&log.pool) else {
+            if let PoolEvent::V4Initialize { key, .. } = &log.event {
+                self.discovered.push(key.clone());
+            }
+            return;
+        };
+        self.journal
+            .record(log.block_number, log.block_hash, log.pool, pool);
+
+        match (pool, &log.event) {
+            // V2 and Solidly pairs log a `Sync` with their new reserves right
+            // before every swap, mint and burn, applying their amounts too
+            // counts them twice
+            (
+                AnyPoolSim::V2(_) | AnyPoolSim::Solidly(_),
+                PoolEvent::V2Swap { .. } | PoolEvent::V2Mint { .. } | PoolEvent::V2Burn { .. },
+            ) => {}
+            (AnyPoolSim::V2(v2), PoolEvent::V2Sync { reserve0, reserve1 }) => {
+                v2.apply_sync(*reserve0, *reserve1)
+            }
+            (AnyPoolSim::Solidly(solidly), PoolEvent::V2Sync { reserve0, reserve1 }) => {
+                solidly.apply_sync(*reserve0, *reserve1)
+            }
+            // Algebra pools log swaps and liquidity with V3's signatures
+            (
+                AnyPoolSim::V3(v3) | AnyPoolSim::Algebra(AlgebraPoolSim { pool: v3, .. }),
+                PoolEvent::V3Swap {
+                    sqrt_price_x96,
+                    liquidity,
+                    tick,
+                },
+            ) => {
+                v3.apply_swap(*sqrt_price_x96, *liquidity, *tick);
+                if !v3.in_window(*tick) {
+                    self.stale.insert(log.pool);
+                }
+            }
+            (
+                AnyPoolSim::V3(v3) | AnyPoolSim::Algebra(AlgebraPoolSim { pool: v3, .. }),
+                PoolEvent::V3Mint {
+                    tick_lower,
+                    tick_upper,
+                    amount,
+                },
+            ) => v3.mint(*tick_lower, *tick_upper, *amount),
+            (
+                AnyPoolSim::V3(v3) | AnyPoolSim::Algebra(AlgebraPoolSim { pool: v3, .. }),
+                PoolEvent::V3Burn {
+                    tick_lower,
+                    tick_upper,
+                    amount,
+                },
+            ) => v3.burn(*tick_lower, *tick_upper, *amount),
+            (
+                AnyPoolSim::V3(_) | AnyPoolSim::Algebra(_),
+                PoolEvent::V3Collect | PoolEvent::V3Initialize { .. },
+            ) => {}
+            (AnyPoolSim::Algebra(algebra), PoolEvent::AlgebraFee { fee_zto, fee_otz }) => {
+                algebra.set_fee(*fee_zto, *fee_otz)
+            }
+            // the volatility decays with time, without the block's the fee
+            // can't be followed
+            (
+                AnyPoolSim::Lb(lb),
+                PoolEvent::LbSwap {
+                    id,
+                    amounts_in,
+                    amounts_out,
+                    volatility_accumulator,
+                    timestamp: Some(timestamp),
+                },
+            ) if lb.in_window(*id) => lb.apply_swap(
+                *id,
+                *amounts_in,
+                *amounts_out,
+                *volatility_accumulator,
+                *timestamp,
+            ),
+            (AnyPoolSim::Lb(lb), PoolEvent::LbDeposit { ids, amounts }) => {
+                for (id, amounts) in ids.iter().zip(amounts) {
+                    lb.deposit(*id, *amounts);
+                }
+            }
+            (AnyPoolSim::Lb(lb), PoolEvent::LbWithdraw { ids, amounts }) => {
+                for (id, amounts) in ids.iter().zip(amounts) {
+                    lb.withdraw(*id, *amounts);
+                }
+            }
+            (
+                AnyPoolSim::V4(v4),
+                PoolEvent::V4Swap {
+                    sqrt_price_x96,
+                    liquidity,
+                    tick,
+                    fee,
+                },
+            ) => {
+                v4.pool.apply_swap(*sqrt_price_x96, *liquidity, *tick);
+                // a dynamic fee pool's logged fee may be a hook's override for
+                // that one swap, and its stored fee changes without a log
+                if is_dynamic_fee(v4.key_fee) {
+                    self.stale.insert(log.pool);
+                } else if v4.protocol_fee.is_zero() {
+                    // the logged fee includes the protocol's
+                    v4.lp_fee = *fee;
+                }
+                if !v4.pool.in_window(*tick) {
+                    self.stale.insert(log.pool);
+                }
+            }
+            (
+                AnyPoolSim::V4(v4),
+                PoolEvent::V4ModifyLiquidity {
+                    tick_lower,
+                    tick_upper,
+                    liquidity_delta,
+                },
+            ) => match u128::try_from(*liquidity_delta) {
+                Ok(amount) => v4.pool.mint(*tick_lower, *tick_upper, amount),
+                Err(_) => v4
+                    .pool
+                    .burn(*tick_lower, *tick_upper, liquidity_delta.unsigned_abs()),
+            },
+            // an event from the other pool kind, the pool isn't what we think it is
+            _ => {
+                self.stale.insert(log.pool);
+            }
+        }
+    }
+
+    async fn refetch_stale(&mut self, block: u64, hash: B256) -> Result<(), anyhow::Error> {
+        let stale: Vec<Address> = self.stale.iter().copied().collect();
+        for address in stale {
+            if let Some(pool) = self.pools.get_mut(&address) {
+                let state_view = self.v4.map(|(_, state_view)| state_view);
+                Self::refetch(
+                    &self.provider,
+                    state_view,
+                    &mut self.sources,
+                    pool,
+                    BlockId::hash(hash),
+                )
+                .await?;
+                self.journal.record_refetch(block, hash, address);
+            }
+            self.stale.remove(&address);
+        }
+        Ok(())
+    }
+
+    async fn refetch(
+        provider: &Rpc,
+        state_view: Option<Address>,
+        sources: &mut HashMap<Address, AnyPoolSrc>,
+        pool: &mut AnyPoolSim,
+        block: BlockId,
+    ) -> Result<(), anyhow::Error> {
+        match pool {
+            AnyPoolSim::V2(v2) => {
+                let pair = IUniswapV2Pair::new(v2.address, provider.clone());
+                let reserves = pair.getReserves().call().block(block).await?;
+                v2.apply_sync(U256::from(reserves.reserve0), U256::from(reserves.reserve1));
+            }
+            // taxes are the caller's, the chain doesn't know them
+            AnyPoolSim::V3(v3) => {
+                let src = V3PoolSrc::new_at(v3.address, provider.clone(), block).await?;
+                let taxes = (v3.tax0.clone(), v3.tax1.clone());
+                *v3 = src.into_sim();
+                (v3.tax0, v3.tax1) = taxes;
+            }
+            AnyPoolSim::V4(v4) => {
+                let state_view = state_view
+                    .ok_or_else(|| anyhow!("V4 pool {} tracked without `with_v4`", v4.id))?;
+                let src = V4PoolSrc::new_at(v4.key(), state_view, provider.clone(), block).await?;
+                let hook_model = v4.hook_model.take();
+                let taxes = (v4.pool.tax0.clone(), v4.pool.tax1.clone());
+                *v4 = src.into_sim();
+                v4.hook_model = hook_model;
+                (v4.pool.tax0, v4.pool.tax1) = taxes;
+            }
+            // coins, decimals and the base pool stay, the rest is read again
+            AnyPoolSim::Curve(curve) => match sources.get_mut(&curve.address) {
+                Some(AnyPoolSrc::Curve(src)) => {
+                    src.update_at(block).await?;
+                    *curve = src.into_sim();
+                }
+                _ => {
+                    let src = CurvePoolSrc::new_at(curve.address, provider.clone(), block).await?;
+                    *curve = src.into_sim();
+                    sources.insert(curve.address, AnyPoolSrc::Curve(src));
+                }
+            },
+            // coins, decimals and which math the pool runs stay
+            AnyPoolSim::CurveCrypto(curve) => match sources.get_mut(&curve.address) {
+                Some(AnyPoolSrc::CurveCrypto(src)) => {
+                    src.update_at(block).await?;
+                    *curve = src.into_sim();
+                }
+                _ => {
+                    let src =
+                        CurveCryptoPoolSrc::new_at(curve.address, provider.clone(), block).await?;
+                    *curve = src.into_sim();
+                    sources.insert(curve.address, AnyPoolSrc::CurveCrypto(src));
+                }
+            },
+            // tokens, decimals and the kind of pool stay
+            AnyPoolSim::Balancer(balancer) => match sources.get_mut(&balancer.address) {
+                Some(AnyPoolSrc::Balancer(src)) => {
+                    src.update_at(block).await?;
+                    *balancer = src.into_sim();
+                }
+                _ => {
+                    let src =
+                        BalancerPoolSrc::new_at(balancer.address, provider.clone(), block).await?;
+                    *balancer = src.into_sim();
+                    sources.insert(balancer.address, AnyPoolSrc::Balancer(src));
+                }
+            },
+            AnyPoolSim::Algebra(algebra) => {
+                let src =
+                    AlgebraPoolSrc::new_at(algebra.pool.address, provider.clone(), block).await?;
+                let taxes = (algebra.pool.tax0.clone(), algebra.pool.tax1.clone());
+                *algebra = src.into_sim();
+                (algebra.pool.tax0, algebra.pool.tax1) = taxes;
+            }
+            AnyPoolSim::Solidly(solidly) => {
+                let src = SolidlyPoolSrc::new_at(solidly.address, provider.clone(), block).await?;
+                let taxes = (solidly.tax0.clone(), solidly.tax1.clone());
+                *solidly = src.into_sim();
+                (solidly.tax0, solidly.tax1) = taxes;
+            }
+            AnyPoolSim::Lb(lb) => {
+                let src = LbPoolSrc::new_at(lb.address, provider.clone(), block).await?;
+                *lb = src.into_sim();
+            }
+            // the conversion was told when the vault was loaded
+            AnyPoolSim::Erc4626(vault) => {
+                let contract = IERC4626::new(vault.address, provider.clone());
+                vault.total_assets = contract.totalAssets().call().block(block).await?;
+                vault.total_supply = contract.totalSupply().call().block(block).await?;
+            }
+            AnyPoolSim::Wrap(_) => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+//! Transport with per-request timeouts, retries with exponential backoff,
+//! failover across a list of endpoints and per-endpoint rate limits.
+//!
+//! Plug it into any provider with `RpcConfig::connect`, everything built on
+//! `Rpc` then gets the same behaviour without changes. Clones of a provider
+//! share one transport, so its limits and counters cover every source.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use alloy::rpc::client::RpcClient;
+use alloy::rpc::json_rpc::{RequestPacket, ResponsePacket};
+use alloy::transports::http::{Http, reqwest::Client, reqwest::Url};
+use alloy::transports::{TransportError, TransportErrorKind, TransportFut};
+use alloy_provider::ProviderBuilder;
+use anyhow::bail;
+use tower::Service;
+
+use crate::v3_pool_src::Rpc;
+
+/// Compute units a method costs on the usual hosted providers,
+/// close to the published Alchemy table
+pub fn compute_units(method: &str) -> u32 {
+    match method {
+        "eth_chainId" | "net_version" => 0,
+        "eth_blockNumber" => 10,
+        "eth_getBlockByNumber" | "eth_getBlockByHash" => 16,
+        "eth_getStorageAt" => 17,
+        "eth_getBalance" | "eth_getTransactionCount" => 19,
+        "eth_getProof" => 21,
+        "eth_call" | "eth_getCode" => 26,
+        "eth_getLogs" => 75,
+        _ => 20,
+    }
+}
+
+/// Budget of one endpoint, `None` means unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RateLimit {
+    pub requests_per_second: Option<u32>,
+    pub compute_units_per_second: Option<u32>,
+}
+
+impl RateLimit {
+    pub fn requests(per_second: u32) -> Self {
+        Self {
+            requests_per_second: Some(per_second),
+            compute_units_per_second: None,
+        }
+    }
+
+    pub fn compute_units(per_second: u32) -> Self {
+        Self {
+            requests_per_second: None,
+            compute_units_per_second: Some(per_second),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub url: Url,
+    pub limit: RateLimit,
+}
+
+/// Endpoints and retry policy of a `ResilientTransport`
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    pub endpoints: Vec<Endpoint>,
+    /// Time a single attempt on one endpoint may take
+    pub timeout: Duration,
+    /// Rounds over all endpoints after the first one fails
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RpcConfig {
+    /// Unlimited endpoints, tried in the given order
+    pub fn new(urls: Vec<Url>) -> Self {
+        Self {
+            endpoints: urls
+                .into_iter()
+                .map(|url| Endpoint {
+                    url,
+                    limit: RateLimit::default(),
+                })
+                .collect(),
+            timeout: Duration::from_secs(10),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    /// Add an endpoint with its own budget
+    pub fn endpoint(mut self, url: Url, limit: RateLimit) -> Self {
+        self.endpoints.push(Endpoint { url, limit });
+        self
+    }
+
+    /// Same budget for every endpoint added so far
+    pub fn limit(mut self, limit: RateLimit) -> Self {
+        for endpoint in &mut self.endpoints {
+            endpoint.limit = limit;
+        }
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    pub fn transport(self) -> Result<ResilientTransport, anyhow::Error> {
+        ResilientTransport::new(self)
+    }
+
+    /// Provider over a `ResilientTransport` with the usual fillers
+    pub fn connect(self) -> Result<Rpc, anyhow::Error> {
+        Ok(self.transport()?.provider())
+    }
+}
+
+/// Spaces requests out evenly so neither budget of an endpoint is exceeded
+#[derive(Debug)]
+struct Limiter {
+    limit: RateLimit,
+    /// Earliest time the next request and the next compute unit are free
+    next: Mutex<(Instant, Instant)>,
+}
+
+impl Limiter {
+    fn new(limit: RateLimit) -> Self {
+        let now = Instant::now();
+        Self {
+            limit,
+            next: Mutex::new((now, now)),
+        }
+    }
+
+    /// Reserve room for `requests` costing `units` and wait for it
+    async fn acquire(&self, requests: u32, units: u32) {
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let (next_request, next_unit) = &mut *next;
+            let now = Instant::now();
+            let request_at = (*next_request).max(now);
+            let unit_at = (*next_unit).max(now);
+
+            if let Some(per_second) = self.limit.requests_per_second {
+                *next_request = request_at + Duration::from_secs(requests as u64) / per_second;
+            }
+            if let Some(per_second) = self.limit.compute_units_per_second {
+                *next_unit = unit_at + Duration::from_secs(units as u64) / per_second;
+            }
+            request_at.max(unit_at)
+        };
+        tokio::time::sleep_until(start.into()).await;
+    }
+}
+
+/// Requests sent through a transport, every attempt counts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestCount {
+    pub requests: u64,
+    pub compute_units: u64,
+    /// Attempts that failed and were retried or failed over
+    pub failures: u64,
+    pub by_method: BTreeMap<String, u64>,
+}
+
+impl RequestCount {
+    /// What was sent between `earlier` and this snapshot
+    pub fn since(&self, earlier: &RequestCount) -> RequestCount {
+        RequestCount {
+            requests: self.requests - earlier.requests,
+            compute_units: self.compute_units - earlier.compute_units,
+            failures: self.failures - earlier.failures,
+            by_method: self
+                .by_method
+                .iter()
+                .map(|(method, n)| {
+                    let before = earlier.by_method.get(method).copied().unwrap_or(0);
+                    (method.clone(), n - before)
+                })
+                .filter(|(_, n)| *n > 0)
+                .collect(),
+        }
+    }
+}
+
+/// Sends every request to the current endpoint and moves on to the next one
+/// when it times out, fails at the transport level or reports a rate limit.
+/// After a full round without an answer it backs off and starts over.
+///
+/// JSON-RPC errors that are a real answer, like a revert, are returned as is.
+#[derive(Debug, Clone)]
+pub struct ResilientTransport {
+    endpoints: Arc<Vec<(Http<Client>, Limiter)>>,
+    /// Endpoint that answered last, shared by all clones
+    active: Arc<AtomicUsize>,
+    config: Arc<RpcConfig>,
+    count: Arc<Mutex<RequestCount>>,
+}
+
+impl ResilientTransport {
+    pub fn new(config: RpcConfig) -> Result<Self, anyhow::Error> {
+        if config.endpoints.is_empty() {
+            bail!("no RPC endpoints configured");
+        }
+        // a budget of 0 per second would never let a request through
+        for endpoint in &config.endpoints {
+            let RateLimit {
+                requests_per_second,
+                compute_units_per_second,
+            } = endpoint.limit;
+            if requests_per_second == Some(0) || compute_units_per_second == Some(0) {
+                bail!("rate limit of 0 per second for {}", endpoint.url);
+            }
+        }
+        let endpoints = config
+            .endpoints
+            .iter()
+            .map(|e| (Http::new(e.url.clone()), Limiter::new(e.limit)))
+            .collect();
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+            active: Arc::new(AtomicUsize::new(0)),
+            config: Arc::new(config),
+            count: Arc::new(Mutex::new(RequestCount::default())),
+        })
+    }
+
+    /// Provider sending through this transport, keep the transport around
+    /// to read its counters
+    pub fn provider(&self) -> Rpc {
+        ProviderBuilder::new().connect_client(RpcClient::new(self.clone(), false))
+    }
+
+    /// Index of the endpoint requests currently go to
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Snapshot of the counters, diff two with `RequestCount::since`
+    pub fn count(&self) -> RequestCount {
+        self.count.lock().unwrap().clone()
+    }
+
+    fn record(&self, request: &RequestPacket, units: u32) {
+        let mut count = self.count.lock().unwrap();
+        count.requests += request.len() as u64;
+        count.compute_units += units as u64;
+        for method in request.method_names() {
+            *count.by_method.entry(method.to_string()).or_default() += 1;
+        }
+    }
+
+    async fn send(self, request: RequestPacket) -> Result<ResponsePacket, TransportError> {
+        let count = self.endpoints.len();
+        let units: u32 = request.method_names().map(compute_units).sum();
+        let mut backoff = self.config.initial_backoff;
+        let mut last_error = None;
+
+        for round in 0..=self.config.max_retries {
+            if round > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(self.config.max_backoff);
+            }
+
+            for _ in 0..count {
+                let index = self.active();
+                let (endpoint, limiter) = &self.endpoints[index];
+                limiter.acquire(request.len() as u32, units).await;
+                self.record(&request, units);
+
+                match tokio::time::timeout(
+                    self.config.timeout,
+                    endpoint.clone().call(request.clone()),
+                )
+                .await
+                {
+                    Ok(Ok(response)) => match response.as_error() {
+                        Some(e) if e.is_retry_err() => {
+                            last_error = Some(TransportError::ErrorResp(e.clone()))
+                        }
+                        _ => return Ok(response),
+                    },
+                    Ok(Err(e)) => last_error = Some(e),
+                    Err(_) => {
+                        last_error = Some(TransportErrorKind::custom_str(&format!(
+                            "request timed out after {:?}",
+                            self.config.timeout
+                        )))
+                    }
+                }
+                self.count.lock().unwrap().failures += 1;
+
+                // another request may already have moved on
+                let _ = self.active.compare_exchange(
+                    index,
+                    (index + 1) % count,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+            }
+        }
+
+        let last_error = last_error.map(|e| e.to_string()).unwrap_or_default();
+        Err(TransportErrorKind::custom_str(&format!(
+            "all {count} endpoints failed after {} rounds, last error: {last_error}",
+            self.config.max_retries + 1
+        )))
+    }
+}
+
+impl Service<RequestPacket> for ResilientTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        Box::pin(self.clone().send(request))
+    }
+}
@@ -0,0 +1,230 @@
+//! Solidly pair math, ported from Velodrome V2's `Pool` so quotes match its
+//! `getAmountOut` to the wei. Aerodrome runs the same code, pairs of the
+//! first Solidly forks solve `y` with a slightly looser stop and can differ
+//! by a wei on stable pairs.
+
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, U256};
+
+use crate::curve_pool_sim::{add, div, mul, sub};
+use crate::err::{MathError, PoolError};
+use crate::token_tax::{TokenTaxes, TransferKind, TransferTax};
+use crate::trade::Trade;
+
+/// Scale of `fee`
+pub const FEE_DENOMINATOR: u64 = 10_000;
+const ONE: u128 = 1_000_000_000_000_000_000;
+
+/// Rounds of Newton iteration before the pool reverts with `!y`
+const MAX_ROUNDS: usize = 255;
+
+fn one() -> U256 {
+    U256::from(ONE)
+}
+
+/// `x0^3 * y + y^3 * x0` of 18 decimal amounts
+fn f(x0: U256, y: U256) -> Result<U256, MathError> {
+    let op = "solidly f";
+    let a = div(mul(x0, y, op)?, one(), op)?;
+    let b = add(
+        div(mul(x0, x0, op)?, one(), op)?,
+        div(mul(y, y, op)?, one(), op)?,
+        op,
+    )?;
+    div(mul(a, b, op)?, one(), op)
+}
+
+/// Derivative of `f` in `y`
+fn d(x0: U256, y: U256) -> Result<U256, MathError> {
+    let op = "solidly d";
+    let y2 = div(mul(y, y, op)?, one(), op)?;
+    let x2 = div(mul(x0, x0, op)?, one(), op)?;
+    add(
+        div(mul(mul(U256::from(3), x0, op)?, y2, op)?, one(), op)?,
+        div(mul(x2, x0, op)?, one(), op)?,
+        op,
+    )
+}
+
+/// Offline copy of a Solidly stable or volatile pair.
+#[derive(Debug, Clone)]
+pub struct SolidlyPoolSim {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    /// `x^3y + y^3x` when set, `xy` otherwise
+    pub stable: bool,
+    /// `10**decimals` of each token
+    pub decimals0: U256,
+    pub decimals1: U256,
+    pub reserves0: U256,
+    pub reserves1: U256,
+    /// Fee on the input in basis points, set per pair by the factory
+    pub fee: U256,
+    pub tax0: TransferTax,
+    pub tax1: TransferTax,
+}
+
+impl SolidlyPoolSim {
+    /// Take the transfer taxes of both tokens from `taxes`
+    pub fn apply_taxes(&mut self, taxes: &TokenTaxes) {
+        self.tax0 = taxes.get(&self.token0);
+        self.tax1 = taxes.get(&self.token1);
+    }
+
+    /// The pair's invariant, `_k`
+    fn k(&self, x: U256, y: U256) -> Result<U256, MathError> {
+        let op = "solidly k";
+        if !self.stable {
+            return mul(x, y, op);
+        }
+        let x = div(mul(x, one(), op)?, self.decimals0, op)?;
+        let y = div(mul(y, one(), op)?, self.decimals1, op)?;
+        f(x, y)
+    }
+
+    /// `y` that keeps `f(x0, y)` at `xy`, `_get_y`
+    fn get_y(&self, x0: U256, xy: U256, mut y: U256) -> Result<U256, MathError> {
+        let op = "solidly get_y";
+        for _ in 0..MAX_ROUNDS {
+            let k = f(x0, y)?;
+            if k < xy {
+                let mut dy = div(mul(xy - k, one(), op)?, d(x0, y)?, op)?;
+                if dy.is_zero() {
+                    if k == xy {
+                        return Ok(y);
+                    }
+                    // `_k`, not `_f`, like the pool: the decimals are
+                    // applied a second time
+                    if self.k(x0, add(y, U256::ONE, op)?)? > xy {
+                        return add(y, U256::ONE, op);
+                    }
+                    dy = U256::ONE;
+                }
+                y = add(y, dy, op)?;
+            } else {
+                let mut dy = div(mul(k - xy, one(), op)?, d(x0, y)?, op)?;
+                if dy.is_zero() {
+                    if k == xy || f(x0, sub(y, U256::ONE, op)?)? < xy {
+                        return Ok(y);
+                    }
+                    dy = U256::ONE;
+                }
+                y = sub(y, dy, op)?;
+            }
+        }
+        Err(MathError::NoConvergence { op })
+    }
+
+    /// Fee the pair takes from `amount_in`, sent on to its fee contract
+    fn fee_of(&self, amount_in: U256) -> Result<U256, MathError> {
+        let op = "solidly fee";
+        div(
+            mul(amount_in, self.fee, op)?,
+            U256::from(FEE_DENOMINATOR),
+            op,
+        )
+    }
+
+    /// Output for `amount_in` as received by the pair, `getAmountOut`
+    pub fn get_amount_out(&self, amount_in: U256, from0: bool) -> Result<U256, PoolError> {
+        if self.reserves0.is_zero() || self.reserves1.is_zero() {
+            return Err(PoolError::NoLiquidity { pool: self.address });
+        }
+        let op = "solidly amount out";
+        let amount_in = sub(amount_in, self.fee_of(amount_in)?, op)?;
+        let (decimals_in, decimals_out) = match from0 {
+            true => (self.decimals0, self.decimals1),
+            false => (self.decimals1, self.decimals0),
+        };
+
+        if !self.stable {
+            let (reserve_in, reserve_out) = match from0 {
+                true => (self.reserves0, self.reserves1),
+                false => (self.reserves1, self.reserves0),
+            };
+            let numerator = mul(amount_in, reserve_out, op)?;
+            return Ok(div(numerator, add(reserve_in, amount_in, op)?, op)?);
+        }
+
+        let xy = self.k(self.reserves0, self.reserves1)?;
+        let reserve0 = div(mul(self.reserves0, one(), op)?, self.decimals0, op)?;
+        let reserve1 = div(mul(self.reserves1, one(), op)?, self.decimals1, op)?;
+        let (reserve_a, reserve_b) = match from0 {
+            true => (reserve0, reserve1),
+            false => (reserve1, reserve0),
+        };
+        let amount_in = div(mul(amount_in, one(), op)?, decimals_in, op)?;
+        let y = self.get_y(add(amount_in, reserve_a, op)?, xy, reserve_b)?;
+        let y = sub(reserve_b, y, op)?;
+        Ok(div(mul(y, decimals_out, op)?, one(), op)?)
+    }
+
+    /// Swap `amount_in` sent by the trader, the pair prices what it
+    /// received after the input token's tax
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let (tax_in, tax_out) = match from0 {
+            true => (&self.tax0, &self.tax1),
+            false => (&self.tax1, &self.tax0),
+        };
+        let amount_in_net = tax_in.net(amount_in, TransferKind::Sell);
+        let tax_out = tax_out.clone();
+
+        let amount_out = self.get_amount_out(amount_in_net, from0)?;
+        let (amount0_in, amount1_in, amount0_out, amount1_out) = match from0 {
+            true => (amount_in_net, U256::ZERO, U256::ZERO, amount_out),
+            false => (U256::ZERO, amount_in_net, amount_out, U256::ZERO),
+        };
+        self.apply_swap(amount0_in, amount1_in, amount0_out, amount1_out)?;
+
+        Ok(Trade {
+            fee: self.fee_pips(),
+            token0: self.token0,
+            token1: self.token1,
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out,
+            amount_in_net,
+            amount_out_net: tax_out.net(amount_out, TransferKind::Buy),
+        })
+    }
+
+    /// Apply a swap of logged amounts, the fee part of the input leaves the
+    /// reserves
+    pub fn apply_swap(
+        &mut self,
+        amount0_in: U256,
+        amount1_in: U256,
+        amount0_out: U256,
+        amount1_out: U256,
+    ) -> Result<(), PoolError> {
+        let op = "solidly reserves";
+        let in0 = sub(amount0_in, self.fee_of(amount0_in)?, op)?;
+        let in1 = sub(amount1_in, self.fee_of(amount1_in)?, op)?;
+        self.reserves0 = sub(add(self.reserves0, in0, op)?, amount0_out, op)?;
+        self.reserves1 = sub(add(self.reserves1, in1, op)?, amount1_out, op)?;
+        Ok(())
+    }
+
+    /// Apply a `Sync` log, the pair logs its reserves after every change
+    pub fn apply_sync(&mut self, reserve0: U256, reserve1: U256) {
+        self.reserves0 = reserve0;
+        self.reserves1 = reserve1;
+    }
+
+    pub fn mint(&mut self, amount0: U256, amount1: U256) {
+        self.reserves0 = self.reserves0.saturating_add(amount0);
+        self.reserves1 = self.reserves1.saturating_add(amount1);
+    }
+
+    pub fn burn(&mut self, amount0: U256, amount1: U256) {
+        self.reserves0 = self.reserves0.saturating_sub(amount0);
+        self.reserves1 = self.reserves1.saturating_sub(amount1);
+    }
+
+    /// Fee in millionths, the unit `Trade` reports fees in
+    pub fn fee_pips(&self) -> U24 {
+        U24::saturating_from(self.fee * U256::from(1_000_000 / FEE_DENOMINATOR))
+    }
+}
@@ -0,0 +1,112 @@
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, U256};
+
+use crate::ISolidlyPool::ISolidlyPoolInstance;
+use crate::curve_pool_src::optional;
+use crate::err::PoolError;
+use crate::solidly_pool_sim::SolidlyPoolSim;
+use crate::token_tax::TransferTax;
+use crate::v3_pool_src::Rpc;
+use crate::{ISolidlyFactory, ISolidlyFactoryV1};
+
+type SolidlyContract = ISolidlyPoolInstance<Rpc>;
+
+/// A Solidly pair, stable or volatile, with the fee its factory charges it.
+///
+/// Velodrome V2 and Aerodrome factories price each pool on its own, the
+/// first forks one fee for all stable and one for all volatile pairs.
+#[derive(Debug)]
+pub struct SolidlyPoolSrc {
+    pub address: Address,
+    pub factory: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub stable: bool,
+    /// `10**decimals` of each token
+    pub decimals0: U256,
+    pub decimals1: U256,
+    pub reserves0: U256,
+    pub reserves1: U256,
+    /// Basis points of the input
+    pub fee: U256,
+    pub contract: SolidlyContract,
+}
+
+impl SolidlyPoolSrc {
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(address, provider, BlockId::latest()).await
+    }
+
+    /// Load the pair and its fee as of `block`
+    pub async fn new_at(
+        address: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = ISolidlyPoolInstance::new(address, provider);
+        let factory = contract.factory().call().block(block).await?;
+        let metadata = contract.metadata().call().block(block).await?;
+
+        let mut instance = Self {
+            address,
+            factory,
+            token0: metadata.t0,
+            token1: metadata.t1,
+            stable: metadata.st,
+            decimals0: metadata.dec0,
+            decimals1: metadata.dec1,
+            reserves0: metadata.r0,
+            reserves1: metadata.r1,
+            fee: U256::ZERO,
+            contract,
+        };
+        instance.fee = instance.read_fee(block).await?;
+        Ok(instance)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
+    }
+
+    /// Reload reserves and fee as of `block`
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let metadata = self.contract.metadata().call().block(block).await?;
+        self.reserves0 = metadata.r0;
+        self.reserves1 = metadata.r1;
+        self.fee = self.read_fee(block).await?;
+        Ok(())
+    }
+
+    async fn read_fee(&self, block: BlockId) -> Result<U256, PoolError> {
+        let provider = self.contract.provider().clone();
+        let factory = ISolidlyFactory::new(self.factory, provider.clone());
+        if let Some(fee) = optional(
+            factory
+                .getFee(self.address, self.stable)
+                .call()
+                .block(block),
+        )
+        .await?
+        {
+            return Ok(fee);
+        }
+        let factory = ISolidlyFactoryV1::new(self.factory, provider);
+        Ok(factory.getFee(self.stable).call().block(block).await?)
+    }
+
+    pub fn into_sim(&self) -> SolidlyPoolSim {
+        SolidlyPoolSim {
+            address: self.address,
+            token0: self.token0,
+            token1: self.token1,
+            stable: self.stable,
+            decimals0: self.decimals0,
+            decimals1: self.decimals1,
+            reserves0: self.reserves0,
+            reserves1: self.reserves1,
+            fee: self.fee,
+            tax0: TransferTax::None,
+            tax1: TransferTax::None,
+        }
+    }
+}
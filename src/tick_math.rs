@@ -2,6 +2,8 @@
 
 use alloy::primitives::{I256, U256, U512, aliases::I24};
 
+use crate::err::MathError;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Tick {
     pub tick: I24,
@@ -36,20 +38,14 @@ pub fn extract_ticks_from_bitmap(bitmap: U256, word_idx: I24, tick_spacing: I24)
 
 pub fn next_left(word: &U256, start: &i16) -> Option<usize> {
     // clamp start to valid range 0..=255
-    let mut idx = *start.max(&0_i16).min(&255_i16) as usize;
-    // scan forward until we find a set bit or run out of bits
-    while idx >= 0 {
-        if word.bit(idx) {
-            return Some(idx);
-        }
-        idx -= 1;
-    }
-    None
+    let start = (*start).clamp(0, 255) as usize;
+    // scan backward until we find a set bit or run out of bits
+    (0..=start).rev().find(|&idx| word.bit(idx))
 }
 
 pub fn next_right(word: &U256, start: &i16) -> Option<usize> {
     // clamp start to valid range 0..=255
-    let mut idx = *start.max(&0_i16).min(&255_i16) as usize;
+    let mut idx = (*start).clamp(0, 255) as usize;
     // scan forward until we find a set bit or run out of bits
     while idx <= 255 {
         if word.bit(idx) {
@@ -73,17 +69,12 @@ pub fn collect_ticks_from_map(
     ticks
 }
 
-pub fn price_from_tick(target_tick: I24) -> Option<U256> {
-    println!("calculating prince fomr tick: {}", target_tick);
+pub fn price_from_tick(target_tick: I24) -> Result<U256, MathError> {
     let max_tick: I24 = I24::try_from(887272).unwrap();
     let abs_tick = target_tick.abs();
 
     if abs_tick > max_tick {
-        eprintln!(
-            "[0] Tick {} exceeds maximum allowed (±{})",
-            target_tick, max_tick
-        );
-        return None;
+        return Err(MathError::TickOutOfRange(target_tick));
     }
 
     let mut sqrt_price_x128 = if (abs_tick & I24::ONE) != I24::ZERO {
@@ -178,15 +169,14 @@ pub fn price_from_tick(target_tick: I24) -> Option<U256> {
             // wrap on overflow, then shift down
             let (wrapped, _) = sqrt_price_x128.overflowing_mul(*magic);
             sqrt_price_x128 = wrapped >> 128;
-            println!("price {}", sqrt_price_x128);
-            println!("step {}", mask);
         }
     }
     let mut p256 = U256::from(sqrt_price_x128);
 
     if target_tick > I24::ZERO {
         if sqrt_price_x128.is_zero() {
-            return None; // Should ideally not happen if initial sqrt_price_x128 is non-zero
+            // Should ideally not happen if initial sqrt_price_x128 is non-zero
+            return Err(MathError::overflow("price from tick"));
         }
         p256 = U256::MAX.checked_div(p256).unwrap();
     }
@@ -203,19 +193,22 @@ pub fn price_from_tick(target_tick: I24) -> Option<U256> {
     // 5) cast to U160
     // let sqrt_price_x96 = U160::from(sqrt_price_x96_u256);
 
-    Some(sqrt_price_x96_u256)
+    Ok(sqrt_price_x96_u256)
 }
 // Convert a sqrt price Q128.96 to the nearest tick index (I24)
 /// Port of Uniswap V3's TickMath.getTickAtSqrtRatio
-pub fn tick_from_price(sqrt_price_x96: U256) -> Option<I24> {
+pub fn tick_from_price(sqrt_price_x96: U256) -> Result<I24, MathError> {
     // Define bounds as U256 to avoid u128 overflow
     let min_sqrt = U256::from(4295128739u64);
     let max_sqrt =
         U256::from_str_radix("1461446703485210103287273052203988822378723970342", 10).unwrap();
 
     if sqrt_price_x96 < min_sqrt || sqrt_price_x96 >= max_sqrt {
-        eprintln!("Sqrt price {} out of bounds", sqrt_price_x96);
-        return None;
+        return Err(MathError::PriceOutOfBounds {
+            price: sqrt_price_x96,
+            min: min_sqrt,
+            max: max_sqrt,
+        });
     }
 
     // Convert to Q128.128 for log calculation
@@ -223,16 +216,13 @@ pub fn tick_from_price(sqrt_price_x96: U256) -> Option<I24> {
 
     // Compute log2(sqrroot_price_x128)
     let msb = 255 - sqrroot_price_x128.leading_zeros();
-    println!("most significant bit {}", msb);
     let mut log2: I256 = (I256::try_from(msb).unwrap() - I256::try_from(128u8).unwrap()) << 64;
 
-    println!("log2 before loop: {}", log2);
     let mut r = if msb >= 128 {
         sqrroot_price_x128 >> (msb - 127)
     } else {
         sqrroot_price_x128 << (127 - msb)
     };
-    println!("r: {}", r);
     for i in 0..14 {
         r = (r * r) >> 127;
         let f: U256 = r >> 128;
@@ -242,42 +232,45 @@ pub fn tick_from_price(sqrt_price_x96: U256) -> Option<I24> {
         r >>= f;
     }
 
-    println!("log2 after loop: {}", log2);
     let log_sqrt10001 = log2 * I256::try_from("255738958999603826347141").unwrap();
     I256::try_from("255738958999603826347141").unwrap();
 
-    println!("log2 afet / 2^128: {}", log_sqrt10001);
-    let low = (log_sqrt10001 - I256::try_from("3402992956809132418596140100660247210").unwrap())
-        >> 128_u32;
+    // arithmetic shift, ticks below zero have to stay negative
+    let low =
+        (log_sqrt10001 - I256::try_from("3402992956809132418596140100660247210").unwrap()).asr(128);
     let high = (log_sqrt10001 + I256::try_from("291339464771989622907027621153398088495").unwrap())
-        >> 128_u32;
-    println!("high {}", high);
-    println!("low {}", low);
+        .asr(128);
 
     // Calculate candidate ticks
-    let tick_low: I24 = I24::from(low);
-    let tick_high: I24 = I24::from(high);
-
-    println!("low: {} = high: {}", tick_low, tick_high);
+    let to_tick = |v: I256| {
+        i32::try_from(v)
+            .ok()
+            .and_then(|v| I24::try_from(v).ok())
+            .ok_or(MathError::overflow("tick from price"))
+    };
+    let tick_low: I24 = to_tick(low)?;
+    let tick_high: I24 = to_tick(high)?;
 
+    // the greatest tick whose price isn't above the input, as in
+    // getTickAtSqrtRatio, so a tick's own price maps back to it
     let result = if tick_high == tick_low {
         tick_high
     } else {
-        if price_from_tick(tick_high)? >= sqrt_price_x96 {
+        if price_from_tick(tick_high)? <= sqrt_price_x96 {
             tick_high
         } else {
             tick_low
         }
     };
-    Some(result)
+    Ok(result)
 }
 pub fn compute_amount_possible(
     from0: bool,
     available_liquidity: &U256,
     current_sqrt_price: &U256,
     next_sqrt_price: &U256,
-) -> Option<U256> {
-    println!("computing amount possible");
+) -> Result<U256, MathError> {
+    let overflow = || MathError::overflow("amount to next price");
     // Q96 = 2^96
     let q96: U512 = U512::ONE << 96;
 
@@ -288,45 +281,33 @@ pub fn compute_amount_possible(
 
     if from0 {
         // Δx = L·(√P_next − √P_curr)·Q96 ÷ (√P_curr·√P_next)
-        println!("from 0");
-        println!("next price {}", next_sqrt_price);
-        println!("current price {}", current_sqrt_price);
 
-        let diff = nxt.checked_sub(cur)?;
-        println!("passed");
+        let diff = nxt.checked_sub(cur).ok_or_else(overflow)?;
 
+        // already at the next price, nothing to swap to reach it
         if diff.is_zero() {
-            println!("diff is zezo");
-            return None;
+            return Ok(U256::ZERO);
         }
 
-        println!("q 96 {}", q96);
-        println!("available liquidity {}", available_liquidity);
-        println!("diff {}", diff);
         // numerator = L * diff * Q96
-        let impact = liq.checked_mul(diff)?;
-        let numerator: U512 = U512::from(impact).checked_mul(q96)?;
-        println!("numerator {}", numerator);
+        let impact = liq.checked_mul(diff).ok_or_else(overflow)?;
+        let numerator: U512 = U512::from(impact).checked_mul(q96).ok_or_else(overflow)?;
         // denominator = cur * nxt
-        let denominator = cur.checked_mul(nxt)?;
-        println!("denominator {}", denominator);
+        let denominator = cur.checked_mul(nxt).ok_or_else(overflow)?;
 
-        let res = U256::from(numerator.checked_div(denominator)?);
+        let res = U256::from(numerator.checked_div(denominator).ok_or_else(overflow)?);
 
-        Some(res)
+        Ok(res)
     } else {
-        println!("from 1");
         // Δy = L·(√P_curr − √P_next) ÷ Q96
-        let diff = cur.checked_sub(nxt)?;
-        println!("diff {}", diff);
+        let diff = cur.checked_sub(nxt).ok_or_else(overflow)?;
+        // same as above, a zero step rather than an error
         if diff.is_zero() {
-            println!("diff is zero");
-            return None;
+            return Ok(U256::ZERO);
         }
 
-        let numerator = liq.checked_mul(diff)?;
-        println!("numerator {}", numerator);
-        Some(U256::from(numerator.checked_div(q96)?))
+        let numerator = liq.checked_mul(diff).ok_or_else(overflow)?;
+        Ok(U256::from(numerator.checked_div(q96).ok_or_else(overflow)?))
     }
 }
 /// Given Δy (token1 amount) and liquidity L, compute the next √P
@@ -335,33 +316,27 @@ pub fn compute_price_from0(
     available_liquidity: &U256,
     current_sqrt_price: &U256,
     add: bool,
-) -> Option<U256> {
-    // Debug prints (optional)
-    // println!("Inputs:");
-    // println!("  Δx (amount): {}", amount);
-    // println!("  L (liquidity): {}", available_liquidity);
-    // println!("  √P (current_sqrt_price): {}", current_sqrt_price);
+) -> Result<U256, MathError> {
+    let overflow = || MathError::overflow("price from amount0");
     // Step 1: Compute L << 96 (Q96L)
     let q96_l = *available_liquidity << (U256::from(96_u32));
-    // println!("Q96L (L << 96): {}", Q96L);
 
     // Step 2: Compute (L << 96) / √P (scaled_liquidity)
-    let scaled_liquidity = q96_l.checked_div(U256::from(*current_sqrt_price))?;
-    // println!("scaled_liquidity (Q96L / √P): {}", scaled_liquidity);
+    let scaled_liquidity = q96_l
+        .checked_div(U256::from(*current_sqrt_price))
+        .ok_or_else(overflow)?;
 
     // Step 3: Compute denominator = scaled_liquidity ± Δx
     let denominator = if add {
-        scaled_liquidity.checked_add(*amount)?
+        scaled_liquidity.checked_add(*amount).ok_or_else(overflow)?
     } else {
-        scaled_liquidity.checked_sub(*amount)?
+        scaled_liquidity.checked_sub(*amount).ok_or_else(overflow)?
     };
-    // println!("denominator (scaled_liquidity ± Δx): {}", denominator);
 
     // Step 4: Compute new_sqrt_price = Q96L / denominator
-    let new_sqrt_price = q96_l.checked_div(denominator)?;
-    // println!("new_sqrt_price (Q96L / denominator): {}", new_sqrt_price);
+    let new_sqrt_price = q96_l.checked_div(denominator).ok_or_else(overflow)?;
 
-    Some(new_sqrt_price)
+    Ok(new_sqrt_price)
 } // Given Δy (token1 amount) and liquidity L, compute the next √P
 /// note: everything in Q96 fixed‐point (i.e. <<96) internally
 pub fn compute_price_from1(
@@ -369,32 +344,37 @@ pub fn compute_price_from1(
     available_liquidity: &U256,
     current_sqrt_price: &U256,
     add: bool,
-) -> Option<U256> {
+) -> Result<U256, MathError> {
+    let overflow = || MathError::overflow("price from amount1");
     // Q96 = 2^96
     let q96 = U256::ONE << 96;
     // 1) Scale amount into Q96:   Δy * Q96
-    let dy_q96 = amount.checked_mul(q96)?;
+    let dy_q96 = amount.checked_mul(q96).ok_or_else(overflow)?;
     // 2) Divide by liquidity:    Δ√P = (Δy·Q96) / L
     let liquidity_u256 = U256::from(*available_liquidity);
-    let delta_sqrt = dy_q96.checked_div(liquidity_u256)?;
+    let delta_sqrt = dy_q96.checked_div(liquidity_u256).ok_or_else(overflow)?;
     // 3) Apply to current √P
     let cur: U256 = U256::from(*current_sqrt_price);
     let next = if add {
-        cur.checked_add(delta_sqrt)?
+        cur.checked_add(delta_sqrt).ok_or_else(overflow)?
     } else {
-        cur.checked_sub(delta_sqrt)?
+        cur.checked_sub(delta_sqrt).ok_or_else(overflow)?
     };
-    Some(next)
+    Ok(next)
 }
-pub fn update_liquidity(current_liquidity: U256, liquidity_net: i128) -> Option<U256> {
+pub fn update_liquidity(current_liquidity: U256, liquidity_net: i128) -> Result<U256, MathError> {
     if liquidity_net < 0 {
         // If liquidity_net is negative, it means liquidity is removed.
         // We need to subtract the absolute value of liquidity_net.
-        let abs_net = U256::from(liquidity_net.abs() as u128); // Convert abs(i128) to u128 then U256
-        current_liquidity.checked_sub(abs_net)
+        let abs_net = U256::from(liquidity_net.unsigned_abs()); // Convert abs(i128) to u128 then U256
+        current_liquidity
+            .checked_sub(abs_net)
+            .ok_or(MathError::overflow("liquidity update"))
     } else {
         // If liquidity_net is positive or zero, it means liquidity is added.
         let pos_net = U256::from(liquidity_net as u128); // Convert positive i128 to u128 then U256
-        current_liquidity.checked_add(pos_net)
+        current_liquidity
+            .checked_add(pos_net)
+            .ok_or(MathError::overflow("liquidity update"))
     }
 }
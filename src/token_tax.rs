@@ -0,0 +1,127 @@
+//! Transfer taxes of fee-on-transfer tokens, applied by the sims to amounts
+//! entering and leaving a pool.
+//!
+//! Finding a token's rate is left to the caller, e.g. by simulating a buy and
+//! a sell against a fork; the sims only apply what they are given.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use alloy::primitives::{Address, U256};
+
+/// Which way a token moves relative to a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    /// Out of a pool to the trader
+    Buy,
+    /// From the trader into a pool
+    Sell,
+    /// Anything else, wallet to wallet
+    Transfer,
+}
+
+/// Amount that arrives out of `amount` sent
+pub type TaxFn = dyn Fn(U256, TransferKind) -> U256 + Send + Sync;
+
+#[derive(Clone, Default)]
+pub enum TransferTax {
+    #[default]
+    None,
+    /// Basis points burned or skimmed per kind of transfer
+    Percent {
+        buy_bps: u32,
+        sell_bps: u32,
+        transfer_bps: u32,
+    },
+    /// Tokens whose tax isn't a flat rate, e.g. tiered or time based
+    Custom(Arc<TaxFn>),
+}
+
+impl TransferTax {
+    /// Same rate on every transfer
+    pub fn flat(bps: u32) -> Self {
+        Self::Percent {
+            buy_bps: bps,
+            sell_bps: bps,
+            transfer_bps: bps,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+
+    fn bps(&self, kind: TransferKind) -> Option<u32> {
+        match (self, kind) {
+            (Self::None, _) => Some(0),
+            (Self::Percent { buy_bps, .. }, TransferKind::Buy) => Some(*buy_bps),
+            (Self::Percent { sell_bps, .. }, TransferKind::Sell) => Some(*sell_bps),
+            (Self::Percent { transfer_bps, .. }, TransferKind::Transfer) => Some(*transfer_bps),
+            (Self::Custom(_), _) => None,
+        }
+    }
+
+    /// What the receiver gets when `amount` is sent, the tax rounds down
+    /// like `amount * tax / 10_000` in the token contract
+    pub fn net(&self, amount: U256, kind: TransferKind) -> U256 {
+        match self {
+            Self::Custom(f) => f(amount, kind),
+            _ => {
+                let bps = self.bps(kind).unwrap_or(0).min(10_000);
+                amount - amount * U256::from(bps) / U256::from(10_000)
+            }
+        }
+    }
+
+    /// Smallest amount to send so at least `net` arrives, `None` when the
+    /// tax can't be inverted (custom functions, a 100% tax)
+    pub fn gross(&self, net: U256, kind: TransferKind) -> Option<U256> {
+        let bps = self.bps(kind)?;
+        if bps == 0 {
+            return Some(net);
+        }
+        let kept = U256::from(10_000u32.checked_sub(bps).filter(|k| *k > 0)?);
+        let gross = net.checked_mul(U256::from(10_000))?.div_ceil(kept);
+        Some(gross)
+    }
+}
+
+impl fmt::Debug for TransferTax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::Percent {
+                buy_bps,
+                sell_bps,
+                transfer_bps,
+            } => f
+                .debug_struct("Percent")
+                .field("buy_bps", buy_bps)
+                .field("sell_bps", sell_bps)
+                .field("transfer_bps", transfer_bps)
+                .finish(),
+            Self::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// Known taxed tokens, every other token transfers in full
+#[derive(Debug, Clone, Default)]
+pub struct TokenTaxes {
+    by_token: HashMap<Address, TransferTax>,
+}
+
+impl TokenTaxes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, token: Address, tax: TransferTax) {
+        self.by_token.insert(token, tax);
+    }
+
+    pub fn get(&self, token: &Address) -> TransferTax {
+        self.by_token.get(token).cloned().unwrap_or_default()
+    }
+}
@@ -1,5 +1,7 @@
 use alloy::primitives::{Address, U256, aliases::U24};
 
+use crate::currency::Currency;
+
 #[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Trade {
     pub fee: U24,
@@ -7,6 +9,22 @@ pub struct Trade {
     pub token1: Address,
     pub pool: Address,
     pub from0: bool,
+    /// Sent by the trader
     pub amount_in: U256,
+    /// Sent by the pool
     pub amount_out: U256,
+    /// What reached the pool after the input token's transfer tax
+    pub amount_in_net: U256,
+    /// What reached the trader after the output token's transfer tax
+    pub amount_out_net: U256,
+}
+
+impl Trade {
+    pub fn currency_in(&self) -> Currency {
+        Currency::from(if self.from0 { self.token0 } else { self.token1 })
+    }
+
+    pub fn currency_out(&self) -> Currency {
+        Currency::from(if self.from0 { self.token1 } else { self.token0 })
+    }
 }
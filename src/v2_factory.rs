@@ -0,0 +1,188 @@
+//! Discovers the pairs of a Uniswap V2 style factory, by index through
+//! `allPairs` or from its `PairCreated` logs, instead of keeping pool lists
+//! by hand.
+//!
+//! Every pair comes out as an unloaded `V2PoolSrc` with the name and fee the
+//! `FeeRegistry` has for the factory, call `update` on it to read the
+//! reserves, and the pair's own fee where the factory lets pairs set one.
+
+use alloy::eips::BlockId;
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{Address, Bytes, U256};
+use alloy::rpc::client::BatchRequest;
+use alloy::rpc::types::{Filter, TransactionRequest};
+use alloy::sol_types::{SolCall, SolEvent};
+use alloy_provider::Provider;
+
+use crate::IUniswapV2Factory::{self, IUniswapV2FactoryInstance};
+use crate::IUniswapV2Pair;
+use crate::err::PoolError;
+use crate::v2_fees::FeeRegistry;
+use crate::v2_pool_src::V2PoolSrc;
+use crate::v3_pool_src::Rpc;
+
+#[derive(Debug, Clone)]
+pub struct V2Factory {
+    pub address: Address,
+    provider: Rpc,
+    /// Fees applied to every pair, the Uniswap defaults stay on pairs of
+    /// factories it doesn't know
+    fees: FeeRegistry,
+    /// `eth_call`s per JSON-RPC batch, nodes usually cap batches somewhere
+    /// between 50 and 1000 calls
+    batch_size: usize,
+}
+
+impl V2Factory {
+    pub fn new(address: Address, provider: Rpc, fees: &FeeRegistry, batch_size: usize) -> Self {
+        Self {
+            address,
+            provider,
+            fees: fees.clone(),
+            batch_size,
+        }
+    }
+
+    fn contract(&self) -> IUniswapV2FactoryInstance<Rpc> {
+        IUniswapV2FactoryInstance::new(self.address, self.provider.clone())
+    }
+
+    pub async fn pair_count(&self, block: BlockId) -> Result<u64, PoolError> {
+        let count = self.contract().allPairsLength().call().block(block).await?;
+        u64::try_from(count).map_err(|_| PoolError::Decode(format!("pair count {count}")))
+    }
+
+    /// Pair for two tokens in either order, `None` if the factory has none
+    pub async fn get_pair(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        block: BlockId,
+    ) -> Result<Option<V2PoolSrc>, PoolError> {
+        let pair = self
+            .contract()
+            .getPair(token_a, token_b)
+            .call()
+            .block(block)
+            .await?;
+        if pair.is_zero() {
+            return Ok(None);
+        }
+        // pairs sort their tokens the same way
+        let (token0, token1) = if token_a < token_b {
+            (token_a, token_b)
+        } else {
+            (token_b, token_a)
+        };
+        Ok(Some(self.pair(pair, token0, token1)))
+    }
+
+    /// Addresses of pairs `from..to` by creation order, batched
+    pub async fn pair_addresses(
+        &self,
+        from: u64,
+        to: u64,
+        block: BlockId,
+    ) -> Result<Vec<Address>, PoolError> {
+        let calls: Vec<_> = (from..to)
+            .map(|index| {
+                let call = IUniswapV2Factory::allPairsCall {
+                    index: U256::from(index),
+                };
+                (self.address, call)
+            })
+            .collect();
+        self.batch_call(&calls, block).await
+    }
+
+    /// Every pair of the factory as of `block`, at two batched rounds of
+    /// calls per `batch_size` pairs
+    pub async fn all_pairs(&self, block: BlockId) -> Result<Vec<V2PoolSrc>, PoolError> {
+        let count = self.pair_count(block).await?;
+        let addresses = self.pair_addresses(0, count, block).await?;
+        self.pairs_at(&addresses, block).await
+    }
+
+    /// Read the tokens of `pairs`, batched
+    pub async fn pairs_at(
+        &self,
+        pairs: &[Address],
+        block: BlockId,
+    ) -> Result<Vec<V2PoolSrc>, PoolError> {
+        let token0 = pairs
+            .iter()
+            .map(|&pair| (pair, IUniswapV2Pair::token0Call {}));
+        let token1 = pairs
+            .iter()
+            .map(|&pair| (pair, IUniswapV2Pair::token1Call {}));
+        let token0 = self.batch_call(&token0.collect::<Vec<_>>(), block).await?;
+        let token1 = self.batch_call(&token1.collect::<Vec<_>>(), block).await?;
+
+        Ok(pairs
+            .iter()
+            .zip(token0.into_iter().zip(token1))
+            .map(|(&pair, (token0, token1))| self.pair(pair, token0, token1))
+            .collect())
+    }
+
+    /// Pairs created between `from_block` and `to_block` inclusive, from the
+    /// factory's logs in chunks of `max_block_range` blocks
+    pub async fn created_pairs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        max_block_range: u64,
+    ) -> Result<Vec<V2PoolSrc>, PoolError> {
+        let mut pairs = Vec::new();
+        let mut from = from_block;
+
+        while from <= to_block {
+            let to = to_block.min(from + max_block_range.max(1) - 1);
+            let filter = Filter::new()
+                .address(self.address)
+                .event_signature(IUniswapV2Factory::PairCreated::SIGNATURE_HASH)
+                .from_block(from)
+                .to_block(to);
+
+            for log in self.provider.get_logs(&filter).await? {
+                let created = IUniswapV2Factory::PairCreated::decode_log_data(log.data())?;
+                pairs.push(self.pair(created.pair, created.token0, created.token1));
+            }
+            from = to + 1;
+        }
+
+        Ok(pairs)
+    }
+
+    fn pair(&self, address: Address, token0: Address, token1: Address) -> V2PoolSrc {
+        let mut pair =
+            V2PoolSrc::unloaded(address, self.address, token0, token1, self.provider.clone());
+        pair.apply_fees(&self.fees);
+        pair
+    }
+
+    /// Send `calls` as JSON-RPC batches of `batch_size` `eth_call`s
+    async fn batch_call<C: SolCall>(
+        &self,
+        calls: &[(Address, C)],
+        block: BlockId,
+    ) -> Result<Vec<C::Return>, PoolError> {
+        let mut returns = Vec::with_capacity(calls.len());
+        for chunk in calls.chunks(self.batch_size.max(1)) {
+            let mut batch = BatchRequest::new(self.provider.client());
+            let mut waiters = Vec::with_capacity(chunk.len());
+            for (to, call) in chunk {
+                let tx = TransactionRequest::default()
+                    .with_to(*to)
+                    .with_input(call.abi_encode());
+                waiters.push(batch.add_call::<_, Bytes>("eth_call", &(tx, block))?);
+            }
+            batch.send().await?;
+
+            for waiter in waiters {
+                returns.push(C::abi_decode_returns(&waiter.await?)?);
+            }
+        }
+        Ok(returns)
+    }
+}
@@ -0,0 +1,108 @@
+//! Swap fees of V2 forks, looked up by the factory that deployed a pair.
+
+use std::collections::HashMap;
+
+use alloy::primitives::{Address, address};
+
+/// Fee charged on the input, `fee / denominator`, the way the fork's
+/// `getAmountOut` spells it (Uniswap `997/1000` is `3/1000`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct V2Fee {
+    pub fee: u32,
+    pub denominator: u32,
+}
+
+impl V2Fee {
+    pub const fn new(fee: u32, denominator: u32) -> Self {
+        Self { fee, denominator }
+    }
+
+    /// A share of the input short of all of it, anything else can't be priced
+    pub fn is_valid(&self) -> bool {
+        self.fee < self.denominator
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V2Dex {
+    pub exchange: String,
+    pub version: String,
+    pub fee: V2Fee,
+    /// Every pair keeps its own `swapFee()` over `fee.denominator`, `fee`
+    /// is only what new pairs start at
+    pub pair_fee: bool,
+}
+
+/// Factory address to DEX name and fee
+#[derive(Debug, Clone, Default)]
+pub struct FeeRegistry {
+    by_factory: HashMap<Address, V2Dex>,
+}
+
+impl FeeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the well known factories on Ethereum and BSC
+    pub fn with_known() -> Self {
+        let mut registry = Self::new();
+        let known = [
+            (
+                address!("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+                "uniswap",
+                V2Fee::new(3, 1_000),
+            ),
+            (
+                address!("0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"),
+                "sushiswap",
+                V2Fee::new(3, 1_000),
+            ),
+            (
+                address!("0xcA143Ce32Fe78f1f7019d7d551a6402fC5350c73"),
+                "pancakeswap",
+                V2Fee::new(25, 10_000),
+            ),
+            (
+                address!("0x0841BD0B734E4F5853f0dD8d7Ea041c241fb0Da6"),
+                "apeswap",
+                V2Fee::new(2, 1_000),
+            ),
+        ];
+        for (factory, exchange, fee) in known {
+            registry.insert(factory, exchange, fee);
+        }
+        // pairs start at 0.1% but each one can change its own swapFee
+        registry.insert_pair_fee(
+            address!("0x858E3312ed3A876947EA49d572A7C42DE08af7EE"),
+            "biswap",
+            V2Fee::new(1, 1_000),
+        );
+        registry
+    }
+
+    pub fn insert(&mut self, factory: Address, exchange: &str, fee: V2Fee) {
+        self.add(factory, exchange, fee, false);
+    }
+
+    /// Factory whose pairs each read their fee from `swapFee()`
+    pub fn insert_pair_fee(&mut self, factory: Address, exchange: &str, fee: V2Fee) {
+        self.add(factory, exchange, fee, true);
+    }
+
+    fn add(&mut self, factory: Address, exchange: &str, fee: V2Fee, pair_fee: bool) {
+        self.by_factory.insert(
+            factory,
+            V2Dex {
+                exchange: exchange.to_string(),
+                version: "v2".to_string(),
+                fee,
+                pair_fee,
+            },
+        );
+    }
+
+    pub fn get(&self, factory: &Address) -> Option<&V2Dex> {
+        self.by_factory.get(factory)
+    }
+}
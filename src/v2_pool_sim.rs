@@ -1,21 +1,29 @@
-use alloy::primitives::{Address, U256};
+use alloy::primitives::{Address, U256, aliases::U24};
 
+use crate::err::{MathError, PoolError};
+use crate::token_tax::{TokenTaxes, TransferKind, TransferTax};
 use crate::trade::Trade;
+use crate::v2_fees::V2Fee;
 
-#[derive(Debug,)]
+#[derive(Debug, Clone,)]
 pub struct V2PoolSim {
     pub address: Address,
     pub token0: Address,
     pub token1: Address,
     pub exchange: String,
     pub version: String,
+    /// Fee taken from the input, `fee / fee_denominator`
     pub fee: u32,
+    pub fee_denominator: u32,
     pub reserves0: U256,
     pub reserves1: U256,
+    pub tax0: TransferTax,
+    pub tax1: TransferTax,
 }
 
 impl V2PoolSim {
-    // Private constructor
+    /// `fee` is in millionths like V3 fees, `with_fee` sets another scale
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         exchange: String, version: String, fee: u32, address: Address, token0: Address, token1: Address, reserves0: U256,
         reserves1: U256,
@@ -27,15 +35,57 @@ impl V2PoolSim {
             exchange,
             version,
             fee,
+            fee_denominator: 1_000_000,
             reserves0,
             reserves1,
+            tax0: TransferTax::None,
+            tax1: TransferTax::None,
         }
     }
 
-    pub fn trade(&mut self, amount_in: U256, from0: bool,) -> Option<Trade,> {
+    /// Take the transfer taxes of both tokens from `taxes`
+    pub fn apply_taxes(&mut self, taxes: &TokenTaxes,) {
+        self.tax0 = taxes.get(&self.token0,);
+        self.tax1 = taxes.get(&self.token1,);
+    }
+
+    /// Taxes of the token going in and the token coming out
+    fn taxes(&self, from0: bool,) -> (&TransferTax, &TransferTax,) {
+        match from0 {
+            true => (&self.tax0, &self.tax1,),
+            false => (&self.tax1, &self.tax0,),
+        }
+    }
+
+    /// Charge `fee / denominator` instead, refused unless it's a valid `V2Fee`
+    pub fn with_fee(mut self, fee: u32, denominator: u32,) -> Result<Self, PoolError,> {
+        if !V2Fee::new(fee, denominator,).is_valid() {
+            return Err(PoolError::Unsupported {
+                pool: self.address,
+                reason: format!("fee {fee}/{denominator}",),
+            },);
+        }
+        self.fee = fee;
+        self.fee_denominator = denominator;
+        Ok(self,)
+    }
+
+    /// Fee in millionths, the unit `Trade` reports fees in
+    pub fn fee_pips(&self,) -> U24 {
+        U24::from(self.fee as u64 * 1_000_000 / self.fee_denominator as u64,)
+    }
+
+    /// Swap `amount_in` sent by the trader, the pair prices what it
+    /// received after the input token's tax
+    pub fn trade(&mut self, amount_in: U256, from0: bool,) -> Result<Trade, PoolError,> {
+        let (tax_in, tax_out,) = self.taxes(from0,);
+        let amount_in_net = tax_in.net(amount_in, TransferKind::Sell,);
+        let tax_out = tax_out.clone();
+
         if (from0 && self.reserves0 == U256::ZERO) || (!from0 && self.reserves1 == U256::ZERO) {
-            return None;
+            return Err(PoolError::NoLiquidity { pool: self.address, },);
         }
+        let overflow = || MathError::overflow("v2 amount out",);
 
         // 2. Get reserves in proper decimal scale
         let (reserve_in, reserve_out,) = match from0 {
@@ -43,24 +93,20 @@ impl V2PoolSim {
 
             false => (self.reserves1, self.reserves0,),
         };
-        // 3. Apply V2 fee calculation correctly (0.3% fee)
-        let amount_in_less_fee = amount_in.checked_mul(U256::from(997,),)?.checked_div(U256::from(1000,),)?;
-        let numerator = amount_in_less_fee.checked_mul(reserve_out,)?;
-        let denominator = reserve_in.checked_add(amount_in_less_fee,)?;
-        let amount_out = numerator.checked_div(denominator,)?;
-
-        let new_reserve_in = reserve_in.checked_add(amount_in_less_fee,)?;
-        let new_reserve_out = reserve_out.checked_sub(amount_out,)?;
-    
-        // Multiply numerator first to preserve precision (like fixed-point math)
-        let scale = U256::from(10,).pow(U256::from(18),); // or 1e6 if 1e18 feels too big
-        let current_price = reserve_out.checked_mul(scale,)?.checked_div(reserve_in,)?;
-        let new_price = new_reserve_out.checked_mul(scale,)?.checked_div(new_reserve_in,)?;
-
-        let price_impact = current_price
-            .checked_sub(new_price,)?
-            .checked_mul(U256::from(10000,),)?
-            .checked_div(current_price,)?;
+        // 3. getAmountOut, in the router's order so the rounding matches
+        let scale = U256::from(self.fee_denominator,);
+        let fee_multiplier = scale.checked_sub(U256::from(self.fee,),).ok_or_else(overflow,)?;
+        let amount_in_with_fee = amount_in_net.checked_mul(fee_multiplier,).ok_or_else(overflow,)?;
+        let numerator = amount_in_with_fee.checked_mul(reserve_out,).ok_or_else(overflow,)?;
+        let denominator = reserve_in
+            .checked_mul(scale,)
+            .and_then(|r| r.checked_add(amount_in_with_fee,),)
+            .ok_or_else(overflow,)?;
+        let amount_out = numerator.checked_div(denominator,).ok_or_else(overflow,)?;
+
+        // the fee stays in the pair
+        let new_reserve_in = reserve_in.checked_add(amount_in_net,).ok_or_else(overflow,)?;
+        let new_reserve_out = reserve_out.checked_sub(amount_out,).ok_or_else(overflow,)?;
 
         // Commit state
         if from0 {
@@ -71,19 +117,78 @@ impl V2PoolSim {
             self.reserves0 = new_reserve_out;
         }
 
-        Some(Trade {
-            dex: self.exchange.clone(),
-            version: self.version.clone(),
-            fee: self.fee,
+        Ok(Trade {
+            fee: self.fee_pips(),
+            token0: self.token0,
+            token1: self.token1,
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out,
+            amount_in_net,
+            amount_out_net: tax_out.net(amount_out, TransferKind::Buy,),
+        },)
+    }
+
+    /// Input needed to get exactly `amount_out`, the router's `getAmountIn`
+    /// with its `+1` so the quoted input never falls short
+    pub fn quote_in(&self, amount_out: U256, from0: bool,) -> Result<U256, PoolError,> {
+        let (reserve_in, reserve_out,) = match from0 {
+            true => (self.reserves0, self.reserves1,),
+            false => (self.reserves1, self.reserves0,),
+        };
+        if reserve_in.is_zero() || amount_out >= reserve_out {
+            return Err(PoolError::NoLiquidity { pool: self.address, },);
+        }
+        let overflow = || MathError::overflow("v2 amount in",);
+
+        let scale = U256::from(self.fee_denominator,);
+        let fee_multiplier = scale.checked_sub(U256::from(self.fee,),).ok_or_else(overflow,)?;
+        let numerator = reserve_in
+            .checked_mul(amount_out,)
+            .and_then(|n| n.checked_mul(scale,),)
+            .ok_or_else(overflow,)?;
+        let denominator = (reserve_out - amount_out).checked_mul(fee_multiplier,).ok_or_else(overflow,)?;
+        let amount_in = numerator.checked_div(denominator,).ok_or_else(overflow,)? + U256::ONE;
+        Ok(amount_in,)
+    }
+
+    /// Exact-output counterpart of `trade`, the trader receives
+    /// `amount_out` after the output token's tax. `quote_in` alone prices
+    /// the pair without taxes
+    pub fn trade_exact_out(&mut self, amount_out: U256, from0: bool,) -> Result<Trade, PoolError,> {
+        let (tax_in, tax_out,) = self.taxes(from0,);
+        let untaxable = || PoolError::Unsupported {
+            pool: self.address,
+            reason: "exact output through a custom transfer tax".into(),
+        };
+        let wanted = amount_out;
+        let amount_out = tax_out.gross(wanted, TransferKind::Buy,).ok_or_else(untaxable,)?;
+        let needed = self.quote_in(amount_out, from0,)?;
+        let amount_in = tax_in.gross(needed, TransferKind::Sell,).ok_or_else(untaxable,)?;
+        // rounding the gross amounts up can land a wei or so over what's needed
+        let amount_in_net = tax_in.net(amount_in, TransferKind::Sell,);
+        let amount_out_net = tax_out.net(amount_out, TransferKind::Buy,);
+        let overflow = || MathError::overflow("v2 amount in",);
+
+        if from0 {
+            self.reserves0 = self.reserves0.checked_add(amount_in_net,).ok_or_else(overflow,)?;
+            self.reserves1 -= amount_out;
+        } else {
+            self.reserves1 = self.reserves1.checked_add(amount_in_net,).ok_or_else(overflow,)?;
+            self.reserves0 -= amount_out;
+        }
+
+        Ok(Trade {
+            fee: self.fee_pips(),
             token0: self.token0,
             token1: self.token1,
             pool: self.address,
             from0,
             amount_in,
             amount_out,
-            price_impact,
-            fee_amount: amount_in.checked_sub(amount_in_less_fee,)?,
-            raw_price: current_price,
+            amount_in_net,
+            amount_out_net,
         },)
     }
 
@@ -103,6 +208,12 @@ impl V2PoolSim {
             .unwrap_or(U256::ZERO,);
     }
 
+    /// Apply an on-chain Sync event: the pair logs its reserves after every change
+    pub fn apply_sync(&mut self, reserve0: U256, reserve1: U256,) {
+        self.reserves0 = reserve0;
+        self.reserves1 = reserve1;
+    }
+
     /// Mint (add liquidity) to the pool: both reserves increase
     pub fn mint(&mut self, amount0: U256, amount1: U256,) {
         self.reserves0 = self.reserves0.checked_add(amount0,).unwrap_or(self.reserves0,);
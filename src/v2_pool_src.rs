@@ -1,66 +1,138 @@
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, U256};
+
+use crate::IUniswapV2Pair::IUniswapV2PairInstance;
+use crate::err::PoolError;
+use crate::v2_fees::{FeeRegistry, V2Fee};
 use crate::v2_pool_sim::V2PoolSim;
-use alloy::{
-    network::Ethereum, primitives::{Address, Bytes, U256}, sol, sol_types::SolCall
-};
-use alloy_provider::{fillers::FillProvider, utils::JoinedRecommendedFillers, RootProvider};
+use crate::v3_pool_src::Rpc;
 
-sol!{
-        interface IUniswapV2Pair {
-            function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
-        }
-    }
+type PairContract = IUniswapV2PairInstance<Rpc>;
 
 #[derive(Debug)]
 pub struct V2PoolSrc {
     pub address: Address,
+    pub factory: Address,
     pub token0: Address,
     pub token1: Address,
     pub exchange: String,
     pub version: String,
     pub fee: u32,
+    pub fee_denominator: u32,
+    /// `fee` is the pair's own `swapFee()`, read again on every update
+    pub pair_fee: bool,
     pub reserves0: U256,
     pub reserves1: U256,
+    pub block_timestamp_last: u32,
+    pub contract: PairContract,
 }
 
 impl V2PoolSrc {
-    // Private constructor
-    async fn new(
-        exchange: String,
-        version: String,
-        fee: u32,
+    /// Load the pair at the latest block, named and priced by its factory's
+    /// entry in `FeeRegistry::with_known`
+    pub async fn new(address: Address, provider: Rpc) -> Result<Self, PoolError> {
+        let fees = FeeRegistry::with_known();
+        Self::new_at(address, provider, &fees, BlockId::latest()).await
+    }
+
+    /// Load the pair as of `block` with the fee `fees` has for its factory,
+    /// a plain Uniswap V2 pair with a 0.3% fee if the factory isn't there
+    pub async fn new_at(
         address: Address,
+        provider: Rpc,
+        fees: &FeeRegistry,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let contract = IUniswapV2PairInstance::new(address, provider.clone());
+
+        let factory = contract.factory().call().block(block).await?;
+        let token0 = contract.token0().call().block(block).await?;
+        let token1 = contract.token1().call().block(block).await?;
+
+        let mut instance = Self::unloaded(address, factory, token0, token1, provider);
+        instance.apply_fees(fees);
+        instance.update_at(block).await?;
+        Ok(instance)
+    }
+
+    /// Pair whose factory and tokens are already known, e.g. from factory
+    /// discovery, without reserves until `update` is called
+    pub fn unloaded(
+        address: Address,
+        factory: Address,
         token0: Address,
         token1: Address,
+        provider: Rpc,
     ) -> Self {
-        let mut instance = V2PoolSrc {
-            exchange,
-            version,
-            fee,
+        Self {
             address,
+            factory,
             token0,
             token1,
+            exchange: "uniswap".to_string(),
+            version: "v2".to_string(),
+            fee: 3,
+            fee_denominator: 1_000,
+            pair_fee: false,
             reserves0: U256::ZERO,
             reserves1: U256::ZERO,
+            block_timestamp_last: 0,
+            contract: IUniswapV2PairInstance::new(address, provider),
+        }
+    }
+
+    /// Take name and fee from the registry entry of the pair's factory,
+    /// false if the factory isn't known or its fee isn't valid and the
+    /// Uniswap defaults stay
+    pub fn apply_fees(&mut self, registry: &FeeRegistry) -> bool {
+        let Some(dex) = registry.get(&self.factory).filter(|dex| dex.fee.is_valid()) else {
+            return false;
         };
-        instance.update().await;
-        instance
+        self.exchange = dex.exchange.clone();
+        self.version = dex.version.clone();
+        self.fee = dex.fee.fee;
+        self.fee_denominator = dex.fee.denominator;
+        self.pair_fee = dex.pair_fee;
+        true
     }
 
-    pub async fn update(&mut self, provider: &FillProvider<JoinedRecommendedFillers, RootProvider<Ethereum>>) {
-        let call = IUniswapV2Pair::getReservesCall {};
-        let calldata: Bytes = call.abi_encode().into();
+    /// Refresh reserves from the latest block
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
     }
 
-    pub async fn into_sim(&self) -> V2PoolSim {
-        V2PoolSim::new(
-            self.exchange.clone(),
-            self.version.clone(),
-            self.fee.clone(),
-            self.address.clone(),
-            self.token0.clone(),
-            self.token1.clone(),
-            self.reserves0,
-            self.reserves1,
-        )
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let reserves = self.contract.getReserves().call().block(block).await?;
+        self.reserves0 = U256::from(reserves.reserve0);
+        self.reserves1 = U256::from(reserves.reserve1);
+        self.block_timestamp_last = reserves.blockTimestampLast;
+        if self.pair_fee {
+            let fee = self.contract.swapFee().call().block(block).await?;
+            if !V2Fee::new(fee, self.fee_denominator).is_valid() {
+                return Err(PoolError::Unsupported {
+                    pool: self.address,
+                    reason: format!("swap fee {fee}/{}", self.fee_denominator),
+                });
+            }
+            self.fee = fee;
+        }
+        Ok(())
+    }
+
+    /// The fee was checked when it was taken from the registry or the pair
+    pub fn into_sim(&self) -> V2PoolSim {
+        V2PoolSim {
+            fee_denominator: self.fee_denominator,
+            ..V2PoolSim::new(
+                self.exchange.clone(),
+                self.version.clone(),
+                self.fee,
+                self.address,
+                self.token0,
+                self.token1,
+                self.reserves0,
+                self.reserves1,
+            )
+        }
     }
 }
@@ -0,0 +1,97 @@
+//! V3 forks told apart by the factory that deployed a pool, with what it
+//! takes to derive their pool addresses offline.
+
+use alloy::eips::BlockId;
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, B256, address, b256, keccak256};
+use alloy::sol_types::SolValue;
+
+use crate::PancakeV3Pool;
+use crate::UniV3Pool::UniV3PoolInstance;
+use crate::curve_pool_src::optional;
+use crate::err::PoolError;
+use crate::v3_pool_src::Rpc;
+
+/// V3 code a pool runs, for the few calls and logs that differ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum V3Fork {
+    #[default]
+    Uniswap,
+    /// `uint32 feeProtocol` in `slot0` and a `Swap` log with protocol fees
+    PancakeSwap,
+}
+
+/// A factory of a V3 fork. Pancake pools are deployed by a separate
+/// deployer, Uniswap's by the factory itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct V3Deployment {
+    pub fork: V3Fork,
+    pub factory: Address,
+    pub deployer: Address,
+    pub init_code_hash: B256,
+}
+
+const UNISWAP_INIT_CODE_HASH: B256 =
+    b256!("0xe34f199b19b2b4f47f68442619d555527d244f78a3297ea89325f843f87b8b54");
+
+/// The well known V3 factories on Ethereum and BSC
+pub const KNOWN_DEPLOYMENTS: [V3Deployment; 3] = [
+    V3Deployment {
+        fork: V3Fork::Uniswap,
+        factory: address!("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+        deployer: address!("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+        init_code_hash: UNISWAP_INIT_CODE_HASH,
+    },
+    V3Deployment {
+        fork: V3Fork::Uniswap,
+        factory: address!("0xdB1d10011AD0Ff90774D0C6Bb92e5C5c8b4461F7"),
+        deployer: address!("0xdB1d10011AD0Ff90774D0C6Bb92e5C5c8b4461F7"),
+        init_code_hash: UNISWAP_INIT_CODE_HASH,
+    },
+    // same addresses on both chains
+    V3Deployment {
+        fork: V3Fork::PancakeSwap,
+        factory: address!("0x0BFbCF9fa4f9C56B0F40a671Ad40E0805A091865"),
+        deployer: address!("0x41ff9AA7e16B8B1a8a8dc4f0eFacd93D02d071c9"),
+        init_code_hash: b256!("0x6ce8eb472fa82df5469c6ab6d485f17c3ad13c8cd7af59b3d4a8026c5ce0f7e2"),
+    },
+];
+
+impl V3Deployment {
+    pub fn by_factory(factory: &Address) -> Option<&'static V3Deployment> {
+        KNOWN_DEPLOYMENTS.iter().find(|d| d.factory == *factory)
+    }
+
+    /// Address of the pool for two tokens in either order and a fee tier,
+    /// whether or not it was created yet
+    pub fn pool_address(&self, token_a: Address, token_b: Address, fee: U24) -> Address {
+        let (token0, token1) = if token_a < token_b {
+            (token_a, token_b)
+        } else {
+            (token_b, token_a)
+        };
+        let salt = keccak256((token0, token1, fee).abi_encode());
+        self.deployer.create2(salt, self.init_code_hash)
+    }
+}
+
+impl V3Fork {
+    /// Fork of the pool behind `contract`, by its factory, or for factories
+    /// not in `KNOWN_DEPLOYMENTS` by whether it has an LM pool like Pancake's
+    pub async fn detect(
+        contract: &UniV3PoolInstance<Rpc>,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        if let Some(factory) = optional(contract.factory().call().block(block)).await?
+            && let Some(deployment) = V3Deployment::by_factory(&factory)
+        {
+            return Ok(deployment.fork);
+        }
+        let pancake = PancakeV3Pool::new(*contract.address(), contract.provider().clone());
+        let lm_pool = optional(pancake.lmPool().call().block(block)).await?;
+        Ok(match lm_pool {
+            Some(_) => V3Fork::PancakeSwap,
+            None => V3Fork::Uniswap,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+; Source of the V3 lens in `v3_lens.rs`, `LENS_RUNTIME` is `[runtime]` and
+; `LENS_INIT` is `[init]`, each followed by `[body]`. The test
+; `v3_lens_bytecode_matches_source` assembles both and compares them byte
+; for byte with the constants.
+;
+; One instruction per token, `PUSHn` takes a hex immediate or `:label`,
+; `label:` marks an offset and `;` starts a comment. `:end` is the size of
+; the assembled code.
+;
+; Memory
+;   0x00    selector and argument of the call being made
+;   0x40    first two words the call returned
+;   0x80    index of the pool
+;   0xa0    where the next output word goes
+;   0xc0    the pool
+;   0xe0    its tickSpacing
+;   0x100   bitmap word being read
+;   0x120   last bitmap word to read
+;   0x140   what is left of that word
+;   0x160   bit of it being looked at
+;   0x180   the pool's tick
+;   0x1a0   start of the output, offset and length of the uint256[]
+;   0x200   the input, `[n, count, pool_0, .., pool_count-1]`
+
+[runtime]
+    ; input from the calldata
+    CALLDATASIZE DUP1 PUSH1 0x00 PUSH2 0x0200 CALLDATACOPY
+
+[init]
+    ; input appended to the init code
+    PUSH2 :end CODESIZE SUB DUP1 PUSH2 :end PUSH2 0x0200 CODECOPY
+
+[body]
+    ; output right after the input, its words after offset and length
+    PUSH2 0x0200 ADD DUP1 PUSH2 0x01a0 MSTORE
+    PUSH1 0x40 ADD PUSH2 0x00a0 MSTORE
+    PUSH1 0x00 PUSH2 0x0080 MSTORE
+
+pools:
+    JUMPDEST
+    PUSH2 0x0220 MLOAD PUSH2 0x0080 MLOAD LT ISZERO PUSH2 :done JUMPI
+    PUSH2 0x0080 MLOAD PUSH1 0x05 SHL PUSH2 0x0240 ADD MLOAD PUSH2 0x00c0 MSTORE
+
+    ; slot0(), a pool that fails it, returns too little or isn't
+    ; initialized is a single 0
+    PUSH1 0x00 PUSH2 0x0040 MSTORE
+    PUSH32 0x3850c7bd00000000000000000000000000000000000000000000000000000000
+    PUSH1 0x00 MSTORE
+    PUSH1 0x40 PUSH1 0x40 PUSH1 0x04 PUSH1 0x00 PUSH2 0x00c0 MLOAD GAS STATICCALL
+    ISZERO PUSH2 :failed JUMPI
+    PUSH1 0x40 RETURNDATASIZE LT PUSH2 :failed JUMPI
+    PUSH2 0x0040 MLOAD ISZERO PUSH2 :failed JUMPI
+    ; sqrtPriceX96 and tick
+    PUSH2 0x0040 MLOAD PUSH2 0x00a0 MLOAD MSTORE
+    PUSH2 0x00a0 MLOAD PUSH1 0x20 ADD PUSH2 0x00a0 MSTORE
+    PUSH2 0x0060 MLOAD DUP1 PUSH2 0x0180 MSTORE PUSH2 0x00a0 MLOAD MSTORE
+    PUSH2 0x00a0 MLOAD PUSH1 0x20 ADD PUSH2 0x00a0 MSTORE
+
+    ; liquidity()
+    PUSH1 0x00 PUSH2 0x0040 MSTORE
+    PUSH32 0x1a68650200000000000000000000000000000000000000000000000000000000
+    PUSH1 0x00 MSTORE
+    PUSH1 0x20 PUSH1 0x40 PUSH1 0x04 PUSH1 0x00 PUSH2 0x00c0 MLOAD GAS STATICCALL POP
+    PUSH2 0x0040 MLOAD PUSH2 0x00a0 MLOAD MSTORE
+    PUSH2 0x00a0 MLOAD PUSH1 0x20 ADD PUSH2 0x00a0 MSTORE
+
+    ; tickSpacing()
+    PUSH1 0x00 PUSH2 0x0040 MSTORE
+    PUSH32 0xd0c93a7c00000000000000000000000000000000000000000000000000000000
+    PUSH1 0x00 MSTORE
+    PUSH1 0x20 PUSH1 0x40 PUSH1 0x04 PUSH1 0x00 PUSH2 0x00c0 MLOAD GAS STATICCALL POP
+    PUSH2 0x0040 MLOAD DUP1 PUSH2 0x00e0 MSTORE PUSH2 0x00a0 MLOAD MSTORE
+    PUSH2 0x00a0 MLOAD PUSH1 0x20 ADD PUSH2 0x00a0 MSTORE
+
+    ; word of the compressed tick, rounded toward negative infinity, and
+    ; the n words on each side
+    PUSH2 0x00e0 MLOAD PUSH2 0x0180 MLOAD SDIV
+    PUSH1 0x00 PUSH2 0x0180 MLOAD SLT
+    PUSH2 0x00e0 MLOAD PUSH2 0x0180 MLOAD SMOD ISZERO ISZERO AND
+    SWAP1 SUB PUSH1 0x08 SAR
+    DUP1 PUSH2 0x0200 MLOAD SWAP1 SUB PUSH2 0x0100 MSTORE
+    PUSH2 0x0200 MLOAD ADD PUSH2 0x0120 MSTORE
+
+words:
+    JUMPDEST
+    PUSH2 0x0120 MLOAD PUSH2 0x0100 MLOAD SGT PUSH2 :next JUMPI
+    ; tickBitmap(word)
+    PUSH32 0x5339c29600000000000000000000000000000000000000000000000000000000
+    PUSH1 0x00 MSTORE
+    PUSH2 0x0100 MLOAD PUSH1 0x04 MSTORE
+    PUSH1 0x00 PUSH2 0x0040 MSTORE
+    PUSH1 0x20 PUSH1 0x40 PUSH1 0x24 PUSH1 0x00 PUSH2 0x00c0 MLOAD GAS STATICCALL POP
+    PUSH2 0x0040 MLOAD DUP1 PUSH2 0x0140 MSTORE PUSH2 0x00a0 MLOAD MSTORE
+    PUSH2 0x00a0 MLOAD PUSH1 0x20 ADD PUSH2 0x00a0 MSTORE
+    PUSH1 0x00 PUSH2 0x0160 MSTORE
+
+bits:
+    JUMPDEST
+    PUSH2 0x0140 MLOAD ISZERO PUSH2 :word_done JUMPI
+    PUSH2 0x0140 MLOAD PUSH1 0x01 AND ISZERO PUSH2 :bit_done JUMPI
+    ; ticks((word * 256 + bit) * tickSpacing), liquidityNet is its second word
+    PUSH2 0x00e0 MLOAD PUSH2 0x0160 MLOAD PUSH2 0x0100 MLOAD PUSH1 0x08 SHL ADD MUL
+    PUSH32 0xf30dba9300000000000000000000000000000000000000000000000000000000
+    PUSH1 0x00 MSTORE
+    PUSH1 0x04 MSTORE
+    PUSH1 0x00 PUSH2 0x0060 MSTORE
+    PUSH1 0x40 PUSH1 0x40 PUSH1 0x24 PUSH1 0x00 PUSH2 0x00c0 MLOAD GAS STATICCALL POP
+    PUSH2 0x0060 MLOAD PUSH2 0x00a0 MLOAD MSTORE
+    PUSH2 0x00a0 MLOAD PUSH1 0x20 ADD PUSH2 0x00a0 MSTORE
+
+bit_done:
+    JUMPDEST
+    PUSH2 0x0140 MLOAD PUSH1 0x01 SHR PUSH2 0x0140 MSTORE
+    PUSH2 0x0160 MLOAD PUSH1 0x01 ADD PUSH2 0x0160 MSTORE
+    PUSH2 :bits JUMP
+
+word_done:
+    JUMPDEST
+    PUSH2 0x0100 MLOAD PUSH1 0x01 ADD PUSH2 0x0100 MSTORE
+    PUSH2 :words JUMP
+
+failed:
+    JUMPDEST
+    PUSH1 0x00 PUSH2 0x00a0 MLOAD MSTORE
+    PUSH2 0x00a0 MLOAD PUSH1 0x20 ADD PUSH2 0x00a0 MSTORE
+
+next:
+    JUMPDEST
+    PUSH2 0x0080 MLOAD PUSH1 0x01 ADD PUSH2 0x0080 MSTORE
+    PUSH2 :pools JUMP
+
+done:
+    ; ABI encode the words as one uint256[]
+    JUMPDEST
+    PUSH1 0x20 PUSH2 0x01a0 MLOAD MSTORE
+    PUSH2 0x01a0 MLOAD PUSH2 0x00a0 MLOAD SUB PUSH1 0x40 SWAP1 SUB PUSH1 0x05 SHR
+    PUSH2 0x01a0 MLOAD PUSH1 0x20 ADD MSTORE
+    PUSH2 0x01a0 MLOAD PUSH2 0x00a0 MLOAD SUB PUSH2 0x01a0 MLOAD RETURN
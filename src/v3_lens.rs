@@ -0,0 +1,226 @@
+//! Loads many V3 pools with a single `eth_call` by running a lens contract
+//! that only exists for the duration of the call.
+//!
+//! The lens walks the same bitmap words and ticks as `V3PoolSrc::update_ticks`,
+//! but does it inside the EVM, so refreshing hundreds of pools costs one request.
+
+use std::collections::HashMap;
+
+use alloy::eips::BlockId;
+use alloy::network::TransactionBuilder;
+use alloy::primitives::aliases::I24;
+use alloy::primitives::{Address, Bytes, U256, address, hex};
+use alloy::rpc::types::TransactionRequest;
+use alloy::rpc::types::state::{AccountOverride, StateOverride};
+use alloy::sol_types::SolValue;
+use alloy_provider::Provider;
+
+use crate::tick_math::{self, Tick};
+use crate::v3_pool_src::Rpc;
+
+/// Runtime code of the lens, assembled from `v3_lens.asm`.
+///
+/// Input is raw words `[n, count, pool_0, .., pool_count-1]`. For every pool it
+/// calls `slot0`, `liquidity` and `tickSpacing`, then the `2n + 1` `tickBitmap`
+/// words centered on the current tick, and `ticks(t)` for every set bit. The
+/// result is ABI encoded as one flat `uint256[]` holding, per pool:
+///
+/// `sqrtPriceX96, tick, liquidity, tickSpacing, (bitmap, liquidityNet..)*`
+///
+/// with one `liquidityNet` per set bit of the preceding bitmap word, lowest bit
+/// first. A pool whose `slot0` call fails or that isn't initialized, a
+/// price of 0, is a single `0`.
+pub(crate) const LENS_RUNTIME: &[u8] = &hex!(
+    "368060006102003761020001806101a0526040016100a0526000610080525b610220516100805110156102be57610080"
+    "5160051b61024001516100c0526000610040527f3850c7bd000000000000000000000000000000000000000000000000"
+    "0000000060005260406040600460006100c0515afa1561029b5760403d1061029b57610040511561029b576100405161"
+    "00a051526100a0516020016100a0526100605180610180526100a051526100a0516020016100a0526000610040527f1a"
+    "6865020000000000000000000000000000000000000000000000000000000060005260206040600460006100c0515afa"
+    "50610040516100a051526100a0516020016100a0526000610040527fd0c93a7c00000000000000000000000000000000"
+    "00000000000000000000000060005260206040600460006100c0515afa5061004051806100e0526100a051526100a051"
+    "6020016100a0526100e0516101805105600061018051126100e0516101805107151516900360081d8061020051900361"
+    "0100526102005101610120525b6101205161010051136102ae577f5339c2960000000000000000000000000000000000"
+    "00000000000000000000006000526101005160045260006100405260206040602460006100c0515afa50610040518061"
+    "0140526100a051526100a0516020016100a0526000610160525b610140511561028b5761014051600116156102705761"
+    "00e051610160516101005160081b01027ff30dba93000000000000000000000000000000000000000000000000000000"
+    "0060005260045260006100605260406040602460006100c0515afa50610060516100a051526100a0516020016100a052"
+    "5b6101405160011c6101405261016051600101610160526101f9565b610100516001016101005261018c565b60006100"
+    "a051526100a0516020016100a0525b610080516001016100805261001e565b60206101a051526101a0516100a0510360"
+    "40900360051c6101a051602001526101a0516100a051036101a051f3"
+);
+
+/// The same program as init code: the input is appended to the code instead of
+/// passed as calldata and the result comes back as the "deployed" code. Works
+/// without state override support, but EIP-170 caps the result at 24KB.
+pub(crate) const LENS_INIT: &[u8] = &hex!(
+    "6102f13803806102f16102003961020001806101a0526040016100a0526000610080525b610220516100805110156102"
+    "c3576100805160051b61024001516100c0526000610040527f3850c7bd00000000000000000000000000000000000000"
+    "00000000000000000060005260406040600460006100c0515afa156102a05760403d106102a05761004051156102a057"
+    "610040516100a051526100a0516020016100a0526100605180610180526100a051526100a0516020016100a052600061"
+    "0040527f1a68650200000000000000000000000000000000000000000000000000000000600052602060406004600061"
+    "00c0515afa50610040516100a051526100a0516020016100a0526000610040527fd0c93a7c0000000000000000000000"
+    "000000000000000000000000000000000060005260206040600460006100c0515afa5061004051806100e0526100a051"
+    "526100a0516020016100a0526100e0516101805105600061018051126100e0516101805107151516900360081d806102"
+    "00519003610100526102005101610120525b6101205161010051136102b3577f5339c296000000000000000000000000"
+    "000000000000000000000000000000006000526101005160045260006100405260206040602460006100c0515afa5061"
+    "00405180610140526100a051526100a0516020016100a0526000610160525b6101405115610290576101405160011615"
+    "610275576100e051610160516101005160081b01027ff30dba9300000000000000000000000000000000000000000000"
+    "00000000000060005260045260006100605260406040602460006100c0515afa50610060516100a051526100a0516020"
+    "016100a0525b6101405160011c6101405261016051600101610160526101fe565b610100516001016101005261019156"
+    "5b60006100a051526100a0516020016100a0525b6100805160010161008052610023565b60206101a051526101a05161"
+    "00a051036040900360051c6101a051602001526101a0516100a051036101a051f3"
+);
+
+/// Where the lens code is injected, nothing lives there on any chain
+pub const LENS_ADDRESS: Address = address!("0x000000000000000000000000000000006c656e73");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LensMode {
+    /// `eth_call` to `LENS_ADDRESS` with the runtime code as a state override
+    StateOverride,
+    /// Deployless `eth_call` of the init code, for nodes without overrides
+    Constructor,
+}
+
+/// Swap state of one pool as read by the lens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LensPool {
+    pub address: Address,
+    pub x96price: U256,
+    pub current_tick: I24,
+    pub liquidity: U256,
+    pub tick_spacing: I24,
+    pub bitmap: HashMap<i16, U256>,
+    pub active_ticks: Vec<Tick>,
+}
+
+#[derive(Debug, Clone)]
+pub struct V3Lens {
+    provider: Rpc,
+    mode: LensMode,
+    /// Bitmap words read on each side of the current one
+    words_each_side: u16,
+    /// Pools per `eth_call`, keeps each call under the node's gas cap
+    batch_size: usize,
+}
+
+impl V3Lens {
+    pub fn new(provider: Rpc, mode: LensMode, words_each_side: u16, batch_size: usize) -> Self {
+        Self {
+            provider,
+            mode,
+            words_each_side,
+            batch_size,
+        }
+    }
+
+    /// Load every pool as of `block`, `None` for the ones the lens couldn't read
+    pub async fn load(
+        &self,
+        pools: &[Address],
+        block: BlockId,
+    ) -> Result<Vec<Option<LensPool>>, anyhow::Error> {
+        let mut loaded = Vec::with_capacity(pools.len());
+        for batch in pools.chunks(self.batch_size.max(1)) {
+            let words = self.call(batch, block).await?;
+            loaded.extend(decode(batch, self.words_each_side, &words)?);
+        }
+        Ok(loaded)
+    }
+
+    async fn call(&self, pools: &[Address], block: BlockId) -> Result<Vec<U256>, anyhow::Error> {
+        let mut input = Vec::with_capacity(32 * (pools.len() + 2));
+        input.extend_from_slice(&U256::from(self.words_each_side).to_be_bytes::<32>());
+        input.extend_from_slice(&U256::from(pools.len()).to_be_bytes::<32>());
+        for pool in pools {
+            input.extend_from_slice(pool.into_word().as_slice());
+        }
+
+        let output = match self.mode {
+            LensMode::StateOverride => {
+                let tx = TransactionRequest::default()
+                    .with_to(LENS_ADDRESS)
+                    .with_input(Bytes::from(input));
+                let mut overrides = StateOverride::default();
+                overrides.insert(
+                    LENS_ADDRESS,
+                    AccountOverride::default().with_code(LENS_RUNTIME),
+                );
+                self.provider
+                    .call(tx)
+                    .overrides(overrides)
+                    .block(block)
+                    .await?
+            }
+            LensMode::Constructor => {
+                let mut code = LENS_INIT.to_vec();
+                code.extend_from_slice(&input);
+                let tx = TransactionRequest::default().with_deploy_code(code);
+                self.provider.call(tx).block(block).await?
+            }
+        };
+
+        Ok(Vec::<U256>::abi_decode(&output)?)
+    }
+}
+
+/// Split the lens output back into pools
+pub fn decode(
+    pools: &[Address],
+    words_each_side: u16,
+    words: &[U256],
+) -> Result<Vec<Option<LensPool>>, anyhow::Error> {
+    let mut words = words.iter().copied();
+    let mut next = || {
+        words
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("lens output ended early"))
+    };
+
+    let mut loaded = Vec::with_capacity(pools.len());
+    for &address in pools {
+        let x96price = next()?;
+        if x96price.is_zero() {
+            loaded.push(None);
+            continue;
+        }
+        let current_tick = I24::from_raw(low_bits(next()?, 24).to());
+        let liquidity = next()?;
+        let tick_spacing = I24::from_raw(low_bits(next()?, 24).to());
+
+        let center = tick_math::word_index(tick_math::normalize_tick(current_tick, tick_spacing));
+        let mut bitmap = HashMap::new();
+        let mut active_ticks = Vec::new();
+        for offset in -(words_each_side as i32)..=words_each_side as i32 {
+            let word_idx = (center as i32 + offset) as i16;
+            let word = next()?;
+            bitmap.insert(word_idx, word);
+
+            for tick in
+                tick_math::extract_ticks_from_bitmap(word, I24::try_from(word_idx)?, tick_spacing)
+            {
+                let net = low_bits(next()?, 128).to::<u128>() as i128;
+                active_ticks.push(Tick {
+                    tick,
+                    liquidity_net: Some(net),
+                });
+            }
+        }
+
+        loaded.push(Some(LensPool {
+            address,
+            x96price,
+            current_tick,
+            liquidity,
+            tick_spacing,
+            bitmap,
+            active_ticks,
+        }));
+    }
+    Ok(loaded)
+}
+
+// Signed words come back sign extended to 256 bits
+fn low_bits(word: U256, bits: usize) -> U256 {
+    word & ((U256::ONE << bits) - U256::ONE)
+}
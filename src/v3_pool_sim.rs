@@ -0,0 +1,274 @@
+use alloy::primitives::aliases::{I24, U24};
+use alloy::primitives::{Address, U256, U512};
+
+use crate::err::{MathError, PoolError};
+use crate::tick_math::{self, Tick};
+use crate::token_tax::{TokenTaxes, TransferKind, TransferTax};
+use crate::trade::Trade;
+
+/// Offline copy of a V3 pool's swap state, detached from any provider.
+#[derive(Debug, Clone)]
+pub struct V3PoolSim {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: U24,
+    pub current_tick: I24,
+    pub active_ticks: Vec<Tick>,
+    pub tick_spacing: I24,
+    pub liquidity: U256,
+    pub x96price: U256,
+    pub tax0: TransferTax,
+    pub tax1: TransferTax,
+}
+
+impl V3PoolSim {
+    /// Take the transfer taxes of both tokens from `taxes`
+    pub fn apply_taxes(&mut self, taxes: &TokenTaxes) {
+        self.tax0 = taxes.get(&self.token0);
+        self.tax1 = taxes.get(&self.token1);
+    }
+
+    /// Swap `amount_in` sent by the trader, taxes of both tokens applied
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let (tax_in, tax_out) = match from0 {
+            true => (&self.tax0, &self.tax1),
+            false => (&self.tax1, &self.tax0),
+        };
+        let amount_in_net = tax_in.net(amount_in, TransferKind::Sell);
+        let tax_out = tax_out.clone();
+
+        let amount_out = self.swap(amount_in_net, from0)?;
+
+        Ok(Trade {
+            fee: self.fee,
+            token0: self.token0,
+            token1: self.token1,
+            pool: self.address,
+            from0,
+            amount_in,
+            amount_out,
+            amount_in_net,
+            amount_out_net: tax_out.net(amount_out, TransferKind::Buy),
+        })
+    }
+
+    /// Swap `amount_in` as received by the pool, no transfer taxes, and
+    /// return what the pool sends out
+    pub fn swap(&mut self, amount_in: U256, from0: bool) -> Result<U256, PoolError> {
+        let out_of_ticks = PoolError::OutOfTicks {
+            pool: self.address,
+            from0,
+        };
+        let fee_overflow = || MathError::overflow("fee deduction");
+        let out_overflow = || MathError::overflow("amount out");
+
+        // 1. Fee deduction
+        let fee_amount = amount_in
+            .checked_mul(U256::from(self.fee))
+            .ok_or_else(fee_overflow)?
+            / U256::from(1_000_000);
+        let mut remaining = amount_in.checked_sub(fee_amount).ok_or_else(fee_overflow)?;
+        // 2. Local state
+        let mut total_out = U256::ZERO;
+
+        let mut curr_price = self.x96price;
+
+        let current_tick = tick_math::tick_from_price(self.x96price)?;
+
+        let mut next_tick_index = match self
+            .active_ticks
+            .binary_search_by_key(&current_tick, |t| t.tick)
+        {
+            Ok(i) => {
+                if from0 {
+                    if i + 1 >= self.active_ticks.len() {
+                        return Err(out_of_ticks);
+                    } // No ticks above
+                    i + 1
+                } else {
+                    if i == 0 {
+                        return Err(out_of_ticks);
+                    } // No ticks below
+                    i - 1
+                }
+            }
+            Err(i) => {
+                if from0 {
+                    if i >= self.active_ticks.len() {
+                        return Err(out_of_ticks);
+                    } // No ticks above
+                    i
+                } else {
+                    if i == 0 {
+                        return Err(out_of_ticks);
+                    } // No ticks below
+                    i - 1
+                }
+            }
+        };
+        let mut curr_liq = self.liquidity;
+
+        // 3. Iterate ticks
+
+        while remaining > U256::ZERO {
+            // get target tick price
+
+            let next_tick =
+                self.active_ticks
+                    .get(next_tick_index)
+                    .ok_or(PoolError::OutOfTicks {
+                        pool: self.address,
+                        from0,
+                    })?;
+            let next_price = tick_math::price_from_tick(next_tick.tick)?;
+
+            // past the first tick wraps to an index `get` won't find
+            next_tick_index = if from0 {
+                next_tick_index + 1
+            } else {
+                next_tick_index.wrapping_sub(1)
+            };
+
+            // compute max amount possible to cross this tick
+            let possible =
+                tick_math::compute_amount_possible(from0, &curr_liq, &curr_price, &next_price)?;
+
+            if remaining < possible {
+                // won't cross full tick
+                let new_price = if from0 {
+                    tick_math::compute_price_from0(&remaining, &curr_liq, &curr_price, true)?
+                } else {
+                    tick_math::compute_price_from1(&remaining, &curr_liq, &curr_price, true)?
+                };
+
+                let u512_curr_price = U512::from(curr_price);
+                let u512_curr_liq = U512::from(curr_liq);
+
+                // compute out
+                let delta = if from0 {
+                    let price_diff = u512_curr_price
+                        .checked_sub(U512::from(new_price))
+                        .ok_or_else(out_overflow)?;
+                    u512_curr_liq
+                        .checked_mul(price_diff)
+                        .ok_or_else(out_overflow)?
+                        / (U512::ONE << 96)
+                } else {
+                    let q192 = U512::ONE << 192usize;
+                    let inv_curr = q192.checked_div(u512_curr_price).ok_or_else(out_overflow)?;
+                    let inv_new = q192
+                        .checked_div(U512::from(new_price))
+                        .ok_or_else(out_overflow)?;
+                    u512_curr_liq
+                        .checked_mul(inv_curr.checked_sub(inv_new).ok_or_else(out_overflow)?)
+                        .ok_or_else(out_overflow)?
+                        / U512::from(1u128 << 96)
+                };
+
+                total_out = total_out
+                    .checked_add(U256::from(delta))
+                    .ok_or_else(out_overflow)?;
+                curr_price = U256::from(new_price);
+
+                break;
+            }
+
+            // cross entire tick
+            let price_diff = if from0 {
+                next_price.checked_sub(curr_price)
+            } else {
+                curr_price.checked_sub(next_price)
+            };
+            let out_cross = curr_liq
+                .checked_mul(price_diff.ok_or_else(out_overflow)?)
+                .ok_or_else(out_overflow)?
+                / U256::from(1u128 << 96);
+            total_out = total_out.checked_add(out_cross).ok_or_else(out_overflow)?;
+
+            // update liquidity
+            let net = next_tick.liquidity_net.ok_or(PoolError::MissingTick {
+                pool: self.address,
+                tick: next_tick.tick,
+            })?;
+            let delta = U256::from(net.unsigned_abs());
+            // crossing up adds net, crossing down removes it
+            curr_liq = if (net > 0) == from0 {
+                curr_liq.saturating_add(delta)
+            } else {
+                curr_liq.saturating_sub(delta)
+            };
+
+            // move pointer
+            curr_price = next_price;
+            remaining = remaining
+                .checked_sub(possible)
+                .ok_or_else(|| MathError::overflow("remaining amount"))?;
+        }
+
+        self.liquidity = curr_liq;
+        self.x96price = curr_price;
+        self.current_tick = tick_math::tick_from_price(curr_price)?;
+
+        Ok(total_out)
+    }
+
+    /// Apply an on-chain Swap event: the pool logs its post-swap price, liquidity and tick
+    pub fn apply_swap(&mut self, sqrt_price_x96: U256, liquidity: U256, tick: I24) {
+        self.x96price = sqrt_price_x96;
+        self.liquidity = liquidity;
+        self.current_tick = tick;
+    }
+
+    /// Mint (add liquidity) to a position between `tick_lower` and `tick_upper`
+    pub fn mint(&mut self, tick_lower: I24, tick_upper: I24, amount: u128) {
+        self.modify_position(tick_lower, tick_upper, amount as i128);
+    }
+
+    /// Burn (remove liquidity) from a position between `tick_lower` and `tick_upper`
+    pub fn burn(&mut self, tick_lower: I24, tick_upper: I24, amount: u128) {
+        self.modify_position(tick_lower, tick_upper, -(amount as i128));
+    }
+
+    /// Is `tick` inside the window of ticks loaded from chain?
+    pub fn in_window(&self, tick: I24) -> bool {
+        match (self.active_ticks.first(), self.active_ticks.last()) {
+            (Some(first), Some(last)) => tick >= first.tick && tick <= last.tick,
+            _ => false,
+        }
+    }
+
+    fn modify_position(&mut self, tick_lower: I24, tick_upper: I24, delta: i128) {
+        self.update_tick(tick_lower, delta);
+        self.update_tick(tick_upper, -delta);
+
+        if self.current_tick >= tick_lower && self.current_tick < tick_upper {
+            self.liquidity =
+                tick_math::update_liquidity(self.liquidity, delta).unwrap_or(self.liquidity);
+        }
+    }
+
+    // Ticks outside the loaded window are left alone, we don't know their neighbours
+    fn update_tick(&mut self, tick: I24, delta: i128) {
+        if !self.in_window(tick) {
+            return;
+        }
+        match self.active_ticks.binary_search_by_key(&tick, |t| t.tick) {
+            Ok(i) => {
+                let net = self.active_ticks[i].liquidity_net.unwrap_or(0) + delta;
+                if net == 0 {
+                    self.active_ticks.remove(i);
+                } else {
+                    self.active_ticks[i].liquidity_net = Some(net);
+                }
+            }
+            Err(i) => self.active_ticks.insert(
+                i,
+                Tick {
+                    tick,
+                    liquidity_net: Some(delta),
+                },
+            ),
+        }
+    }
+}
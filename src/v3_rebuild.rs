@@ -0,0 +1,347 @@
+//! Rebuilds a V3 pool's full tick map by replaying its logs from creation,
+//! for pools where per-tick RPC reads are too expensive or not trusted.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use alloy::eips::BlockId;
+use alloy::primitives::aliases::{I24, U24};
+use alloy::primitives::{Address, B256, U256};
+use alloy::rpc::types::Filter;
+use alloy::sol_types::SolEvent;
+use alloy_provider::Provider;
+use anyhow::{anyhow, bail};
+
+use crate::pool_sync::{PoolEvent, PoolLog};
+use crate::tick_math::{self, Tick};
+use crate::token_tax::TransferTax;
+use crate::v3_pool_sim::V3PoolSim;
+use crate::v3_pool_src::Rpc;
+use crate::{PancakeV3Pool, UniV3Factory, UniV3Pool};
+
+/// Liquidity referencing a single tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TickLiquidity {
+    pub gross: u128,
+    pub net: i128,
+}
+
+/// Replay state of a pool, doubles as a resumable checkpoint.
+///
+/// Everything before `next_block` has been applied; persist it with
+/// `to_checkpoint` after any `run` and pick up again with `from_checkpoint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V3Rebuild {
+    pub pool: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: U24,
+    pub tick_spacing: I24,
+    pub next_block: u64,
+    pub x96price: U256,
+    pub liquidity: U256,
+    pub current_tick: I24,
+    pub ticks: BTreeMap<I24, TickLiquidity>,
+}
+
+impl V3Rebuild {
+    /// Start a rebuild at the block the factory created `pool` in.
+    /// `search_from` should be the factory's deployment block.
+    pub async fn new(
+        pool: Address,
+        factory: Address,
+        provider: &Rpc,
+        search_from: u64,
+        max_block_range: u64,
+    ) -> Result<Self, anyhow::Error> {
+        let contract = UniV3Pool::new(pool, provider.clone());
+        let token0 = contract.token0().call().await?;
+        let token1 = contract.token1().call().await?;
+        let fee = contract.fee().call().await?;
+        let tick_spacing = contract.tickSpacing().call().await?;
+
+        let created = Self::creation_block(
+            pool,
+            factory,
+            (token0, token1, fee),
+            provider,
+            search_from,
+            max_block_range,
+        )
+        .await?;
+
+        Ok(Self {
+            pool,
+            token0,
+            token1,
+            fee,
+            tick_spacing,
+            next_block: created,
+            x96price: U256::ZERO,
+            liquidity: U256::ZERO,
+            current_tick: I24::ZERO,
+            ticks: BTreeMap::new(),
+        })
+    }
+
+    /// Find the block of the factory's `PoolCreated` event for `pool`
+    pub async fn creation_block(
+        pool: Address,
+        factory: Address,
+        (token0, token1, fee): (Address, Address, U24),
+        provider: &Rpc,
+        search_from: u64,
+        max_block_range: u64,
+    ) -> Result<u64, anyhow::Error> {
+        let head = provider.get_block_number().await?;
+        let mut from = search_from;
+
+        while from <= head {
+            let to = head.min(from + max_block_range.max(1) - 1);
+            let filter = Filter::new()
+                .address(factory)
+                .event_signature(UniV3Factory::PoolCreated::SIGNATURE_HASH)
+                .topic1(token0.into_word())
+                .topic2(token1.into_word())
+                .topic3(B256::from(U256::from(fee)))
+                .from_block(from)
+                .to_block(to);
+
+            for log in provider.get_logs(&filter).await? {
+                let created = UniV3Factory::PoolCreated::decode_log_data(log.data())?;
+                if created.pool == pool {
+                    return log
+                        .block_number
+                        .ok_or_else(|| anyhow!("PoolCreated log without a block number"));
+                }
+            }
+            from = to + 1;
+        }
+
+        bail!("no PoolCreated event for {pool} from factory {factory}")
+    }
+
+    /// Replay logs up to and including `to_block`, `next_block` advances after
+    /// every chunk so an interrupted run can be resumed from the checkpoint
+    pub async fn run(
+        &mut self,
+        provider: &Rpc,
+        to_block: u64,
+        max_block_range: u64,
+    ) -> Result<(), anyhow::Error> {
+        let signatures = vec![
+            UniV3Pool::Initialize::SIGNATURE_HASH,
+            UniV3Pool::Mint::SIGNATURE_HASH,
+            UniV3Pool::Burn::SIGNATURE_HASH,
+            UniV3Pool::Swap::SIGNATURE_HASH,
+            PancakeV3Pool::Swap::SIGNATURE_HASH,
+        ];
+
+        while self.next_block <= to_block {
+            let to = to_block.min(self.next_block + max_block_range.max(1) - 1);
+            let filter = Filter::new()
+                .address(self.pool)
+                .event_signature(signatures.clone())
+                .from_block(self.next_block)
+                .to_block(to);
+
+            let mut logs: Vec<PoolLog> = provider
+                .get_logs(&filter)
+                .await?
+                .iter()
+                .filter(|log| !log.removed)
+                .filter_map(PoolLog::decode)
+                .collect();
+            logs.sort_by_key(|l| (l.block_number, l.log_index));
+
+            for log in &logs {
+                self.apply(&log.event);
+            }
+            self.next_block = to + 1;
+        }
+
+        Ok(())
+    }
+
+    /// Apply a single event in chain order
+    pub fn apply(&mut self, event: &PoolEvent) {
+        match event {
+            PoolEvent::V3Initialize {
+                sqrt_price_x96,
+                tick,
+            } => {
+                self.x96price = *sqrt_price_x96;
+                self.current_tick = *tick;
+            }
+            PoolEvent::V3Swap {
+                sqrt_price_x96,
+                liquidity,
+                tick,
+            } => {
+                self.x96price = *sqrt_price_x96;
+                self.liquidity = *liquidity;
+                self.current_tick = *tick;
+            }
+            PoolEvent::V3Mint {
+                tick_lower,
+                tick_upper,
+                amount,
+            } => self.modify_position(*tick_lower, *tick_upper, *amount as i128),
+            PoolEvent::V3Burn {
+                tick_lower,
+                tick_upper,
+                amount,
+            } => self.modify_position(*tick_lower, *tick_upper, -(*amount as i128)),
+            _ => {}
+        }
+    }
+
+    fn modify_position(&mut self, tick_lower: I24, tick_upper: I24, delta: i128) {
+        if delta == 0 {
+            return;
+        }
+        self.update_tick(tick_lower, delta, false);
+        self.update_tick(tick_upper, delta, true);
+
+        if self.current_tick >= tick_lower && self.current_tick < tick_upper {
+            self.liquidity =
+                tick_math::update_liquidity(self.liquidity, delta).unwrap_or(self.liquidity);
+        }
+    }
+
+    // Mirrors Tick.update: gross always grows with the position, net flips sign on the upper tick
+    fn update_tick(&mut self, tick: I24, delta: i128, upper: bool) {
+        let entry = self.ticks.entry(tick).or_default();
+        entry.gross = entry.gross.saturating_add_signed(delta);
+        entry.net += if upper { -delta } else { delta };
+
+        if entry.gross == 0 {
+            self.ticks.remove(&tick);
+        }
+    }
+
+    /// Compare the replayed state with `slot0` and `liquidity` at the last replayed block
+    pub async fn verify(&self, provider: &Rpc) -> Result<(), anyhow::Error> {
+        let block = BlockId::number(self.next_block.saturating_sub(1));
+        let contract = UniV3Pool::new(self.pool, provider.clone());
+        let slot0 = contract.slot0().call().block(block).await?;
+        let liquidity = U256::from(contract.liquidity().call().block(block).await?);
+
+        if U256::from(slot0.sqrtPriceX96) != self.x96price || slot0.tick != self.current_tick {
+            bail!(
+                "replayed price {} (tick {}) differs from slot0 {} (tick {})",
+                self.x96price,
+                self.current_tick,
+                slot0.sqrtPriceX96,
+                slot0.tick
+            );
+        }
+        if liquidity != self.liquidity {
+            bail!(
+                "replayed liquidity {} differs from on chain {}",
+                self.liquidity,
+                liquidity
+            );
+        }
+        Ok(())
+    }
+
+    pub fn into_sim(&self) -> V3PoolSim {
+        V3PoolSim {
+            address: self.pool,
+            token0: self.token0,
+            token1: self.token1,
+            fee: self.fee,
+            current_tick: self.current_tick,
+            active_ticks: self
+                .ticks
+                .iter()
+                .map(|(&tick, liq)| Tick {
+                    tick,
+                    liquidity_net: Some(liq.net),
+                })
+                .collect(),
+            tick_spacing: self.tick_spacing,
+            liquidity: self.liquidity,
+            x96price: self.x96price,
+            tax0: TransferTax::None,
+            tax1: TransferTax::None,
+        }
+    }
+
+    /// Plain text checkpoint: a header line with the pool and its state,
+    /// then one `tick gross net` line per initialized tick
+    pub fn to_checkpoint(&self) -> String {
+        let mut out = format!(
+            "{} {} {} {} {} {} {} {} {}\n",
+            self.pool,
+            self.token0,
+            self.token1,
+            self.fee,
+            self.tick_spacing,
+            self.next_block,
+            self.x96price,
+            self.liquidity,
+            self.current_tick
+        );
+        for (tick, liq) in &self.ticks {
+            out.push_str(&format!("{} {} {}\n", tick, liq.gross, liq.net));
+        }
+        out
+    }
+
+    pub fn from_checkpoint(checkpoint: &str) -> Result<Self, anyhow::Error> {
+        let mut lines = checkpoint.lines();
+        let header: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| anyhow!("empty checkpoint"))?
+            .split_whitespace()
+            .collect();
+        let [
+            pool,
+            token0,
+            token1,
+            fee,
+            tick_spacing,
+            next_block,
+            x96price,
+            liquidity,
+            tick,
+        ] = header[..]
+        else {
+            bail!("malformed checkpoint header");
+        };
+
+        let mut ticks = BTreeMap::new();
+        for line in lines.filter(|l| !l.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [tick, gross, net] = fields[..] else {
+                bail!("malformed checkpoint tick line: {line}");
+            };
+            ticks.insert(
+                parse_i24(tick)?,
+                TickLiquidity {
+                    gross: gross.parse()?,
+                    net: net.parse()?,
+                },
+            );
+        }
+
+        Ok(Self {
+            pool: Address::from_str(pool)?,
+            token0: Address::from_str(token0)?,
+            token1: Address::from_str(token1)?,
+            fee: U24::from_str(fee)?,
+            tick_spacing: parse_i24(tick_spacing)?,
+            next_block: next_block.parse()?,
+            x96price: U256::from_str(x96price)?,
+            liquidity: U256::from_str(liquidity)?,
+            current_tick: parse_i24(tick)?,
+            ticks,
+        })
+    }
+}
+
+fn parse_i24(s: &str) -> Result<I24, anyhow::Error> {
+    Ok(I24::try_from(s.parse::<i32>()?)?)
+}
@@ -0,0 +1,94 @@
+//! Hooks of V4 pools. What a hook may do is encoded in the low 14 bits of
+//! its address, a hook that touches swaps needs a `HookModel` before its
+//! pool can be quoted.
+
+use std::fmt;
+
+use alloy::primitives::aliases::U24;
+use alloy::primitives::{Address, U256};
+
+use crate::err::PoolError;
+use crate::v3_pool_sim::V3PoolSim;
+
+/// `PoolKey.fee` of a pool whose LP fee is set by its hook, the current fee
+/// is slot0's `lpFee`
+pub const DYNAMIC_FEE_FLAG: u32 = 0x800000;
+/// Set on a fee returned by `beforeSwap` to replace the LP fee for one swap
+pub const OVERRIDE_FEE_FLAG: u32 = 0x400000;
+
+pub fn is_dynamic_fee(fee: U24) -> bool {
+    fee.to::<u32>() == DYNAMIC_FEE_FLAG
+}
+
+/// Permission flags of a hook, as `Hooks.sol` reads them off the address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HookPermissions(pub u16);
+
+impl HookPermissions {
+    pub const BEFORE_INITIALIZE: u16 = 1 << 13;
+    pub const AFTER_INITIALIZE: u16 = 1 << 12;
+    pub const BEFORE_ADD_LIQUIDITY: u16 = 1 << 11;
+    pub const AFTER_ADD_LIQUIDITY: u16 = 1 << 10;
+    pub const BEFORE_REMOVE_LIQUIDITY: u16 = 1 << 9;
+    pub const AFTER_REMOVE_LIQUIDITY: u16 = 1 << 8;
+    pub const BEFORE_SWAP: u16 = 1 << 7;
+    pub const AFTER_SWAP: u16 = 1 << 6;
+    pub const BEFORE_DONATE: u16 = 1 << 5;
+    pub const AFTER_DONATE: u16 = 1 << 4;
+    pub const BEFORE_SWAP_RETURNS_DELTA: u16 = 1 << 3;
+    pub const AFTER_SWAP_RETURNS_DELTA: u16 = 1 << 2;
+    pub const AFTER_ADD_LIQUIDITY_RETURNS_DELTA: u16 = 1 << 1;
+    pub const AFTER_REMOVE_LIQUIDITY_RETURNS_DELTA: u16 = 1;
+
+    const ALL: u16 = (1 << 14) - 1;
+    const SWAP: u16 = Self::BEFORE_SWAP
+        | Self::AFTER_SWAP
+        | Self::BEFORE_SWAP_RETURNS_DELTA
+        | Self::AFTER_SWAP_RETURNS_DELTA;
+
+    pub fn from_address(hooks: Address) -> Self {
+        Self(u16::from_be_bytes([hooks[18], hooks[19]]) & Self::ALL)
+    }
+
+    pub fn contains(&self, flag: u16) -> bool {
+        self.0 & flag == flag
+    }
+
+    /// Does the hook run on swaps, so quoting needs a model of it?
+    pub fn affects_swap(&self) -> bool {
+        self.0 & Self::SWAP != 0
+    }
+}
+
+/// What a `beforeSwap` hook did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BeforeSwap {
+    /// LP fee for this swap in place of the pool's, in pips
+    pub lp_fee: Option<U24>,
+    /// Part of the input the hook kept, the pool swaps the rest
+    pub amount_taken: U256,
+}
+
+/// Offline model of a hook's swap callbacks, written per hook contract.
+/// Both default to a hook that changes nothing
+pub trait HookModel: fmt::Debug + Send + Sync {
+    fn before_swap(
+        &self,
+        _pool: &V3PoolSim,
+        _amount_in: U256,
+        _from0: bool,
+    ) -> Result<BeforeSwap, PoolError> {
+        Ok(BeforeSwap::default())
+    }
+
+    /// Amount the trader ends up with out of `amount_out` sent by the pool
+    fn after_swap(
+        &self,
+        _pool: &V3PoolSim,
+        _amount_in: U256,
+        amount_out: U256,
+        _from0: bool,
+    ) -> Result<U256, PoolError> {
+        Ok(amount_out)
+    }
+}
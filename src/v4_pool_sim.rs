@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use alloy::primitives::aliases::{I24, U24};
+use alloy::primitives::{Address, B256, U256};
+
+use crate::PoolKey;
+use crate::err::{MathError, PoolError};
+use crate::token_tax::TransferKind;
+use crate::trade::Trade;
+use crate::v3_pool_sim::V3PoolSim;
+use crate::v4_hooks::{HookModel, HookPermissions, OVERRIDE_FEE_FLAG};
+use crate::v4_pool_src::pool_id;
+
+/// Offline copy of a V4 pool: V3 swap math plus the pool's hook and fees.
+#[derive(Debug, Clone)]
+pub struct V4PoolSim {
+    /// Price, liquidity and ticks, keyed by the low 20 bytes of `id`
+    pub pool: V3PoolSim,
+    pub id: B256,
+    pub hooks: Address,
+    pub permissions: HookPermissions,
+    /// `PoolKey.fee`, `DYNAMIC_FEE_FLAG` when the hook sets the fee
+    pub key_fee: U24,
+    /// Current LP fee from slot0, in pips
+    pub lp_fee: U24,
+    /// Protocol fee from slot0, zero for one in the low 12 bits and one for
+    /// zero in the high 12
+    pub protocol_fee: U24,
+    pub hook_model: Option<Arc<dyn HookModel>>,
+}
+
+impl V4PoolSim {
+    /// Pool with only its key known, e.g. found in an `Initialize` event,
+    /// without price or liquidity until it is refetched
+    pub fn new(key: &PoolKey) -> Self {
+        let id = pool_id(key);
+        Self {
+            pool: V3PoolSim {
+                address: Address::from_word(id),
+                token0: key.currency0,
+                token1: key.currency1,
+                fee: U24::ZERO,
+                current_tick: I24::ZERO,
+                active_ticks: Vec::new(),
+                tick_spacing: key.tickSpacing,
+                liquidity: U256::ZERO,
+                x96price: U256::ZERO,
+                tax0: Default::default(),
+                tax1: Default::default(),
+            },
+            id,
+            hooks: key.hooks,
+            permissions: HookPermissions::from_address(key.hooks),
+            key_fee: key.fee,
+            lp_fee: U24::ZERO,
+            protocol_fee: U24::ZERO,
+            hook_model: None,
+        }
+    }
+
+    pub fn key(&self) -> PoolKey {
+        PoolKey {
+            currency0: self.pool.token0,
+            currency1: self.pool.token1,
+            fee: self.key_fee,
+            tickSpacing: self.pool.tick_spacing,
+            hooks: self.hooks,
+        }
+    }
+
+    pub fn with_hook_model(mut self, model: Arc<dyn HookModel>) -> Self {
+        self.hook_model = Some(model);
+        self
+    }
+
+    /// Total fee of a swap in pips, `ProtocolFeeLibrary.calculateSwapFee`
+    pub fn swap_fee(&self, lp_fee: U24, from0: bool) -> U24 {
+        let protocol_fee = self.protocol_fee.to::<u32>();
+        let protocol_fee = if from0 {
+            protocol_fee & 0xfff
+        } else {
+            protocol_fee >> 12
+        } as u64;
+        let lp_fee = lp_fee.to::<u64>();
+        U24::from(protocol_fee + lp_fee - protocol_fee * lp_fee / 1_000_000)
+    }
+
+    /// Swap `amount_in` sent by the trader through the hook's model, pools
+    /// whose hook changes swaps can't be quoted without one
+    pub fn trade(&mut self, amount_in: U256, from0: bool) -> Result<Trade, PoolError> {
+        let model = match &self.hook_model {
+            Some(model) => Some(model.clone()),
+            None if self.permissions.affects_swap() => {
+                return Err(PoolError::Unsupported {
+                    pool: self.pool.address,
+                    reason: format!("hook {} changes swaps and has no model", self.hooks),
+                });
+            }
+            None => None,
+        };
+
+        let (tax_in, tax_out) = match from0 {
+            true => (&self.pool.tax0, &self.pool.tax1),
+            false => (&self.pool.tax1, &self.pool.tax0),
+        };
+        let amount_in_net = tax_in.net(amount_in, TransferKind::Sell);
+        let tax_out = tax_out.clone();
+
+        let before = match &model {
+            Some(model) => model.before_swap(&self.pool, amount_in_net, from0)?,
+            None => Default::default(),
+        };
+        let lp_fee = match before.lp_fee {
+            Some(fee) => U24::from(fee.to::<u32>() & !OVERRIDE_FEE_FLAG),
+            None => self.lp_fee,
+        };
+        let fee = self.swap_fee(lp_fee, from0);
+        let remaining = amount_in_net
+            .checked_sub(before.amount_taken)
+            .ok_or_else(|| MathError::overflow("hook delta"))?;
+
+        self.pool.fee = fee;
+        let mut amount_out = self.pool.swap(remaining, from0)?;
+        if let Some(model) = &model {
+            amount_out = model.after_swap(&self.pool, remaining, amount_out, from0)?;
+        }
+
+        Ok(Trade {
+            fee,
+            token0: self.pool.token0,
+            token1: self.pool.token1,
+            pool: self.pool.address,
+            from0,
+            amount_in,
+            amount_out,
+            amount_in_net,
+            amount_out_net: tax_out.net(amount_out, TransferKind::Buy),
+        })
+    }
+}
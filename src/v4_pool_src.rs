@@ -1,126 +1,180 @@
-use std::sync::Arc;
-
-use ethers::{
-    abi::Address,
-    contract::Contract,
-    types::{H160, U256},
-};
-use ethers_providers::Provider;
-use tokio::sync::RwLock;
-
-use crate::{
-    err::PoolUpdateError,
-    mult_provider::MultiProvider,
-    tick_math::{self, Tick},
-    token::Token,
-    v3_pool_sim::V3PoolSim, // ← your V3 sim types
-};
-
-/// A “source” for a Uniswap v4 pool.  
-/// Internally it calls the single V4 factory/vault contract to
-/// fetch *any* pool’s state by parameters, then feeds that
-/// into your existing V3 simulation.
-pub struct V4PoolSrc {
-    /// The singleton V4 “factory” or “vault” contract
-    factory: Contract<Provider<MultiProvider,>,>,
+use std::collections::HashMap;
+
+use alloy::eips::BlockId;
+use alloy::primitives::aliases::{I24, U24};
+use alloy::primitives::{Address, B256, U256, keccak256};
+use alloy::sol_types::SolValue;
+
+use crate::err::PoolError;
+use crate::tick_math::Tick;
+use crate::token_tax::TransferTax;
+use crate::v3_pool_sim::V3PoolSim;
+use crate::v3_pool_src::{Rpc, walk_left, walk_right};
+use crate::v4_hooks::HookPermissions;
+use crate::v4_pool_sim::V4PoolSim;
+use crate::{PoolKey, StateView::StateViewInstance};
 
-    /// The three parameters that identify a pool in V4:
-    token0: Arc<RwLock<Token,>,>,
-    token1: Arc<RwLock<Token,>,>,
-    fee: u32,
-    tick_spacing: i32,
+type StateViewContract = StateViewInstance<Rpc>;
+
+/// `PoolId` of a key, `keccak256(abi.encode(key))` like `PoolIdLibrary.toId`
+pub fn pool_id(key: &PoolKey) -> B256 {
+    keccak256(key.abi_encode())
+}
 
-    /// Last‐fetched on-chain state:
-    pub sqrt_price_x96: U256,
+/// A Uniswap V4 pool, read through the `StateView` lens since V4 pools live
+/// inside the singleton PoolManager and have no contract of their own.
+///
+/// The swap math is V3's, the sim wraps a `V3PoolSim` with the pool's hook
+/// and fees.
+#[derive(Debug)]
+pub struct V4PoolSrc {
+    pub key: PoolKey,
+    pub id: B256,
+    pub current_tick: I24,
+    pub active_ticks: Vec<Tick>,
+    pub bitmap: HashMap<i16, U256>,
     pub liquidity: U256,
-    pub active_ticks: Vec<Tick,>,
+    pub x96price: U256,
+    /// LP fee of the next swap, differs from `key.fee` for dynamic fee pools
+    pub lp_fee: U24,
+    pub protocol_fee: U24,
+    pub contract: StateViewContract,
 }
 
 impl V4PoolSrc {
-    /// Build a new wrapper around the V4 factory.
-    pub fn new(
-        factory: Contract<Provider<MultiProvider,>,>, token0: Arc<RwLock<Token,>,>, token1: Arc<RwLock<Token,>,>,
-        fee: u32, tick_spacing: i32,
-    ) -> Self {
-        Self {
-            factory,
-            token0,
-            token1,
-            fee,
-            tick_spacing,
-            sqrt_price_x96: U256::zero(),
-            liquidity: U256::zero(),
+    pub async fn new(key: PoolKey, state_view: Address, provider: Rpc) -> Result<Self, PoolError> {
+        Self::new_at(key, state_view, provider, BlockId::latest()).await
+    }
+
+    /// Load the pool state as of `block`
+    pub async fn new_at(
+        key: PoolKey,
+        state_view: Address,
+        provider: Rpc,
+        block: BlockId,
+    ) -> Result<Self, PoolError> {
+        let mut instance = Self {
+            id: pool_id(&key),
+            key,
+            current_tick: I24::ZERO,
             active_ticks: Vec::new(),
-        }
+            bitmap: HashMap::new(),
+            liquidity: U256::ZERO,
+            x96price: U256::ZERO,
+            lp_fee: U24::ZERO,
+            protocol_fee: U24::ZERO,
+            contract: StateViewInstance::new(state_view, provider),
+        };
+        instance.update_at(block).await?;
+        Ok(instance)
+    }
+
+    /// V4 pools have no address, sims and trades key them by the low 20
+    /// bytes of the `PoolId`
+    pub fn address(&self) -> Address {
+        Address::from_word(self.id)
+    }
+
+    pub async fn update(&mut self) -> Result<(), PoolError> {
+        self.update_at(BlockId::latest()).await
     }
 
-    /// Fetch the on-chain state into `self.sqrt_price_x96`, `self.liquidity`, `self.active_ticks`.
-    ///  
-    /// In V4 there is no pool contract; instead the factory/vault exposes:
-    /// 1. `getPool`
-    /// 2. `observe` / `snapshotCumulativesInside` or similar to get tick data
-    pub async fn update(&mut self,) -> Result<(), PoolUpdateError,> {
-        // 1) Call factory.getPool(token0, token1, fee) → returns pool ID (e.g. bytes32 or H160)
-        let pool_id_call = self.factory.method::<(H160, H160, u32,), H160>(
-            "getPool",
-            (self.token0.read().await.address, self.token1.read().await.address, self.fee,),
-        )?;
-        let pool_addr = pool_id_call.call().await?;
-
-        // 2) Call factory.getPoolState(pool_id) → (sqrtPriceX96, liquidity)
-        let state_call = self.factory.method::<H160, (U256, U256,)>("getPoolState", pool_addr,)?.call().await?;
-        let (sqrt_price, liquidity,) = state_call;
-        self.sqrt_price_x96 = sqrt_price;
-        self.liquidity = liquidity;
-
-        // 3) Fetch active ticks around current tick
-        //    Here we fetch e.g.  `snapshotCumulativesInside(pool_id, lower, upper)`
-        //    or `factory.getPopulatedTicks(pool_id, startIndex, endIndex)`
-        //    depending on V4 interface.  Suppose we have:
-        //
-        //    fn getTicks(pool: H160, start: i32, end: i32) -> (Vec<i32>, Vec<i128>);
-        //
-        //    which returns parallel arrays of tick index & net liquidity.
-        let current_tick = tick_math::tick_from_price(self.sqrt_price_x96,)
-            .ok_or(PoolUpdateError::Custom("invalid price".to_string(),),)?;
-
-        let window = 10 * self.tick_spacing; // e.g. ±10 ticks
-        let ticks_call = self
-            .factory
-            .method::<(H160, i32, i32,), (Vec<i32,>, Vec<i128,>,)>(
-                "getTicks",
-                (pool_addr, current_tick - window, current_tick + window,),
-            )?
+    /// Reload price, liquidity and the ticks around the price as of `block`
+    pub async fn update_at(&mut self, block: BlockId) -> Result<(), PoolError> {
+        let slot0 = self.contract.getSlot0(self.id).call().block(block).await?;
+        if slot0.sqrtPriceX96.is_zero() {
+            return Err(PoolError::Unsupported {
+                pool: self.address(),
+                reason: "pool is not initialized".into(),
+            });
+        }
+        let liquidity = self
+            .contract
+            .getLiquidity(self.id)
             .call()
+            .block(block)
             .await?;
 
-        // transform into your Tick struct
-        let (idxs, nets,) = ticks_call;
-        self.active_ticks = idxs
-            .into_iter()
-            .zip(nets.into_iter(),)
-            .map(|(tick, net,)| Tick {
+        self.x96price = U256::from(slot0.sqrtPriceX96);
+        self.current_tick = slot0.tick;
+        self.lp_fee = slot0.lpFee;
+        self.protocol_fee = slot0.protocolFee;
+        self.liquidity = U256::from(liquidity);
+        self.bitmap.clear();
+        self.active_ticks = self.update_ticks(5, block).await?;
+        Ok(())
+    }
+
+    /// `range` initialized ticks on each side of the current one, with their
+    /// liquidity
+    async fn update_ticks(&mut self, range: usize, block: BlockId) -> Result<Vec<Tick>, PoolError> {
+        let (contract, id) = (&self.contract, self.id);
+        let read_word = async |word: i16| -> Result<U256, PoolError> {
+            Ok(contract.getTickBitmap(id, word).call().block(block).await?)
+        };
+        let spacing = self.key.tickSpacing;
+        let mut r = walk_right(
+            &mut self.bitmap,
+            self.current_tick,
+            spacing,
+            range,
+            &read_word,
+        )
+        .await?;
+        let mut l = walk_left(
+            &mut self.bitmap,
+            self.current_tick,
+            spacing,
+            range,
+            &read_word,
+        )
+        .await?;
+
+        l.reverse();
+        l.append(&mut r);
+
+        let mut ticks = Vec::new();
+        for tick in l {
+            let info = contract
+                .getTickLiquidity(id, tick)
+                .call()
+                .block(block)
+                .await?;
+            ticks.push(Tick {
                 tick,
-                liquidityNet: net,
-            },)
-            .collect();
+                liquidity_net: Some(info.liquidityNet),
+            });
+        }
+        Ok(ticks)
+    }
 
-        Ok((),)
+    pub fn permissions(&self) -> HookPermissions {
+        HookPermissions::from_address(self.key.hooks)
     }
 
-    /// Convert into your existing V3 simulation type
-    pub async fn into_sim(&self,) -> V3PoolSim {
-        V3PoolSim {
-            exchange: "UniswapV4".into(),
-            version: "v4".into(),
-            fee: self.fee,
-            tick_spacing: self.tick_spacing,
-            x96price: self.sqrt_price_x96,
-            liquidity: self.liquidity,
-            active_ticks: self.active_ticks.clone(),
-            address: todo!(),
-            token0: todo!(),
-            token1: todo!(),
+    /// Sim without a hook model, attach one with `V4PoolSim::with_hook_model`
+    pub fn into_sim(&self) -> V4PoolSim {
+        V4PoolSim {
+            pool: V3PoolSim {
+                address: self.address(),
+                token0: self.key.currency0,
+                token1: self.key.currency1,
+                fee: self.lp_fee,
+                current_tick: self.current_tick,
+                active_ticks: self.active_ticks.clone(),
+                tick_spacing: self.key.tickSpacing,
+                liquidity: self.liquidity,
+                x96price: self.x96price,
+                tax0: TransferTax::None,
+                tax1: TransferTax::None,
+            },
+            id: self.id,
+            hooks: self.key.hooks,
+            permissions: self.permissions(),
+            key_fee: self.key.fee,
+            lp_fee: self.lp_fee,
+            protocol_fee: self.protocol_fee,
+            hook_model: None,
         }
     }
 }
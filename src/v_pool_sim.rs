@@ -1,59 +1,182 @@
+use alloy::primitives::{Address, U256, aliases::I24};
 
-use alloy::primitives::{Address, U256};
+use crate::{
+    algebra_pool_sim::AlgebraPoolSim,
+    balancer_pool_sim::BalancerPoolSim,
+    currency::{Currency, WrapSim},
+    curve_crypto_pool_sim::CurveCryptoPoolSim,
+    curve_pool_sim::CurvePoolSim,
+    erc4626_sim::Erc4626Sim,
+    err::PoolError,
+    lb_pool_sim::LbPoolSim,
+    solidly_pool_sim::SolidlyPoolSim,
+    token_tax::TokenTaxes, trade::Trade, v2_pool_sim::V2PoolSim, v3_pool_sim::V3PoolSim,
+    v4_pool_sim::V4PoolSim,
+};
 
-use crate::{trade::Trade, v2_pool_sim::V2PoolSim, v3_pool_sim::V3PoolSim};
-
-#[derive(Debug,)]
+#[derive(Debug, Clone,)]
 pub enum AnyPoolSim {
     V2(V2PoolSim,),
     V3(V3PoolSim,),
+    /// Keyed by the low 20 bytes of its `PoolId` like `V4PoolSrc::address`
+    V4(V4PoolSim,),
+    /// Native to wrapped and back, links V4 pools holding native to the rest
+    Wrap(WrapSim,),
+    /// Quoted between its first two coins, `CurvePoolSim::trade_coins`
+    /// reaches the others
+    Curve(CurvePoolSim,),
+    /// Volatile pair or triple, quoted like `Curve`
+    CurveCrypto(CurveCryptoPoolSim,),
+    /// Weighted or stable, quoted between its first two tokens
+    Balancer(BalancerPoolSim,),
+    /// V3 ticks with a fee per direction set by the pool
+    Algebra(AlgebraPoolSim,),
+    /// V2-like pair, `x^3y + y^3x` when stable
+    Solidly(SolidlyPoolSim,),
+    /// Trader Joe Liquidity Book, X as token0, bins of constant price
+    Lb(LbPoolSim,),
+    /// ERC-4626 vault depositing its asset (token0) for shares (token1)
+    /// and redeeming them, keyed by the vault like `Wrap` by the wrapped token
+    Erc4626(Erc4626Sim,),
 }
 
 impl AnyPoolSim {
     /// Purely synchronous AMM calculation, mutating local reserves
-    pub fn trade(&mut self, amount_in: U256, from0: bool,) -> Option<Trade,> {
+    pub fn trade(&mut self, amount_in: U256, from0: bool,) -> Result<Trade, PoolError,> {
         match self {
             AnyPoolSim::V2(sim,) => sim.trade(amount_in, from0,),
             AnyPoolSim::V3(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::V4(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::Wrap(sim,) => Ok(sim.trade(amount_in, from0,),),
+            AnyPoolSim::Curve(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::CurveCrypto(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::Balancer(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::Algebra(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::Solidly(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::Lb(sim,) => sim.trade(amount_in, from0,),
+            AnyPoolSim::Erc4626(sim,) => sim.trade(amount_in, from0,),
+        }
+    }
+
+    pub fn apply_taxes(&mut self, taxes: &TokenTaxes,) {
+        match self {
+            AnyPoolSim::V2(sim,) => sim.apply_taxes(taxes,),
+            AnyPoolSim::V3(sim,) => sim.apply_taxes(taxes,),
+            AnyPoolSim::V4(sim,) => sim.pool.apply_taxes(taxes,),
+            AnyPoolSim::Algebra(sim,) => sim.pool.apply_taxes(taxes,),
+            AnyPoolSim::Solidly(sim,) => sim.apply_taxes(taxes,),
+            AnyPoolSim::Wrap(_,)
+            | AnyPoolSim::Curve(_,)
+            | AnyPoolSim::CurveCrypto(_,)
+            | AnyPoolSim::Balancer(_,)
+            | AnyPoolSim::Lb(_,)
+            | AnyPoolSim::Erc4626(_,) => {},
         }
     }
 
     pub fn get_tokens(&self,) -> [Address; 2] {
         match self {
-            AnyPoolSim::V2(v2_pool,) => [v2_pool.token0.address, v2_pool.token1.address,],
-            AnyPoolSim::V3(v3_pool,) => [v3_pool.token0.address, v3_pool.token1.address,],
+            AnyPoolSim::V2(v2_pool,) => [v2_pool.token0, v2_pool.token1,],
+            AnyPoolSim::V3(v3_pool,) => [v3_pool.token0, v3_pool.token1,],
+            AnyPoolSim::V4(v4_pool,) => [v4_pool.pool.token0, v4_pool.pool.token1,],
+            AnyPoolSim::Wrap(wrap,) => [Address::ZERO, wrap.wrapped,],
+            AnyPoolSim::Curve(curve,) => [curve.coins[0], curve.coins[1],],
+            AnyPoolSim::CurveCrypto(curve,) => [curve.coins[0], curve.coins[1],],
+            AnyPoolSim::Balancer(balancer,) => [balancer.tokens[0], balancer.tokens[1],],
+            AnyPoolSim::Algebra(algebra,) => [algebra.pool.token0, algebra.pool.token1,],
+            AnyPoolSim::Solidly(solidly,) => [solidly.token0, solidly.token1,],
+            AnyPoolSim::Lb(lb,) => [lb.token_x, lb.token_y,],
+            AnyPoolSim::Erc4626(vault,) => [vault.asset, vault.address,],
         }
     }
+
+    /// `get_tokens` telling native apart from ERC-20s
+    pub fn get_currencies(&self,) -> [Currency; 2] {
+        self.get_tokens().map(Currency::from,)
+    }
+
     pub fn get_address(&self,) -> Address {
         match self {
             AnyPoolSim::V2(v2_pool,) => v2_pool.address,
             AnyPoolSim::V3(v3_pool,) => v3_pool.address,
+            AnyPoolSim::V4(v4_pool,) => v4_pool.pool.address,
+            AnyPoolSim::Wrap(wrap,) => wrap.wrapped,
+            AnyPoolSim::Curve(curve,) => curve.address,
+            AnyPoolSim::CurveCrypto(curve,) => curve.address,
+            AnyPoolSim::Balancer(balancer,) => balancer.address,
+            AnyPoolSim::Algebra(algebra,) => algebra.pool.address,
+            AnyPoolSim::Solidly(solidly,) => solidly.address,
+            AnyPoolSim::Lb(lb,) => lb.address,
+            AnyPoolSim::Erc4626(vault,) => vault.address,
         }
     }
     pub fn is_0(&self, token: &Address,) -> bool {
         match self {
-            AnyPoolSim::V2(v2_pool,) => (v2_pool.token0.address == *token),
-            AnyPoolSim::V3(v3_pool,) => (v3_pool.token0.address == *token),
+            AnyPoolSim::V2(v2_pool,) => v2_pool.token0 == *token,
+            AnyPoolSim::V3(v3_pool,) => v3_pool.token0 == *token,
+            AnyPoolSim::V4(v4_pool,) => v4_pool.pool.token0 == *token,
+            AnyPoolSim::Wrap(_,) => token.is_zero(),
+            AnyPoolSim::Curve(curve,) => curve.coins[0] == *token,
+            AnyPoolSim::CurveCrypto(curve,) => curve.coins[0] == *token,
+            AnyPoolSim::Balancer(balancer,) => balancer.tokens[0] == *token,
+            AnyPoolSim::Algebra(algebra,) => algebra.pool.token0 == *token,
+            AnyPoolSim::Solidly(solidly,) => solidly.token0 == *token,
+            AnyPoolSim::Lb(lb,) => lb.token_x == *token,
+            AnyPoolSim::Erc4626(vault,) => vault.asset == *token,
         }
     }
 
-    pub fn apply_swap(&mut self, amount0_in: U256, amount1_in: U256, amount0_out: U256, amount1_out: U256) {
+    pub fn apply_swap(
+        &mut self, amount0_in: U256, amount1_in: U256, amount0_out: U256, amount1_out: U256,
+    ) -> Result<(), PoolError,> {
         match self {
-            AnyPoolSim::V2(v2_pool_sim,) => v2_pool_sim.apply_swap(amount0_in, amount1_in, amount0_out, amount1_out,),
-            AnyPoolSim::V3(v3_pool_sim,) =>
-            // V3 only needs the amount_in and a direction flag (from0)
+            AnyPoolSim::V2(v2_pool_sim,) => {
+                v2_pool_sim.apply_swap(amount0_in, amount1_in, amount0_out, amount1_out,);
+                Ok((),)
+            },
+            AnyPoolSim::Solidly(solidly,) => {
+                solidly.apply_swap(amount0_in, amount1_in, amount0_out, amount1_out,)
+            },
+            AnyPoolSim::V3(v3_pool_sim,) | AnyPoolSim::V4(V4PoolSim { pool: v3_pool_sim, .. },) =>
+            // V3 only needs the amount_in and a direction flag (from0),
+            // logged amounts are what the pool got so no taxes apply
             {
                 if !amount0_in.is_zero() {
-                    v3_pool_sim.trade(amount0_in, true,);
+                    v3_pool_sim.swap(amount0_in, true,)?;
                 } else {
-                    v3_pool_sim.trade(amount1_in, false,);
+                    v3_pool_sim.swap(amount1_in, false,)?;
                 }
+                Ok((),)
             },
+            AnyPoolSim::Algebra(algebra,) => {
+                if !amount0_in.is_zero() {
+                    algebra.swap(amount0_in, true,)?;
+                } else {
+                    algebra.swap(amount1_in, false,)?;
+                }
+                Ok((),)
+            },
+            AnyPoolSim::Lb(lb,) => {
+                if !amount0_in.is_zero() {
+                    lb.swap(amount0_in, true,)?;
+                } else {
+                    lb.swap(amount1_in, false,)?;
+                }
+                Ok((),)
+            },
+            // wrapping holds no state, Curve and Balancer swaps can't be
+            // replayed from amounts alone and the pool is refetched instead,
+            // like vaults whose assets grow without a swap
+            AnyPoolSim::Wrap(_,)
+            | AnyPoolSim::Curve(_,)
+            | AnyPoolSim::CurveCrypto(_,)
+            | AnyPoolSim::Balancer(_,)
+            | AnyPoolSim::Erc4626(_,) => Ok((),),
         }
     }
 
     pub fn apply_mint(
-        &mut self, tick_lower: Option<i32,>, tick_upper: Option<i32,>, liquidity: Option<i128,>,
+        &mut self, tick_lower: Option<I24,>, tick_upper: Option<I24,>, liquidity: Option<u128,>,
         amount0: Option<U256,>, amount1: Option<U256,>,
     ) {
         match self {
@@ -62,17 +185,28 @@ impl AnyPoolSim {
                 let a1 = amount1.unwrap_or_default();
                 v2.mint(a0, a1,);
             },
-            AnyPoolSim::V3(v3,) => {
+            AnyPoolSim::Solidly(solidly,) => {
+                solidly.mint(amount0.unwrap_or_default(), amount1.unwrap_or_default(),);
+            },
+            AnyPoolSim::V3(v3,)
+            | AnyPoolSim::V4(V4PoolSim { pool: v3, .. },)
+            | AnyPoolSim::Algebra(AlgebraPoolSim { pool: v3, .. },) => {
                 let lo = tick_lower.expect("tick_lower required for V3 mint",);
                 let hi = tick_upper.expect("tick_upper required for V3 mint",);
                 let liq = liquidity.expect("liquidity required for V3 mint",);
                 v3.mint(lo, hi, liq,);
             },
+            AnyPoolSim::Wrap(_,)
+            | AnyPoolSim::Curve(_,)
+            | AnyPoolSim::CurveCrypto(_,)
+            | AnyPoolSim::Balancer(_,)
+            | AnyPoolSim::Lb(_,)
+            | AnyPoolSim::Erc4626(_,) => {},
         }
     }
 
     pub fn apply_burn(
-        &mut self, tick_lower: Option<i32,>, tick_upper: Option<i32,>, liquidity: Option<i128,>,
+        &mut self, tick_lower: Option<I24,>, tick_upper: Option<I24,>, liquidity: Option<u128,>,
         amount0: Option<U256,>, amount1: Option<U256,>,
     ) {
         match self {
@@ -81,12 +215,23 @@ impl AnyPoolSim {
                 let a1 = amount1.unwrap_or_default();
                 v2.burn(a0, a1,);
             },
-            AnyPoolSim::V3(v3,) => {
+            AnyPoolSim::Solidly(solidly,) => {
+                solidly.burn(amount0.unwrap_or_default(), amount1.unwrap_or_default(),);
+            },
+            AnyPoolSim::V3(v3,)
+            | AnyPoolSim::V4(V4PoolSim { pool: v3, .. },)
+            | AnyPoolSim::Algebra(AlgebraPoolSim { pool: v3, .. },) => {
                 let lo = tick_lower.expect("tick_lower required for V3 burn",);
                 let hi = tick_upper.expect("tick_upper required for V3 burn",);
                 let liq = liquidity.expect("liquidity required for V3 burn",);
                 v3.burn(lo, hi, liq,);
             },
+            AnyPoolSim::Wrap(_,)
+            | AnyPoolSim::Curve(_,)
+            | AnyPoolSim::CurveCrypto(_,)
+            | AnyPoolSim::Balancer(_,)
+            | AnyPoolSim::Lb(_,)
+            | AnyPoolSim::Erc4626(_,) => {},
         }
     }
 }
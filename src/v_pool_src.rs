@@ -1,16 +1,92 @@
-use alloy::primitives::{Address, U256}; use crate::{tick_math::Tick, v2_pool_src::V2PoolSrc, v3_pool_src::V3PoolSrc}; #[derive(Debug,)] pub enum AnyPoolSrc { V2(V2PoolSrc,), V3(V3PoolSrc,), } impl AnyPoolSrc { pub async fn update(&mut self,) -> Result<Address, crate::err::PoolUpdateError,> { match self { AnyPoolSrc::V2(src,) => src.update().await, AnyPoolSrc::V3(src,) => src.update().await, } }
-    pub async fn into_sim(&self,) -> crate::v_pool_sim::AnyPoolSim {
+use alloy::primitives::Address;
+
+use crate::{
+    algebra_pool_src::AlgebraPoolSrc, balancer_pool_src::BalancerPoolSrc,
+    curve_crypto_pool_src::CurveCryptoPoolSrc, curve_pool_src::CurvePoolSrc,
+    erc4626_src::Erc4626Src, err::PoolError, lb_pool_src::LbPoolSrc,
+    solidly_pool_src::SolidlyPoolSrc, v_pool_sim::AnyPoolSim, v2_pool_src::V2PoolSrc,
+    v3_pool_src::V3PoolSrc, v4_pool_src::V4PoolSrc,
+};
+
+#[derive(Debug,)]
+pub enum AnyPoolSrc {
+    V2(V2PoolSrc,),
+    V3(V3PoolSrc,),
+    V4(V4PoolSrc,),
+    Curve(CurvePoolSrc,),
+    CurveCrypto(CurveCryptoPoolSrc,),
+    Balancer(BalancerPoolSrc,),
+    Algebra(AlgebraPoolSrc,),
+    Solidly(SolidlyPoolSrc,),
+    Lb(LbPoolSrc,),
+    Erc4626(Erc4626Src,),
+}
+
+impl AnyPoolSrc {
+    /// Reload the pool's state from the latest block
+    pub async fn update(&mut self,) -> Result<(), PoolError,> {
+        match self {
+            AnyPoolSrc::V2(src,) => src.update().await,
+            AnyPoolSrc::V3(src,) => {
+                let provider = src.contract.provider().clone();
+                *src = V3PoolSrc::new(src.address, provider,).await?;
+                Ok((),)
+            },
+            AnyPoolSrc::V4(src,) => src.update().await,
+            AnyPoolSrc::Curve(src,) => src.update().await,
+            AnyPoolSrc::CurveCrypto(src,) => src.update().await,
+            AnyPoolSrc::Balancer(src,) => src.update().await,
+            AnyPoolSrc::Algebra(src,) => src.update().await,
+            AnyPoolSrc::Solidly(src,) => src.update().await,
+            AnyPoolSrc::Lb(src,) => src.update().await,
+            AnyPoolSrc::Erc4626(src,) => src.update().await,
+        }
+    }
+
+    pub fn into_sim(&self,) -> AnyPoolSim {
+        match self {
+            AnyPoolSrc::V2(src,) => AnyPoolSim::V2(src.into_sim(),),
+            AnyPoolSrc::V3(src,) => AnyPoolSim::V3(src.into_sim(),),
+            AnyPoolSrc::V4(src,) => AnyPoolSim::V4(src.into_sim(),),
+            AnyPoolSrc::Curve(src,) => AnyPoolSim::Curve(src.into_sim(),),
+            AnyPoolSrc::CurveCrypto(src,) => AnyPoolSim::CurveCrypto(src.into_sim(),),
+            AnyPoolSrc::Balancer(src,) => AnyPoolSim::Balancer(src.into_sim(),),
+            AnyPoolSrc::Algebra(src,) => AnyPoolSim::Algebra(src.into_sim(),),
+            AnyPoolSrc::Solidly(src,) => AnyPoolSim::Solidly(src.into_sim(),),
+            AnyPoolSrc::Lb(src,) => AnyPoolSim::Lb(src.into_sim(),),
+            AnyPoolSrc::Erc4626(src,) => AnyPoolSim::Erc4626(src.into_sim(),),
+        }
+    }
+
+    pub fn get_address(&self,) -> Address {
+        match self {
+            AnyPoolSrc::V2(src,) => src.address,
+            AnyPoolSrc::V3(src,) => src.address,
+            AnyPoolSrc::V4(src,) => src.address(),
+            AnyPoolSrc::Curve(src,) => src.address,
+            AnyPoolSrc::CurveCrypto(src,) => src.address,
+            AnyPoolSrc::Balancer(src,) => src.address,
+            AnyPoolSrc::Algebra(src,) => src.address,
+            AnyPoolSrc::Solidly(src,) => src.address,
+            AnyPoolSrc::Lb(src,) => src.address,
+            AnyPoolSrc::Erc4626(src,) => src.address,
+        }
+    }
+
+    /// Curve and Balancer pools can hold more, these are the first two.
+    /// Vaults trade their asset for their own shares
+    pub fn get_tokens(&self,) -> [Address; 2] {
         match self {
-            AnyPoolSrc::V2(v2_pool,) => crate::v_pool_sim::AnyPoolSim::V2(v2_pool.into_sim().await,),
-            AnyPoolSrc::V3(v3_pool,) => crate::v_pool_sim::AnyPoolSim::V3(v3_pool.into_sim().await,),
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-			-- Mason must be loaded before its dependents so we need to set it up here.
-        } } pub async fn get_tokens(&self,) -> [Address; 2] { match self { AnyPoolSrc::V2(v2_pool,) => [v2_pool.token0, v2_pool.token1,], AnyPoolSrc::V3(v3_pool,) => [v3_pool.token0, v3_pool.token1,], } } /// Is the given address the token0 of this pool? pub async fn is_0(&self, addr: &Address,) -> bool { match self { AnyPoolSrc::V2(v2,) => v2.token0.read().await.address == *addr, AnyPoolSrc::V3(v3,) => v3.token0.read().await.address == *addr, } } pub async fn in_pool(&self, addr: Address,) -> bool { match self { AnyPoolSrc::V2(v2_pool,) => v2_pool.token0.read().await.address == addr, AnyPoolSrc::V3(v3_pool,) => v3_pool.token0.read().await.address == addr, } } pub fn get_address(&self,) -> Address { match self { AnyPoolSrc::V2(v2_pool,) => v2_pool.address, AnyPoolSrc::V3(v3_pool,) => v3_pool.address, } } pub fn get_fee(&self,) -> u32 { match self { AnyPoolSrc::V2(v2_pool,) => v2_pool.fee, AnyPoolSrc::V3(v3_pool,) => v3_pool.fee, } } pub fn get_version(&self,) -> String { match self { AnyPoolSrc::V2(_,) => "v2".to_string(), AnyPoolSrc::V3(_,) => "v3".to_string(), } } pub fn get_dex(&self,) -> String { match self { AnyPoolSrc::V2(v2_pool,) => v2_pool.exchange.clone(), AnyPoolSrc::V3(v3_pool,) => v3_pool.exchange.clone(), } } pub fn get_reserves(&self,) -> Option<(U256, U256,),> { match self { AnyPoolSrc::V2(v2_pool,) => { Assuming V2Pool has an async get_reserves() -> (U256, U256) Some((v2_pool.reserves0, v2_pool.reserves1,),) }, AnyPoolSrc::V3(v3_pool,) => { Assuming V3Pool has an async get_reserves() -> (U256, U256) let Q96 = 2u128.pow(96,); let liquidity = v3_pool.liquidity; let sqrt_price_x96 = v3_pool.x96price; 2) reserve0 = liquidity * 2^96 / sqrtPriceX96 let numerator0 = liquidity.saturating_mul(U256::ONE << 96,); let r0 = numerator0.checked_div(sqrt_price_x96,).unwrap_or(U256::ZERO,); 3) reserve1 = liquidity * sqrtPriceX96 / 2^96 let r1 = liquidity.saturating_mul(sqrt_price_x96,) >> 96; Some((r0, r1,),) }, } } pub fn active_ticks(&self,) -> Option<&Vec<Tick,>,> { match self { AnyPoolSrc::V3(v3,) => Some(&v3.active_ticks,), _ => None, } } Returns the V3 pool’s tick spacing, or `None` if this is a V2 pool. pub fn tick_spacing(&self,) -> Option<i32,> { match self { AnyPoolSrc::V3(v3,) => Some(v3.tick_spacing,), _ => None, } } Returns the V3 pool’s current liquidity, or `None` if this is a V2 pool. pub fn liquidity(&self,) -> Option<&U256,> { match self { AnyPoolSrc::V3(v3,) => Some(&v3.liquidity,), _ => None, } } Returns the V3 pool’s sqrt-price (x96), or `None` if this is a V2 pool. pub fn x96_price(&self,) -> Option<&U256,> { match self { AnyPoolSrc::V3(v3,) => Some(&v3.x96price,), _ => None, } }
+            AnyPoolSrc::V2(src,) => [src.token0, src.token1,],
+            AnyPoolSrc::V3(src,) => [src.token0, src.token1,],
+            AnyPoolSrc::V4(src,) => [src.key.currency0, src.key.currency1,],
+            AnyPoolSrc::Curve(src,) => [src.coins[0], src.coins[1],],
+            AnyPoolSrc::CurveCrypto(src,) => [src.coins[0], src.coins[1],],
+            AnyPoolSrc::Balancer(src,) => [src.tokens[0], src.tokens[1],],
+            AnyPoolSrc::Algebra(src,) => [src.token0, src.token1,],
+            AnyPoolSrc::Solidly(src,) => [src.token0, src.token1,],
+            AnyPoolSrc::Lb(src,) => [src.token_x, src.token_y,],
+            AnyPoolSrc::Erc4626(src,) => [src.asset, src.address,],
+        }
+    }
 }